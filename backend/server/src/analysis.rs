@@ -0,0 +1,171 @@
+// src/analysis.rs
+use crate::models::{AnalysisResponse, ConversionSettings};
+use crate::utils::format_hex_color;
+use crate::vectorizer::{detect_background_color, luminance, otsu_threshold};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
+use std::collections::HashMap;
+
+// Image analysis: what kind of image an upload is, and the settings likely to suit it
+
+// Larger images are measured on a copy scaled down to this longest side
+const ANALYSIS_SIZE: u32 = 1024;
+
+// Share of the opaque pixels a 4-bit color bucket needs to count as one of the image's colors;
+// smaller buckets are antialiasing and noise
+const MIN_COLOR_SHARE: f64 = 0.002;
+
+// Share of pixels that must sit close to one of the two luma levels for a bilevel image,
+// and how far apart the levels must be
+const MIN_BILEVEL_SHARE: f64 = 0.95;
+const MIN_BILEVEL_CONTRAST: f64 = 64.0;
+
+// Largest difference between the channels of a pixel still counted as gray
+const MAX_GRAY_CHROMA: u8 = 32;
+
+// Tolerance used when looking for a background color, the same as the conversion default
+const BACKGROUND_TOLERANCE: u8 = 24;
+
+// Describe an image and suggest settings for it
+pub fn analyze_image(img: &DynamicImage) -> AnalysisResponse {
+    let (width, height) = img.dimensions();
+    let sample = if width.max(height) > ANALYSIS_SIZE {
+        img.resize(ANALYSIS_SIZE, ANALYSIS_SIZE, FilterType::Triangle)
+    } else {
+        img.clone()
+    };
+    let rgba = sample.to_rgba8();
+
+    let has_alpha = rgba.pixels().any(|pixel| pixel[3] < 255);
+    let estimated_colors = estimate_colors(&rgba);
+    let luma = luminance(&rgba);
+    let opaque_luma: Vec<u8> = luma
+        .iter()
+        .zip(rgba.pixels())
+        .filter(|(_, pixel)| pixel[3] >= 128)
+        .map(|(value, _)| *value)
+        .collect();
+
+    // Two luma levels only make a black and white image if there is no color to lose
+    let levels = bilevel_levels(&opaque_luma, otsu_threshold(opaque_luma.iter().copied()))
+        .filter(|_| estimated_colors <= 2 || is_grayscale(&rgba));
+    let bilevel = levels.is_some();
+    let noise = estimate_noise(&luma, rgba.width() as usize, rgba.height() as usize);
+    let background_color = detect_background_color(&rgba, BACKGROUND_TOLERANCE);
+
+    let mut settings = ConversionSettings::default();
+    match levels {
+        Some((dark, light)) => {
+            // Halfway between the levels leaves the most room on both sides
+            settings.output_mode = "bw".to_string();
+            settings.threshold = Some(((dark + light) / 2.0).round() as u8);
+        }
+        None => {
+            settings.output_mode = "color".to_string();
+            settings.color_count = Some(estimated_colors.clamp(2, 64) as u8);
+        }
+    }
+
+    // Grainier images need larger speckles filtered out
+    settings.noise_reduction = Some(match noise {
+        noise if noise < 2.0 => 2,
+        noise if noise < 5.0 => 4,
+        noise if noise < 10.0 => 6,
+        _ => 8,
+    });
+
+    // Images that are already transparent have no background left to remove
+    settings.background = Some(if background_color.is_some() && !has_alpha { "remove" } else { "keep" }.to_string());
+
+    AnalysisResponse {
+        width,
+        height,
+        has_alpha,
+        estimated_colors,
+        bilevel,
+        noise: (noise * 100.0).round() / 100.0,
+        background_color: background_color.map(format_hex_color),
+        suggested_settings: settings,
+    }
+}
+
+// Count the 4-bit color buckets that cover a meaningful share of the opaque pixels
+fn estimate_colors(rgba: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> usize {
+    let mut buckets: HashMap<[u8; 3], u64> = HashMap::new();
+    let mut opaque = 0u64;
+
+    for pixel in rgba.pixels().filter(|pixel| pixel[3] >= 128) {
+        *buckets.entry([pixel[0] >> 4, pixel[1] >> 4, pixel[2] >> 4]).or_insert(0) += 1;
+        opaque += 1;
+    }
+
+    let min_pixels = (opaque as f64 * MIN_COLOR_SHARE).max(1.0);
+    buckets.values().filter(|&&count| count as f64 >= min_pixels).count()
+}
+
+// The dark and light luma levels on either side of the threshold, if nearly every pixel sits close to one of them
+fn bilevel_levels(luma: &[u8], threshold: u8) -> Option<(f64, f64)> {
+    let (dark, light): (Vec<f64>, Vec<f64>) = luma
+        .iter()
+        .map(|&value| value as f64)
+        .partition(|&value| value <= threshold as f64);
+    if dark.is_empty() || light.is_empty() {
+        return None;
+    }
+
+    let dark_level = dark.iter().sum::<f64>() / dark.len() as f64;
+    let light_level = light.iter().sum::<f64>() / light.len() as f64;
+    let contrast = light_level - dark_level;
+    if contrast < MIN_BILEVEL_CONTRAST {
+        return None;
+    }
+
+    // Within a quarter of the contrast of either level
+    let near = luma
+        .iter()
+        .map(|&value| value as f64)
+        .filter(|value| (value - dark_level).abs() <= contrast / 4.0 || (value - light_level).abs() <= contrast / 4.0)
+        .count();
+
+    if near as f64 >= luma.len() as f64 * MIN_BILEVEL_SHARE {
+        Some((dark_level, light_level))
+    } else {
+        None
+    }
+}
+
+// Whether nearly all opaque pixels are shades of gray
+fn is_grayscale(rgba: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> bool {
+    let (mut gray, mut opaque) = (0usize, 0usize);
+    for pixel in rgba.pixels().filter(|pixel| pixel[3] >= 128) {
+        let max = pixel[0].max(pixel[1]).max(pixel[2]);
+        let min = pixel[0].min(pixel[1]).min(pixel[2]);
+        if max - min <= MAX_GRAY_CHROMA {
+            gray += 1;
+        }
+        opaque += 1;
+    }
+
+    gray as f64 >= opaque as f64 * MIN_BILEVEL_SHARE
+}
+
+// Standard deviation of the noise in luma levels, after Immerkær's fast noise variance estimation:
+// a Laplacian mask cancels out edges and flat areas, leaving mostly the noise
+fn estimate_noise(luma: &[u8], width: usize, height: usize) -> f64 {
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let at = |x: usize, y: usize| luma[y * width + x] as f64;
+    let mut sum = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let response = at(x - 1, y - 1) + at(x + 1, y - 1) + at(x - 1, y + 1) + at(x + 1, y + 1)
+                - 2.0 * (at(x, y - 1) + at(x - 1, y) + at(x + 1, y) + at(x, y + 1))
+                + 4.0 * at(x, y);
+            sum += response.abs();
+        }
+    }
+
+    (std::f64::consts::PI / 2.0).sqrt() * sum / (6.0 * (width - 2) as f64 * (height - 2) as f64)
+}
@@ -0,0 +1,293 @@
+// src/cache.rs
+use crate::config::config;
+use crate::models::{ResultCacheStats, ValidatedSettings};
+use crate::validation::settings_to_request;
+use crate::vectorizer::ConversionOutput;
+use log::info;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Finished conversions by the hash of the uploaded bytes and the normalised settings, so
+// converting the same image with the same settings again skips tracing
+
+// Bumped whenever tracing changes, so results from older code are never served
+const CACHE_VERSION: &str = "v1";
+
+// A cached conversion with its geometry, serialized the way it is stored next to an SVG
+struct CachedResult {
+    output: ConversionOutput,
+    geometry: Arc<Vec<u8>>,
+    bytes: usize,
+    created: Instant,
+    last_used: Instant,
+}
+
+struct ResultCache {
+    entries: HashMap<String, CachedResult>,
+    bytes: usize,
+    max_bytes: usize,
+    max_age: Duration,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+lazy_static::lazy_static! {
+    static ref RESULT_CACHE: Arc<Mutex<ResultCache>> = Arc::new(Mutex::new(ResultCache::new(
+        config().result_cache.max_bytes,
+        Duration::from_secs(config().result_cache.max_age_secs),
+    )));
+}
+
+// Cache key for an upload converted with the given settings. Settings are normalised through
+// their request form, so equivalent requests (a default left out or spelled out) share a key.
+pub fn cache_key(data: &[u8], settings: &ValidatedSettings) -> String {
+    // ConversionSettings only holds plain values, which always serialize
+    let settings_json = serde_json::to_vec(&settings_to_request(settings)).unwrap();
+
+    let mut hasher = Sha256::new();
+    hasher.update(CACHE_VERSION.as_bytes());
+    hasher.update([0]);
+    hasher.update((settings_json.len() as u64).to_le_bytes());
+    hasher.update(&settings_json);
+    hasher.update(data);
+    hex::encode(hasher.finalize())
+}
+
+impl ResultCache {
+    // An empty cache of up to `max_bytes` of results, each served for `max_age`; 0 bytes turns it off
+    fn new(max_bytes: usize, max_age: Duration) -> Self {
+        ResultCache {
+            entries: HashMap::new(),
+            bytes: 0,
+            max_bytes,
+            max_age,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.bytes -= entry.bytes;
+            self.evictions += 1;
+        }
+    }
+
+    fn is_expired(&self, entry: &CachedResult, now: Instant) -> bool {
+        now.saturating_duration_since(entry.created) > self.max_age
+    }
+
+    fn remove_expired(&mut self, now: Instant) {
+        let expired: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| self.is_expired(entry, now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.remove(&key);
+        }
+    }
+
+    fn lookup(&mut self, key: &str, now: Instant) -> Option<(ConversionOutput, Arc<Vec<u8>>)> {
+        if self.max_bytes == 0 {
+            return None;
+        }
+
+        let expired = self.entries.get(key).map(|entry| self.is_expired(entry, now));
+        if expired == Some(true) {
+            self.remove(key);
+        }
+
+        let hit = self.entries.get_mut(key).map(|entry| {
+            entry.last_used = now;
+            (entry.output.clone(), entry.geometry.clone())
+        });
+        match hit {
+            Some(hit) => {
+                self.hits += 1;
+                info!("Result cache hit for {} ({} hits, {} misses)", &key[..12], self.hits, self.misses);
+                Some(hit)
+            }
+            None => {
+                self.misses += 1;
+                info!("Result cache miss for {} ({} hits, {} misses)", &key[..12], self.hits, self.misses);
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, key: &str, output: &ConversionOutput, geometry: Vec<u8>, now: Instant) {
+        let bytes = output.svg_data.len() + geometry.len();
+
+        // A result bigger than the whole cache would only push everything else out
+        if bytes > self.max_bytes {
+            return;
+        }
+
+        self.remove_expired(now);
+        if self.entries.contains_key(key) {
+            // Converted twice at the same time; the first result stays
+            return;
+        }
+        while self.bytes + bytes > self.max_bytes {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => self.remove(&oldest),
+                None => break,
+            }
+        }
+
+        self.bytes += bytes;
+        self.entries.insert(
+            key.to_string(),
+            CachedResult {
+                output: output.clone(),
+                geometry: Arc::new(geometry),
+                bytes,
+                created: now,
+                last_used: now,
+            },
+        );
+    }
+
+    fn stats(&mut self, now: Instant) -> ResultCacheStats {
+        self.remove_expired(now);
+
+        ResultCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            entries: self.entries.len(),
+            bytes: self.bytes,
+            max_bytes: self.max_bytes,
+        }
+    }
+}
+
+// A cached result for the key, with the serialized geometry to store next to its SVG
+pub fn lookup(key: &str) -> Option<(ConversionOutput, Arc<Vec<u8>>)> {
+    RESULT_CACHE.lock().unwrap().lookup(key, Instant::now())
+}
+
+// Keep a finished conversion, evicting the least recently used results beyond the size limit
+pub fn insert(key: &str, output: &ConversionOutput, geometry: Vec<u8>) {
+    RESULT_CACHE.lock().unwrap().insert(key, output, geometry, Instant::now());
+}
+
+// Counters and size of the cache, for the metrics endpoint
+pub fn stats() -> ResultCacheStats {
+    RESULT_CACHE.lock().unwrap().stats(Instant::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::QualityReport;
+    use crate::validation::parse_settings;
+
+    // A result taking `bytes` bytes in the cache
+    fn output(bytes: usize) -> ConversionOutput {
+        ConversionOutput {
+            svg_data: "x".repeat(bytes),
+            palette: Vec::new(),
+            quality: QualityReport {
+                psnr: 100.0,
+                ssim: 1.0,
+                colors: Vec::new(),
+                paths: 0,
+                nodes: 0,
+                bytes,
+            },
+            settings: parse_settings("{}").unwrap(),
+            cached: false,
+        }
+    }
+
+    fn at(start: Instant, secs: u64) -> Instant {
+        start + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn least_recently_used_results_make_room() {
+        let start = Instant::now();
+        let mut cache = ResultCache::new(100, Duration::from_secs(3600));
+
+        cache.insert("first-result", &output(30), vec![0; 10], at(start, 0));
+        cache.insert("second-result", &output(40), Vec::new(), at(start, 1));
+        assert!(cache.lookup("first-result", at(start, 2)).is_some());
+
+        // The second result was used longest ago, so it goes
+        cache.insert("third-result", &output(40), Vec::new(), at(start, 3));
+        assert!(cache.lookup("second-result", at(start, 4)).is_none());
+        assert_eq!(cache.lookup("first-result", at(start, 4)).unwrap().1.len(), 10);
+        assert!(cache.lookup("third-result", at(start, 4)).is_some());
+
+        let stats = cache.stats(at(start, 5));
+        assert_eq!((stats.entries, stats.bytes, stats.evictions), (2, 80, 1));
+        assert_eq!((stats.hits, stats.misses), (3, 1));
+    }
+
+    #[test]
+    fn results_expire_after_their_age() {
+        let start = Instant::now();
+        let mut cache = ResultCache::new(100, Duration::from_secs(60));
+
+        cache.insert("some-result1", &output(10), Vec::new(), start);
+        // Using a result does not make it last longer
+        assert!(cache.lookup("some-result1", at(start, 60)).is_some());
+        assert!(cache.lookup("some-result1", at(start, 61)).is_none());
+
+        let stats = cache.stats(at(start, 61));
+        assert_eq!((stats.entries, stats.bytes, stats.evictions), (0, 0, 1));
+
+        // Expired results are cleared when room is made for another
+        cache.insert("other-result", &output(10), Vec::new(), at(start, 100));
+        cache.insert("newer-result", &output(10), Vec::new(), at(start, 170));
+        assert_eq!(cache.stats(at(start, 170)).entries, 1);
+    }
+
+    #[test]
+    fn results_bigger_than_the_cache_are_not_kept() {
+        let start = Instant::now();
+        let mut cache = ResultCache::new(100, Duration::from_secs(3600));
+
+        cache.insert("small-result", &output(60), Vec::new(), start);
+        cache.insert("large-result", &output(90), vec![0; 11], start);
+
+        assert!(cache.lookup("large-result", start).is_none());
+        assert!(cache.lookup("small-result", start).is_some());
+        assert_eq!(cache.stats(start).evictions, 0);
+
+        // A cache of no bytes is off
+        let mut off = ResultCache::new(0, Duration::from_secs(3600));
+        off.insert("empty-result", &output(0), Vec::new(), start);
+        assert!(off.lookup("empty-result", start).is_none());
+        assert_eq!(off.stats(start).misses, 0);
+    }
+
+    #[test]
+    fn equivalent_settings_share_a_key() {
+        let data = b"image bytes";
+        let key = |json: &str| cache_key(data, &parse_settings(json).unwrap());
+
+        // Defaults left out or spelled out, in any order
+        assert_eq!(key("{}"), key(r#"{"threshold": 128}"#));
+        assert_eq!(
+            key(r#"{"smoothing": 3, "threshold": 90}"#),
+            key(r#"{"threshold": 90, "smoothing": 3, "contrast": 0}"#)
+        );
+
+        assert_ne!(key("{}"), key(r#"{"threshold": 129}"#));
+        assert_ne!(key("{}"), cache_key(b"other bytes", &parse_settings("{}").unwrap()));
+        assert_eq!(key("{}").len(), 64);
+    }
+}
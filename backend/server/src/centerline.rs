@@ -0,0 +1,594 @@
+// src/centerline.rs
+use crate::geometry::{simplify_polyline, Point, Segment, Subpath, VectorImage, VectorPath};
+use crate::models::{ConversionStage, CurveMode, ValidatedSettings};
+use crate::tracer::{TraceOutput, Tracer, TRACE_PROGRESS_END, TRACE_PROGRESS_START};
+use crate::vectorizer::binarize_image;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
+use std::collections::HashSet;
+
+// Centerline tracing: thin the binarized image down to a one-pixel skeleton and follow it as strokes,
+// so a pen line becomes one path instead of the two outlines vtracer would give it
+
+// The 8 neighbours clockwise from north, in the P2..P9 order used by Zhang-Suen thinning
+const NEIGHBOURS: [(i64, i64); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+
+// Chamfer weights for straight and diagonal steps, close to 1 and √2 when divided by 3
+const CHAMFER_STRAIGHT: u32 = 3;
+const CHAMFER_DIAGONAL: u32 = 4;
+
+// Stroke widths are rounded to this step so similar strokes share a path
+const WIDTH_STEP: f64 = 0.5;
+
+// Single strokes along the middle of dark lines
+pub struct CenterlineTracer;
+
+impl Tracer for CenterlineTracer {
+    fn name(&self) -> &'static str {
+        "centerline"
+    }
+
+    fn trace(
+        &self,
+        img: &DynamicImage,
+        settings: &ValidatedSettings,
+        on_progress: &mut dyn FnMut(ConversionStage, u8),
+    ) -> Result<TraceOutput, String> {
+        on_progress(ConversionStage::PathTracing, TRACE_PROGRESS_START);
+        let binary = binarize_image(img, settings);
+        let vector_image = trace_centerlines(&binary, img, settings);
+        on_progress(ConversionStage::PathTracing, TRACE_PROGRESS_END);
+
+        Ok(TraceOutput {
+            vector_image,
+            binarized: Some(binary),
+        })
+    }
+}
+
+// Foreground pixels of a binarized image
+struct Mask {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Mask {
+    fn get(&self, x: i64, y: i64) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as usize) < self.width
+            && (y as usize) < self.height
+            && self.pixels[y as usize * self.width + x as usize]
+    }
+
+    fn neighbours(&self, index: usize) -> [bool; 8] {
+        let (x, y) = ((index % self.width) as i64, (index / self.width) as i64);
+        NEIGHBOURS.map(|(dx, dy)| self.get(x + dx, y + dy))
+    }
+
+    // Indices of the set neighbours of a pixel
+    fn neighbour_indices(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let (x, y) = ((index % self.width) as i64, (index / self.width) as i64);
+        NEIGHBOURS
+            .iter()
+            .filter(move |(dx, dy)| self.get(x + dx, y + dy))
+            .map(move |(dx, dy)| (y + dy) as usize * self.width + (x + dx) as usize)
+    }
+
+    fn degree(&self, index: usize) -> usize {
+        self.neighbours(index).iter().filter(|&&set| set).count()
+    }
+}
+
+// Trace the dark strokes of a binarized image along their medial axis.
+// `colors` is the image before binarization, used to pick the stroke color.
+pub fn trace_centerlines(binary: &DynamicImage, colors: &DynamicImage, settings: &ValidatedSettings) -> VectorImage {
+    let (width, height) = binary.dimensions();
+    let rgba = binary.to_rgba8();
+
+    let mut mask = Mask {
+        width: width as usize,
+        height: height as usize,
+        pixels: rgba
+            .pixels()
+            .map(|pixel| pixel[3] >= 128 && (pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) < 384)
+            .collect(),
+    };
+
+    // Same speckle size vtracer would filter
+    remove_specks(&mut mask, settings.noise_reduction as usize * 2);
+
+    let distances = distance_to_background(&mask);
+    thin(&mut mask);
+    let lines = follow_skeleton(&mask);
+
+    let colors = colors.to_rgba8();
+
+    let epsilon = 0.5 + settings.path_simplification as f64 * 0.2;
+
+    // Group strokes of about the same color and width into one path each
+    let mut paths: Vec<VectorPath> = Vec::new();
+    for (line, closed) in lines {
+        let stroke_width = match settings.stroke_width {
+            Some(width) => width as f64,
+            None => estimate_width(&line, &distances),
+        };
+        let color = stroke_color(&line, &colors, mask.width);
+
+        let points: Vec<Point> = line
+            .iter()
+            .map(|&index| Point::new((index % mask.width) as f64 + 0.5, (index / mask.width) as f64 + 0.5))
+            .collect();
+        let points = simplify_polyline(&points, closed, epsilon);
+        let subpath = to_subpath(&points, closed, settings);
+
+        match paths
+            .iter_mut()
+            .find(|path| path.color == color && path.stroke_width == Some(stroke_width))
+        {
+            Some(path) => path.subpaths.push(subpath),
+            None => paths.push(VectorPath {
+                color,
+                subpaths: vec![subpath],
+                stroke_width: Some(stroke_width),
+            }),
+        }
+    }
+
+    // Thick strokes first so thin detail is drawn on top
+    paths.sort_by(|a, b| b.stroke_width.partial_cmp(&a.stroke_width).unwrap_or(std::cmp::Ordering::Equal));
+
+    VectorImage {
+        width,
+        height,
+        paths,
+        pixel_grid: false,
+    }
+}
+
+// Clear 8-connected groups of fewer than `min_size` foreground pixels
+fn remove_specks(mask: &mut Mask, min_size: usize) {
+    if min_size <= 1 {
+        return;
+    }
+
+    let mut visited = vec![false; mask.pixels.len()];
+    for start in 0..mask.pixels.len() {
+        if !mask.pixels[start] || visited[start] {
+            continue;
+        }
+
+        visited[start] = true;
+        let mut component = vec![start];
+        let mut next = 0;
+        while next < component.len() {
+            let neighbours: Vec<usize> = mask.neighbour_indices(component[next]).collect();
+            for neighbour in neighbours {
+                if !visited[neighbour] {
+                    visited[neighbour] = true;
+                    component.push(neighbour);
+                }
+            }
+            next += 1;
+        }
+
+        if component.len() < min_size {
+            for index in component {
+                mask.pixels[index] = false;
+            }
+        }
+    }
+}
+
+// Approximate distance from every foreground pixel to the nearest background, in pixels.
+// A pixel touching the background is 1 away; outside the image counts as background.
+fn distance_to_background(mask: &Mask) -> Vec<f32> {
+    let (width, height) = (mask.width, mask.height);
+    let mut distances: Vec<u32> = mask
+        .pixels
+        .iter()
+        .map(|&set| if set { u32::MAX / 2 } else { 0 })
+        .collect();
+
+    let at = |distances: &[u32], x: i64, y: i64| {
+        if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+            0
+        } else {
+            distances[y as usize * width + x as usize]
+        }
+    };
+
+    // Two chamfer passes: forwards from the top left, then backwards from the bottom right
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let index = y as usize * width + x as usize;
+            if distances[index] == 0 {
+                continue;
+            }
+            let nearest = [
+                at(&distances, x - 1, y) + CHAMFER_STRAIGHT,
+                at(&distances, x, y - 1) + CHAMFER_STRAIGHT,
+                at(&distances, x - 1, y - 1) + CHAMFER_DIAGONAL,
+                at(&distances, x + 1, y - 1) + CHAMFER_DIAGONAL,
+            ];
+            distances[index] = distances[index].min(nearest.into_iter().min().unwrap_or(0));
+        }
+    }
+    for y in (0..height as i64).rev() {
+        for x in (0..width as i64).rev() {
+            let index = y as usize * width + x as usize;
+            if distances[index] == 0 {
+                continue;
+            }
+            let nearest = [
+                at(&distances, x + 1, y) + CHAMFER_STRAIGHT,
+                at(&distances, x, y + 1) + CHAMFER_STRAIGHT,
+                at(&distances, x + 1, y + 1) + CHAMFER_DIAGONAL,
+                at(&distances, x - 1, y + 1) + CHAMFER_DIAGONAL,
+            ];
+            distances[index] = distances[index].min(nearest.into_iter().min().unwrap_or(0));
+        }
+    }
+
+    distances
+        .into_iter()
+        .map(|distance| distance as f32 / CHAMFER_STRAIGHT as f32)
+        .collect()
+}
+
+// Zhang-Suen thinning down to a skeleton one pixel wide
+fn thin(mask: &mut Mask) {
+    loop {
+        let mut changed = false;
+
+        for step in 0..2 {
+            let removable: Vec<usize> = (0..mask.pixels.len())
+                .filter(|&index| mask.pixels[index])
+                .filter(|&index| {
+                    let n = mask.neighbours(index);
+                    let count = n.iter().filter(|&&set| set).count();
+                    let transitions = (0..8).filter(|&i| !n[i] && n[(i + 1) % 8]).count();
+
+                    let (north, east, south, west) = (n[0], n[2], n[4], n[6]);
+                    // First pass: P2·P4·P6 = 0 and P4·P6·P8 = 0; second pass: P2·P4·P8 = 0 and P2·P6·P8 = 0
+                    let sides = if step == 0 {
+                        !(east && south && (north || west))
+                    } else {
+                        !(north && west && (east || south))
+                    };
+
+                    (2..=6).contains(&count) && transitions == 1 && sides
+                })
+                .collect();
+
+            changed |= !removable.is_empty();
+            for index in removable {
+                mask.pixels[index] = false;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    // Zhang-Suen leaves staircase corners on diagonals; drop the ones the diagonal already connects
+    for index in 0..mask.pixels.len() {
+        if !mask.pixels[index] {
+            continue;
+        }
+
+        let n = mask.neighbours(index);
+        let (north, east, south, west) = (n[0], n[2], n[4], n[6]);
+        let (north_east, south_east, south_west, north_west) = (n[1], n[3], n[5], n[7]);
+
+        let staircase = (north && east && !south && !west && !south_west)
+            || (east && south && !north && !west && !north_west)
+            || (south && west && !north && !east && !north_east)
+            || (west && north && !south && !east && !south_east);
+        if staircase {
+            mask.pixels[index] = false;
+        }
+    }
+}
+
+// Split the skeleton into polylines between end points and junctions, plus closed loops.
+// Each polyline is a list of pixel indices and whether it is closed.
+fn follow_skeleton(mask: &Mask) -> Vec<(Vec<usize>, bool)> {
+    let mut lines = Vec::new();
+    let mut visited: HashSet<(usize, usize)> = HashSet::new();
+    let edge = |a: usize, b: usize| (a.min(b), a.max(b));
+
+    // Walk from `start` through `first` until reaching a pixel that is not part of a plain line
+    let walk = |start: usize, first: usize, visited: &mut HashSet<(usize, usize)>| {
+        let mut line = vec![start, first];
+        visited.insert(edge(start, first));
+
+        let mut current = first;
+        while mask.degree(current) == 2 {
+            let next = mask
+                .neighbour_indices(current)
+                .find(|&neighbour| !visited.contains(&edge(current, neighbour)));
+            match next {
+                Some(next) => {
+                    visited.insert(edge(current, next));
+                    line.push(next);
+                    current = next;
+                }
+                None => break,
+            }
+        }
+
+        line
+    };
+
+    let skeleton: Vec<usize> = (0..mask.pixels.len()).filter(|&index| mask.pixels[index]).collect();
+
+    // Start from end points and junctions
+    for &index in &skeleton {
+        match mask.degree(index) {
+            0 => lines.push((vec![index, index], false)),
+            2 => {}
+            _ => {
+                let neighbours: Vec<usize> = mask.neighbour_indices(index).collect();
+                for neighbour in neighbours {
+                    if !visited.contains(&edge(index, neighbour)) {
+                        lines.push((walk(index, neighbour, &mut visited), false));
+                    }
+                }
+            }
+        }
+    }
+
+    // Whatever is left is made of closed loops
+    for &index in &skeleton {
+        let neighbours: Vec<usize> = mask.neighbour_indices(index).collect();
+        for neighbour in neighbours {
+            if !visited.contains(&edge(index, neighbour)) {
+                let mut line = walk(index, neighbour, &mut visited);
+                let closed = line.len() > 2 && line.first() == line.last();
+                if closed {
+                    line.pop();
+                }
+                lines.push((line, closed));
+            }
+        }
+    }
+
+    lines
+}
+
+// Stroke width from the median distance to the background along the skeleton
+fn estimate_width(line: &[usize], distances: &[f32]) -> f64 {
+    let mut values: Vec<f32> = line.iter().map(|&index| distances[index]).collect();
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    // A skeleton pixel d away from both edges sits in a stroke about 2d - 1 pixels wide
+    let median = values[values.len() / 2] as f64;
+    let width = (2.0 * median - 1.0).max(1.0);
+    (width / WIDTH_STEP).round() * WIDTH_STEP
+}
+
+// Average color of the original image under a stroke, rounded to 4 bits per channel
+// so strokes drawn with the same ink end up in the same path
+fn stroke_color(line: &[usize], colors: &ImageBuffer<Rgba<u8>, Vec<u8>>, width: usize) -> [u8; 3] {
+    let mut sums = [0u64; 4];
+    for &index in line {
+        let pixel = colors.get_pixel((index % width) as u32, (index / width) as u32);
+        if pixel[3] >= 128 {
+            for channel in 0..3 {
+                sums[channel] += pixel[channel] as u64;
+            }
+            sums[3] += 1;
+        }
+    }
+
+    match sums[3] {
+        0 => [0, 0, 0],
+        count => [0, 1, 2].map(|channel| ((sums[channel] / count) as u8 >> 4) * 17),
+    }
+}
+
+// Join the points with straight lines, or with a smooth curve through them in spline mode.
+// The curve keeps a sharp corner wherever the line turns by more than corner_threshold.
+fn to_subpath(points: &[Point], closed: bool, settings: &ValidatedSettings) -> Subpath {
+    let start = points[0];
+    let count = points.len();
+
+    let segment_count = if closed { count } else { count - 1 };
+    let corner_threshold = (settings.corner_threshold as f64).to_radians();
+
+    let segments = (0..segment_count)
+        .map(|index| {
+            let from = points[index];
+            let to = points[(index + 1) % count];
+
+            if settings.curve_mode == CurveMode::Polygon || count < 3 {
+                return Segment::Line { to };
+            }
+
+            // Catmull-Rom tangents, falling back to the segment itself at ends and corners
+            let previous = if closed || index > 0 { Some(points[(index + count - 1) % count]) } else { None };
+            let next = if closed || index + 2 < count { Some(points[(index + 2) % count]) } else { None };
+
+            let chord = (to.x - from.x, to.y - from.y);
+            let out_tangent = match previous {
+                Some(previous) if !is_corner(previous, from, to, corner_threshold) => {
+                    ((to.x - previous.x) / 2.0, (to.y - previous.y) / 2.0)
+                }
+                _ => chord,
+            };
+            let in_tangent = match next {
+                Some(next) if !is_corner(from, to, next, corner_threshold) => ((next.x - from.x) / 2.0, (next.y - from.y) / 2.0),
+                _ => chord,
+            };
+
+            Segment::Cubic {
+                c1: Point::new(from.x + out_tangent.0 / 3.0, from.y + out_tangent.1 / 3.0),
+                c2: Point::new(to.x - in_tangent.0 / 3.0, to.y - in_tangent.1 / 3.0),
+                to,
+            }
+        })
+        .collect();
+
+    Subpath { start, segments, closed }
+}
+
+// Whether the line turns at `at` by more than `threshold` radians
+fn is_corner(before: Point, at: Point, after: Point, threshold: f64) -> bool {
+    let incoming = (at.y - before.y).atan2(at.x - before.x);
+    let outgoing = (after.y - at.y).atan2(after.x - at.x);
+    let mut turn = (outgoing - incoming).abs();
+    if turn > std::f64::consts::PI {
+        turn = 2.0 * std::f64::consts::PI - turn;
+    }
+    turn > threshold
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ConversionSettings;
+    use crate::validation::validate_settings;
+
+    fn settings(curve_mode: &str) -> ValidatedSettings {
+        validate_settings(&ConversionSettings {
+            output_mode: "centerline".to_string(),
+            noise_reduction: Some(0),
+            curve_mode: Some(curve_mode.to_string()),
+            ..ConversionSettings::default()
+        })
+        .unwrap()
+    }
+
+    // Black where `ink` holds, on white
+    fn drawing(width: u32, height: u32, ink: impl Fn(u32, u32) -> bool) -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(width, height, |x, y| {
+            if ink(x, y) {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }))
+    }
+
+    fn mask(image: &DynamicImage) -> Mask {
+        let (width, height) = image.dimensions();
+        Mask {
+            width: width as usize,
+            height: height as usize,
+            pixels: image.to_rgba8().pixels().map(|pixel| pixel[0] < 128).collect(),
+        }
+    }
+
+    // A horizontal bar 5 pixels thick
+    fn bar() -> DynamicImage {
+        drawing(40, 15, |x, y| (5..35).contains(&x) && (5..10).contains(&y))
+    }
+
+    #[test]
+    fn straight_bar_traces_as_one_open_stroke_of_its_width() {
+        let image = bar();
+        let traced = trace_centerlines(&image, &image, &settings("spline"));
+
+        assert_eq!((traced.width, traced.height), (40, 15));
+        assert_eq!(traced.paths.len(), 1);
+        let path = &traced.paths[0];
+        assert_eq!(path.color, [0, 0, 0]);
+        assert_eq!(path.stroke_width, Some(5.0));
+        assert_eq!(path.subpaths.len(), 1);
+        assert!(!path.subpaths[0].closed);
+
+        // The stroke runs along the middle row, most of the bar's length
+        let points = path.subpaths[0].flatten(0.1);
+        assert!(points.iter().all(|point| (point.y - 7.5).abs() < 0.5), "{:?}", points);
+        let (left, right) = points
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(left, right), point| (left.min(point.x), right.max(point.x)));
+        assert!(left < 10.0 && right > 30.0, "{} to {}", left, right);
+    }
+
+    #[test]
+    fn thinning_leaves_a_skeleton_one_pixel_wide() {
+        let mut mask = mask(&bar());
+        thin(&mut mask);
+
+        let skeleton: Vec<usize> = (0..mask.pixels.len()).filter(|&index| mask.pixels[index]).collect();
+        assert!(skeleton.len() > 20);
+        for &index in &skeleton {
+            assert!((1..=2).contains(&mask.degree(index)), "pixel {} has degree {}", index, mask.degree(index));
+        }
+        for x in 0..mask.width {
+            assert!((0..mask.height).filter(|&y| mask.pixels[y * mask.width + x]).count() <= 1, "column {}", x);
+        }
+    }
+
+    #[test]
+    fn ring_traces_as_one_closed_stroke() {
+        let image = drawing(40, 40, |x, y| {
+            let distance = (x as f64 + 0.5 - 20.0).hypot(y as f64 + 0.5 - 20.0);
+            (10.0..14.0).contains(&distance)
+        });
+        let traced = trace_centerlines(&image, &image, &settings("spline"));
+
+        assert_eq!(traced.paths.len(), 1);
+        assert_eq!(traced.paths[0].subpaths.len(), 1);
+        assert!(traced.paths[0].subpaths[0].closed);
+        let width = traced.paths[0].stroke_width.unwrap();
+        assert!((3.0..=5.0).contains(&width), "{}", width);
+    }
+
+    #[test]
+    fn set_stroke_width_replaces_the_estimate() {
+        let image = bar();
+        let settings = ValidatedSettings { stroke_width: Some(2.0), ..settings("spline") };
+        let traced = trace_centerlines(&image, &image, &settings);
+
+        assert_eq!(traced.paths[0].stroke_width, Some(2.0));
+    }
+
+    #[test]
+    fn curves_keep_sharp_corners() {
+        let settings = settings("spline");
+
+        // A right angle turns by more than the corner threshold, so the curve meets it along the chord
+        let corner = to_subpath(&[Point::new(0.0, 0.0), Point::new(10.0, 0.0), Point::new(10.0, 10.0)], false, &settings);
+        match &corner.segments[0] {
+            Segment::Cubic { c2, .. } => assert_eq!(c2.y, 0.0),
+            segment => panic!("{:?}", segment),
+        }
+
+        // A gentle bend is smoothed through the middle point
+        let bend = to_subpath(&[Point::new(0.0, 0.0), Point::new(10.0, 0.0), Point::new(20.0, 3.0)], false, &settings);
+        match &bend.segments[0] {
+            Segment::Cubic { c2, .. } => assert!(c2.y < 0.0, "{:?}", c2),
+            segment => panic!("{:?}", segment),
+        }
+    }
+
+    #[test]
+    fn polygon_mode_joins_points_with_lines() {
+        let points = [Point::new(0.0, 0.0), Point::new(10.0, 0.0), Point::new(20.0, 3.0)];
+        let subpath = to_subpath(&points, true, &settings("polygon"));
+
+        assert!(subpath.closed);
+        assert_eq!(
+            subpath.segments,
+            vec![
+                Segment::Line { to: points[1] },
+                Segment::Line { to: points[2] },
+                Segment::Line { to: points[0] },
+            ]
+        );
+    }
+
+    #[test]
+    fn specks_below_the_size_are_removed() {
+        // A 2-pixel speck and a 12-pixel line
+        let mut mask = mask(&drawing(20, 5, |x, y| (y == 1 && x < 2) || (y == 3 && (4..16).contains(&x))));
+        remove_specks(&mut mask, 4);
+
+        assert_eq!(mask.pixels.iter().filter(|&&set| set).count(), 12);
+        assert!(!mask.pixels[20] && !mask.pixels[21]);
+    }
+}
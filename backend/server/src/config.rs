@@ -0,0 +1,577 @@
+// src/config.rs
+use crate::export::ExportFormat;
+use crate::models::ConversionSettings;
+use crate::utils::MIN_RETENTION_SECS;
+use crate::validation::validate_settings;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+
+// Server configuration, read from a TOML file and then overridden by environment variables.
+// Every section and key is optional; anything left out keeps the default below.
+
+// Read when CONFIG_FILE is not set, if it exists
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+// Global configuration, loaded on first use; main checks it before anything else runs
+lazy_static::lazy_static! {
+    static ref CONFIG: Result<Config, ConfigError> = Config::load();
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    Read(String, String),
+
+    #[error("Invalid config file {0}: {1}")]
+    Parse(String, String),
+
+    #[error("Invalid {0}=\"{1}\": {2}")]
+    Env(String, String, String),
+
+    #[error("Invalid configuration:\n  {}", .0.join("\n  "))]
+    Invalid(Vec<String>),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub metadata: MetadataConfig,
+    pub retention: RetentionConfig,
+    pub limits: LimitsConfig,
+    pub conversion: ConversionConfig,
+    pub result_cache: ResultCacheConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    // Address and port to listen on
+    pub host: String,
+    pub port: u16,
+    // Origins allowed to call the API from a browser, or "*" for any
+    pub cors_origins: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 8080,
+            cors_origins: vec!["*".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    Local,
+    S3,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "local" => Ok(StorageBackend::Local),
+            "s3" => Ok(StorageBackend::S3),
+            _ => Err("expected \"local\" or \"s3\"".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    // Where uploads and outputs are kept
+    pub backend: StorageBackend,
+    // Directory holding the upload and output directories with local storage
+    pub root: String,
+    // Key prefixes of uploads and outputs; with local storage, directories under the root
+    pub upload_dir: String,
+    pub output_dir: String,
+    pub s3: S3Config,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::Local,
+            root: ".".to_string(),
+            upload_dir: "uploads".to_string(),
+            output_dir: "output".to_string(),
+            s3: S3Config::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3Config {
+    // e.g. "http://localhost:9000" for MinIO
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        Self {
+            endpoint: String::new(),
+            bucket: String::new(),
+            region: "us-east-1".to_string(),
+            access_key_id: String::new(),
+            secret_access_key: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataBackend {
+    Sled,
+    Memory,
+}
+
+impl FromStr for MetadataBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "sled" => Ok(MetadataBackend::Sled),
+            "memory" => Ok(MetadataBackend::Memory),
+            _ => Err("expected \"sled\" or \"memory\"".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetadataConfig {
+    // "sled" keeps file metadata on disk, "memory" loses it on restart
+    pub store: MetadataBackend,
+    // Directory of the on-disk store
+    pub path: String,
+}
+
+impl Default for MetadataConfig {
+    fn default() -> Self {
+        Self {
+            store: MetadataBackend::Sled,
+            path: "data/metadata".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    // Seconds files are kept after their last conversion when a request doesn't ask otherwise
+    pub default_secs: u64,
+    // Longest retention a request can ask for
+    pub max_secs: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            default_secs: 3600,
+            max_secs: 86400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    // Largest uploaded image, in bytes
+    pub max_upload_bytes: usize,
+    // Most images accepted by one batch conversion
+    pub max_batch_files: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_upload_bytes: 50 * 1024 * 1024,
+            max_batch_files: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConversionConfig {
+    // Conversions running at the same time; 0 runs one per CPU
+    pub workers: usize,
+    // Seconds a conversion may run before it is reported as failed; 0 for no limit
+    pub timeout_secs: u64,
+    // Formats /api/download and /api/download-batch can write
+    pub formats: Vec<String>,
+    // Settings used for every field a request leaves out
+    pub defaults: ConversionSettings,
+}
+
+impl Default for ConversionConfig {
+    fn default() -> Self {
+        Self {
+            workers: 0,
+            timeout_secs: 300,
+            formats: ExportFormat::ALL.iter().map(|format| format.extension().to_string()).collect(),
+            defaults: ConversionSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResultCacheConfig {
+    // Size of the cached SVGs and geometry, in bytes; 0 turns the cache off
+    pub max_bytes: usize,
+    // Seconds a cached result is served
+    pub max_age_secs: u64,
+}
+
+impl Default for ResultCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 * 1024 * 1024,
+            max_age_secs: 3600,
+        }
+    }
+}
+
+// The configuration; only call once main has checked it loaded
+pub fn config() -> &'static Config {
+    CONFIG.as_ref().expect("Configuration not loaded")
+}
+
+// Load and check the configuration, for main to report problems before starting
+pub fn load_config() -> Result<&'static Config, &'static ConfigError> {
+    CONFIG.as_ref()
+}
+
+impl Config {
+    // Read the config file, apply the environment on top and check the result
+    fn load() -> Result<Config, ConfigError> {
+        // Tests leave the machine's config file, environment and data directories alone
+        if cfg!(test) {
+            return Ok(Config::for_tests());
+        }
+
+        let path = match env::var("CONFIG_FILE") {
+            Ok(path) => Some(path),
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => Some(DEFAULT_CONFIG_FILE.to_string()),
+            Err(_) => None,
+        };
+        let text = match &path {
+            Some(path) => {
+                info!("Reading configuration from {}", path);
+                Some(fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_string(), e.to_string()))?)
+            }
+            None => None,
+        };
+
+        Config::build(path.as_deref().zip(text.as_deref()), &|name| env::var(name).ok())
+    }
+
+    // The defaults, with metadata in memory and files under a scratch directory for this process
+    fn for_tests() -> Config {
+        let mut config = Config::default();
+        config.metadata.store = MetadataBackend::Memory;
+        config.storage.root = env::temp_dir()
+            .join(format!("vectorise-tests-{}", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        config
+    }
+
+    // The configuration from a config file's path and TOML, if there is one, with the variables
+    // `env` looks up applied on top; checked before it is returned
+    fn build(file: Option<(&str, &str)>, env: &dyn Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+        let mut config = match file {
+            Some((path, text)) => Config::parse(path, text)?,
+            None => Config::default(),
+        };
+
+        config.apply_env(env)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn parse(path: &str, text: &str) -> Result<Config, ConfigError> {
+        toml::from_str(text).map_err(|e| ConfigError::Parse(path.to_string(), e.to_string()))
+    }
+
+    // Environment variables win over the file
+    fn apply_env(&mut self, env: &dyn Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        env_override(env, "HOST", &mut self.server.host)?;
+        env_override(env, "PORT", &mut self.server.port)?;
+        if let Some(origins) = env("FRONTEND_URL") {
+            self.server.cors_origins = split_list(&origins);
+        }
+
+        env_override(env, "STORAGE_BACKEND", &mut self.storage.backend)?;
+        env_override(env, "STORAGE_ROOT", &mut self.storage.root)?;
+        env_override(env, "UPLOAD_DIR", &mut self.storage.upload_dir)?;
+        env_override(env, "OUTPUT_DIR", &mut self.storage.output_dir)?;
+        env_override(env, "S3_ENDPOINT", &mut self.storage.s3.endpoint)?;
+        env_override(env, "S3_BUCKET", &mut self.storage.s3.bucket)?;
+        env_override(env, "S3_REGION", &mut self.storage.s3.region)?;
+        // The usual AWS variables work too, the S3 ones taking precedence
+        for prefix in ["AWS", "S3"] {
+            env_override(env, &format!("{}_ACCESS_KEY_ID", prefix), &mut self.storage.s3.access_key_id)?;
+            env_override(env, &format!("{}_SECRET_ACCESS_KEY", prefix), &mut self.storage.s3.secret_access_key)?;
+        }
+
+        env_override(env, "METADATA_STORE", &mut self.metadata.store)?;
+        env_override(env, "METADATA_PATH", &mut self.metadata.path)?;
+
+        env_override(env, "FILE_RETENTION_SECS", &mut self.retention.default_secs)?;
+        env_override(env, "MAX_FILE_RETENTION_SECS", &mut self.retention.max_secs)?;
+
+        env_override(env, "MAX_UPLOAD_BYTES", &mut self.limits.max_upload_bytes)?;
+        env_override(env, "MAX_BATCH_FILES", &mut self.limits.max_batch_files)?;
+
+        env_override(env, "CONVERSION_WORKERS", &mut self.conversion.workers)?;
+        env_override(env, "CONVERSION_TIMEOUT_SECS", &mut self.conversion.timeout_secs)?;
+        if let Some(formats) = env("EXPORT_FORMATS") {
+            self.conversion.formats = split_list(&formats);
+        }
+
+        env_override(env, "RESULT_CACHE_MAX_BYTES", &mut self.result_cache.max_bytes)?;
+        env_override(env, "RESULT_CACHE_MAX_AGE_SECS", &mut self.result_cache.max_age_secs)?;
+
+        Ok(())
+    }
+
+    // Check every value and collect all problems instead of stopping at the first
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if self.server.host.trim().is_empty() {
+            problems.push("server.host must not be empty".to_string());
+        }
+        if self.server.port == 0 {
+            problems.push("server.port must be between 1 and 65535".to_string());
+        }
+        if self.server.cors_origins.is_empty() {
+            problems.push("server.cors_origins needs at least one origin, or \"*\"".to_string());
+        }
+        for origin in &self.server.cors_origins {
+            if origin != "*" && !origin.starts_with("http://") && !origin.starts_with("https://") {
+                problems.push(format!("server.cors_origins: \"{}\" is not an http(s) origin or \"*\"", origin));
+            }
+        }
+
+        for (name, dir) in [("upload_dir", &self.storage.upload_dir), ("output_dir", &self.storage.output_dir)] {
+            if dir.is_empty() || dir.starts_with('/') || dir.ends_with('/') {
+                problems.push(format!("storage.{} must be a relative path without a trailing slash, got \"{}\"", name, dir));
+            }
+        }
+        if self.storage.upload_dir == self.storage.output_dir {
+            problems.push("storage.upload_dir and storage.output_dir must differ".to_string());
+        }
+        if self.storage.backend == StorageBackend::S3 {
+            let s3 = &self.storage.s3;
+            if s3.endpoint.is_empty() || s3.bucket.is_empty() {
+                problems.push("storage.s3.endpoint and storage.s3.bucket are needed for S3 storage".to_string());
+            }
+            if s3.access_key_id.is_empty() || s3.secret_access_key.is_empty() {
+                problems.push("storage.s3.access_key_id and storage.s3.secret_access_key are needed for S3 storage".to_string());
+            }
+        }
+
+        if self.metadata.store == MetadataBackend::Sled && self.metadata.path.is_empty() {
+            problems.push("metadata.path must not be empty".to_string());
+        }
+
+        if self.retention.default_secs < MIN_RETENTION_SECS {
+            problems.push(format!("retention.default_secs must be at least {}", MIN_RETENTION_SECS));
+        }
+        if self.retention.max_secs < self.retention.default_secs {
+            problems.push(format!(
+                "retention.max_secs ({}) must be at least retention.default_secs ({})",
+                self.retention.max_secs, self.retention.default_secs
+            ));
+        }
+
+        if self.limits.max_upload_bytes == 0 {
+            problems.push("limits.max_upload_bytes must be above 0".to_string());
+        }
+        if self.limits.max_batch_files == 0 {
+            problems.push("limits.max_batch_files must be at least 1".to_string());
+        }
+
+        for format in &self.conversion.formats {
+            if ExportFormat::parse(format).is_none() {
+                let names: Vec<&str> = ExportFormat::ALL.iter().map(|format| format.extension()).collect();
+                problems.push(format!(
+                    "conversion.formats: unknown format \"{}\", expected one of {}",
+                    format,
+                    names.join(", ")
+                ));
+            }
+        }
+        if let Err(errors) = validate_settings(&self.conversion.defaults) {
+            problems.extend(
+                errors
+                    .into_iter()
+                    .map(|error| format!("conversion.defaults.{}: {}", error.field, error.message)),
+            );
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    // The configuration as TOML with secrets hidden, for the startup log
+    pub fn describe(&self) -> String {
+        let mut shown = self.clone();
+        if !shown.storage.s3.secret_access_key.is_empty() {
+            shown.storage.s3.secret_access_key = "********".to_string();
+        }
+
+        toml::to_string(&shown).unwrap_or_else(|e| format!("<unprintable: {}>", e))
+    }
+
+    // Whether downloads may be written in this format
+    pub fn format_enabled(&self, format: ExportFormat) -> bool {
+        self.conversion.formats.iter().any(|name| ExportFormat::parse(name) == Some(format))
+    }
+}
+
+// Replace a value with the environment variable of that name when it is set
+fn env_override<T>(env: &dyn Fn(&str) -> Option<String>, name: &str, value: &mut T) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(text) = env(name) {
+        *value = text
+            .trim()
+            .parse()
+            .map_err(|e: T::Err| ConfigError::Env(name.to_string(), text.clone(), e.to_string()))?;
+    }
+    Ok(())
+}
+
+// Comma-separated values of a list variable
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // The configuration from a config.toml and a fixed environment, shown as TOML or as its error
+    fn build(toml: &str, vars: &[(&str, &str)]) -> String {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        match Config::build(Some(("config.toml", toml)), &|name| vars.get(name).map(|value| value.to_string())) {
+            Ok(config) => config.describe(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn defaults_need_no_file_or_environment() {
+        let config = Config::build(None, &|_| None).unwrap();
+
+        assert_eq!(config.server.port, 8080);
+        assert_eq!(config.metadata.store, MetadataBackend::Sled);
+        assert!(ExportFormat::ALL.iter().all(|&format| config.format_enabled(format)));
+    }
+
+    #[test]
+    fn toml_sets_the_keys_it_names() {
+        let cases = [
+            ("", "port = 8080"),
+            ("[server]\nport = 9000\n", "port = 9000"),
+            ("[limits]\nmax_batch_files = 5\n", "max_batch_files = 5"),
+            ("[conversion.defaults]\nthreshold = 100\n", "threshold = 100"),
+            ("[metadata]\nstore = \"memory\"\n", "store = \"memory\""),
+            // Unknown keys, wrong types and unknown backends are rejected with the file named
+            ("[server]\nprot = 9000\n", "Invalid config file config.toml"),
+            ("[server]\nport = \"high\"\n", "Invalid config file config.toml"),
+            ("[metadata]\nstore = \"redis\"\n", "Invalid config file config.toml"),
+            ("[storage]\nbackend = \"ftp\"\n", "Invalid config file config.toml"),
+        ];
+
+        for (toml, expected) in cases {
+            let outcome = build(toml, &[]);
+            assert!(outcome.contains(expected), "{:?} gave:\n{}", toml, outcome);
+        }
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        // TOML, environment variables and a line of the resulting configuration or error
+        type Case<'a> = (&'a str, &'a [(&'a str, &'a str)], &'a str);
+        let cases: &[Case] = &[
+            ("[server]\nport = 9000\n", &[("PORT", "7000")], "port = 7000"),
+            (
+                "",
+                &[("FRONTEND_URL", "http://a.example, https://b.example,")],
+                r#"cors_origins = ["http://a.example", "https://b.example"]"#,
+            ),
+            ("", &[("EXPORT_FORMATS", "svg,dxf")], r#"formats = ["svg", "dxf"]"#),
+            ("[conversion]\nformats = [\"pdf\"]\n", &[("EXPORT_FORMATS", " svg ")], r#"formats = ["svg"]"#),
+            ("", &[("S3_ACCESS_KEY_ID", "s3-key"), ("AWS_ACCESS_KEY_ID", "aws-key")], "access_key_id = \"s3-key\""),
+            ("", &[("PORT", "high")], "Invalid PORT=\"high\""),
+            ("", &[("STORAGE_BACKEND", "ftp")], "Invalid STORAGE_BACKEND=\"ftp\": expected \"local\" or \"s3\""),
+            ("", &[("METADATA_STORE", "redis")], "Invalid METADATA_STORE=\"redis\": expected \"sled\" or \"memory\""),
+        ];
+
+        for &(toml, vars, expected) in cases {
+            let outcome = build(toml, vars);
+            assert!(outcome.contains(expected), "{:?} with {:?} gave:\n{}", toml, vars, outcome);
+        }
+    }
+
+    #[test]
+    fn invalid_values_are_all_reported() {
+        // TOML, environment variables and the errors that must all be reported
+        type Case<'a> = (&'a str, &'a [(&'a str, &'a str)], &'a [&'a str]);
+        let cases: &[Case] = &[
+            ("", &[("EXPORT_FORMATS", "svg,png")], &["conversion.formats: unknown format \"png\""]),
+            ("[conversion]\nformats = [\"bmp\"]\n", &[], &["unknown format \"bmp\", expected one of svg, pdf"]),
+            ("", &[("STORAGE_BACKEND", "s3")], &["storage.s3.endpoint and storage.s3.bucket", "storage.s3.access_key_id"]),
+            ("", &[("FRONTEND_URL", "example.com")], &["\"example.com\" is not an http(s) origin"]),
+            ("[retention]\ndefault_secs = 30\n", &[], &["retention.default_secs must be at least 60"]),
+            (
+                "[server]\nport = 0\n[limits]\nmax_batch_files = 0\n",
+                &[],
+                &["server.port must be between 1 and 65535", "limits.max_batch_files must be at least 1"],
+            ),
+            ("[conversion.defaults]\nsmoothing = 20\n", &[], &["conversion.defaults.smoothing"]),
+        ];
+
+        for &(toml, vars, expected) in cases {
+            let outcome = build(toml, vars);
+            assert!(outcome.starts_with("Invalid configuration"), "{:?} with {:?} gave:\n{}", toml, vars, outcome);
+            for problem in expected {
+                assert!(outcome.contains(problem), "{:?} with {:?} gave:\n{}", toml, vars, outcome);
+            }
+        }
+    }
+}
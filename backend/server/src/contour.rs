@@ -0,0 +1,152 @@
+// src/contour.rs
+
+// Outlines of pixel regions along the pixel edges, shared by the Potrace-style and pixel-exact tracers
+
+// Edge directions on the pixel corner grid, in clockwise order with y pointing down
+const EAST: u8 = 0;
+const SOUTH: u8 = 1;
+const WEST: u8 = 2;
+const NORTH: u8 = 3;
+const STEPS: [(i64, i64); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+// Trace the boundaries of every region inside the `width` x `height` area at (`x0`, `y0`).
+// Each outline is a closed list of corner points on the pixel grid, without repeating the start.
+// Outer boundaries run clockwise on screen and holes counterclockwise, so the non-zero fill rule
+// leaves the holes open. Regions touching only at a corner are kept apart.
+pub fn trace_contours<F: Fn(i64, i64) -> bool>(
+    x0: i64,
+    y0: i64,
+    width: usize,
+    height: usize,
+    inside: F,
+) -> Vec<Vec<(i64, i64)>> {
+    let inside_at = |x: i64, y: i64| {
+        x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height && inside(x0 + x, y0 + y)
+    };
+
+    // Outgoing boundary edges of every corner, one bit per direction, with the region on the right
+    let stride = width + 1;
+    let mut edges = vec![0u8; stride * (height + 1)];
+    for y in 0..height {
+        for x in 0..width {
+            let (xi, yi) = (x as i64, y as i64);
+            if !inside_at(xi, yi) {
+                continue;
+            }
+            if !inside_at(xi, yi - 1) {
+                edges[y * stride + x] |= 1 << EAST;
+            }
+            if !inside_at(xi + 1, yi) {
+                edges[y * stride + x + 1] |= 1 << SOUTH;
+            }
+            if !inside_at(xi, yi + 1) {
+                edges[(y + 1) * stride + x + 1] |= 1 << WEST;
+            }
+            if !inside_at(xi - 1, yi) {
+                edges[(y + 1) * stride + x] |= 1 << NORTH;
+            }
+        }
+    }
+
+    let mut used = vec![0u8; edges.len()];
+    let mut contours = Vec::new();
+
+    for start in 0..edges.len() {
+        while edges[start] & !used[start] != 0 {
+            let start_direction = (edges[start] & !used[start]).trailing_zeros() as u8;
+
+            // Follow the boundary, keeping every corner with the direction leaving it
+            let mut steps: Vec<(usize, u8)> = Vec::new();
+            let (mut corner, mut direction) = (start, start_direction);
+            loop {
+                used[corner] |= 1 << direction;
+                steps.push((corner, direction));
+
+                let (dx, dy) = STEPS[direction as usize];
+                let x = (corner % stride) as i64 + dx;
+                let y = (corner / stride) as i64 + dy;
+                corner = y as usize * stride + x as usize;
+
+                // Turn right first so regions meeting at a corner stay separate
+                direction = [(direction + 1) % 4, direction, (direction + 3) % 4]
+                    .into_iter()
+                    .find(|&next| edges[corner] & (1 << next) != 0)
+                    .unwrap_or(direction);
+
+                if corner == start && direction == start_direction {
+                    break;
+                }
+            }
+
+            // Only corners where the boundary changes direction are needed
+            let count = steps.len();
+            let contour: Vec<(i64, i64)> = (0..count)
+                .filter(|&index| steps[(index + count - 1) % count].1 != steps[index].1)
+                .map(|index| {
+                    let corner = steps[index].0;
+                    (x0 + (corner % stride) as i64, y0 + (corner / stride) as i64)
+                })
+                .collect();
+
+            contours.push(contour);
+        }
+    }
+
+    contours
+}
+
+// Signed area of a closed outline; positive for the clockwise outer boundaries
+pub fn contour_area(contour: &[(i64, i64)]) -> i64 {
+    let count = contour.len();
+    let twice_area: i64 = (0..count)
+        .map(|index| {
+            let (x1, y1) = contour[index];
+            let (x2, y2) = contour[(index + 1) % count];
+            x1 * y2 - x2 * y1
+        })
+        .sum();
+    twice_area / 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Regions drawn as rows of text, `#` inside
+    fn trace(rows: &[&str]) -> Vec<Vec<(i64, i64)>> {
+        let inside = |x: i64, y: i64| rows[y as usize].as_bytes()[x as usize] == b'#';
+        trace_contours(0, 0, rows[0].len(), rows.len(), inside)
+    }
+
+    #[test]
+    fn square_is_outlined_clockwise_by_its_corners() {
+        let contours = trace(&["....", ".##.", ".##.", "...."]);
+
+        assert_eq!(contours, vec![vec![(1, 1), (3, 1), (3, 3), (1, 3)]]);
+        assert_eq!(contour_area(&contours[0]), 4);
+    }
+
+    #[test]
+    fn holes_run_counterclockwise() {
+        let contours = trace(&["###", "#.#", "###"]);
+
+        assert_eq!(contours.len(), 2);
+        let areas: Vec<i64> = contours.iter().map(|contour| contour_area(contour)).collect();
+        assert!(areas.contains(&9) && areas.contains(&-1), "{:?}", areas);
+    }
+
+    #[test]
+    fn regions_touching_at_a_corner_stay_apart() {
+        let contours = trace(&["#.", ".#"]);
+
+        assert_eq!(contours.len(), 2);
+        assert!(contours.iter().all(|contour| contour.len() == 4 && contour_area(contour) == 1));
+    }
+
+    #[test]
+    fn offset_areas_keep_image_coordinates() {
+        let contours = trace_contours(10, 20, 2, 1, |_, _| true);
+
+        assert_eq!(contours, vec![vec![(10, 20), (12, 20), (12, 21), (10, 21)]]);
+    }
+}
@@ -0,0 +1,603 @@
+// src/export/dxf.rs
+use super::{format_number, CurveOutput, DxfVersion, ExportOptions, Units};
+use crate::geometry::{Point, Segment, Subpath, VectorImage};
+use std::fmt::Write;
+
+// AutoCAD Color Index entries used to approximate layer colors for readers without true color
+const ACI_COLORS: &[(i32, [u8; 3])] = &[
+    (1, [255, 0, 0]),
+    (2, [255, 255, 0]),
+    (3, [0, 255, 0]),
+    (4, [0, 255, 255]),
+    (5, [0, 0, 255]),
+    (6, [255, 0, 255]),
+    // 7 is drawn black or white depending on the background
+    (7, [0, 0, 0]),
+    (7, [255, 255, 255]),
+    (8, [128, 128, 128]),
+    (9, [192, 192, 192]),
+];
+
+// Group code / value pairs with running handles for R2000
+struct DxfWriter {
+    version: DxfVersion,
+    out: String,
+    next_handle: u32,
+    // Block record that owns the drawn entities
+    model_space: String,
+}
+
+impl DxfWriter {
+    fn new(version: DxfVersion) -> Self {
+        Self {
+            version,
+            out: String::new(),
+            // Handle 0 stands for "no owner"
+            next_handle: 1,
+            model_space: String::new(),
+        }
+    }
+
+    fn pair(&mut self, code: i32, value: impl std::fmt::Display) {
+        let _ = write!(self.out, "{:>3}\n{}\n", code, value);
+    }
+
+    fn number(&mut self, code: i32, value: f64) {
+        self.pair(code, format_number(value));
+    }
+
+    fn point(&mut self, x: f64, y: f64) {
+        self.number(10, x);
+        self.number(20, y);
+        self.number(30, 0.0);
+    }
+
+    fn handle(&mut self) -> String {
+        let handle = format!("{:X}", self.next_handle);
+        self.next_handle += 1;
+        handle
+    }
+
+    // Start an object, table record or entity; R2000 also needs a handle, its owner's handle
+    // and subclass markers. Returns the handle, empty for R12.
+    fn start(&mut self, kind: &str, owner: &str, subclasses: &[&str]) -> String {
+        self.pair(0, kind);
+        if self.version == DxfVersion::R12 {
+            return String::new();
+        }
+
+        let handle = self.handle();
+        self.pair(5, &handle);
+        self.pair(330, owner);
+        for subclass in subclasses {
+            self.pair(100, subclass);
+        }
+        handle
+    }
+
+    // Start a drawn entity in model space
+    fn start_entity(&mut self, kind: &str) {
+        let owner = self.model_space.clone();
+        self.start(kind, &owner, &["AcDbEntity"]);
+    }
+
+    // Start a symbol table, which the drawing itself owns
+    fn start_table(&mut self, name: &str, records: usize) -> String {
+        self.pair(0, "TABLE");
+        self.pair(2, name);
+        let mut handle = String::new();
+        if self.version == DxfVersion::R2000 {
+            handle = self.handle();
+            self.pair(5, &handle);
+            self.pair(330, 0);
+            self.pair(100, "AcDbSymbolTable");
+        }
+        self.pair(70, records);
+        handle
+    }
+}
+
+// Write the traced paths as DXF, one layer per fill color
+pub fn write_dxf(image: &VectorImage, options: &ExportOptions) -> Vec<u8> {
+    let scale = options.units.per_pixel(options.dpi);
+    let height = image.height as f64;
+    let to_units = |point: Point| (point.x * scale, (height - point.y) * scale);
+
+    // One layer per distinct color, in the order they are first drawn
+    let mut colors: Vec<[u8; 3]> = Vec::new();
+    for path in &image.paths {
+        if !colors.contains(&path.color) {
+            colors.push(path.color);
+        }
+    }
+
+    // Everything after the header goes first so the final handle count is known when the header is written
+    let mut body = DxfWriter::new(options.dxf_version);
+    if options.dxf_version == DxfVersion::R2000 {
+        body.pair(0, "SECTION");
+        body.pair(2, "CLASSES");
+        body.pair(0, "ENDSEC");
+    }
+    let block_records = write_tables(&mut body, &colors);
+    if let Some((model_space, paper_space)) = block_records {
+        write_blocks(&mut body, &model_space, &paper_space);
+        body.model_space = model_space;
+    }
+
+    body.pair(0, "SECTION");
+    body.pair(2, "ENTITIES");
+    for path in &image.paths {
+        let layer = layer_name(path.color);
+        for subpath in &path.subpaths {
+            let as_spline = options.dxf_version == DxfVersion::R2000
+                && options.curves == CurveOutput::Spline
+                && subpath.has_curves();
+
+            if as_spline {
+                write_spline(&mut body, &layer, subpath, &to_units);
+            } else {
+                let points: Vec<(f64, f64)> = subpath
+                    .flatten(options.tolerance_px())
+                    .into_iter()
+                    .map(&to_units)
+                    .collect();
+                write_polyline(&mut body, &layer, &points, subpath.closed);
+            }
+        }
+    }
+    body.pair(0, "ENDSEC");
+
+    if options.dxf_version == DxfVersion::R2000 {
+        write_objects(&mut body);
+    }
+
+    // The header's handle seed has to be above every handle used
+    let mut dxf = DxfWriter::new(options.dxf_version);
+    dxf.next_handle = body.next_handle;
+    write_header(&mut dxf, image, options, scale);
+    dxf.out.push_str(&body.out);
+
+    dxf.pair(0, "EOF");
+    dxf.out.into_bytes()
+}
+fn write_header(dxf: &mut DxfWriter, image: &VectorImage, options: &ExportOptions, scale: f64) {
+    dxf.pair(0, "SECTION");
+    dxf.pair(2, "HEADER");
+
+    dxf.pair(9, "$ACADVER");
+    dxf.pair(
+        1,
+        match options.dxf_version {
+            DxfVersion::R12 => "AC1009",
+            DxfVersion::R2000 => "AC1015",
+        },
+    );
+
+    if options.dxf_version == DxfVersion::R2000 {
+        let handle_seed = format!("{:X}", dxf.next_handle);
+        dxf.pair(9, "$HANDSEED");
+        dxf.pair(5, handle_seed);
+
+        // Drawing units: 1 = inches, 4 = millimetres
+        dxf.pair(9, "$INSUNITS");
+        dxf.pair(
+            70,
+            match options.units {
+                Units::Inch => 1,
+                Units::Mm => 4,
+            },
+        );
+        dxf.pair(9, "$MEASUREMENT");
+        dxf.pair(
+            70,
+            match options.units {
+                Units::Inch => 0,
+                Units::Mm => 1,
+            },
+        );
+    }
+
+    dxf.pair(9, "$EXTMIN");
+    dxf.point(0.0, 0.0);
+    dxf.pair(9, "$EXTMAX");
+    dxf.point(image.width as f64 * scale, image.height as f64 * scale);
+
+    dxf.pair(0, "ENDSEC");
+}
+
+// Returns the handles of the *Model_Space and *Paper_Space block records for R2000
+fn write_tables(dxf: &mut DxfWriter, colors: &[[u8; 3]]) -> Option<(String, String)> {
+    let r2000 = dxf.version == DxfVersion::R2000;
+    dxf.pair(0, "SECTION");
+    dxf.pair(2, "TABLES");
+
+    // R2000 readers expect every table, even when it has no records
+    if r2000 {
+        for name in ["VPORT", "VIEW", "UCS"] {
+            dxf.start_table(name, 0);
+            dxf.pair(0, "ENDTAB");
+        }
+    }
+
+    // Line types: only CONTINUOUS is drawn, R2000 also wants ByBlock and ByLayer
+    let line_types: &[(&str, &str)] = if r2000 {
+        &[("ByBlock", ""), ("ByLayer", ""), ("CONTINUOUS", "Solid line")]
+    } else {
+        &[("CONTINUOUS", "Solid line")]
+    };
+    let table = dxf.start_table("LTYPE", line_types.len());
+    for (name, description) in line_types {
+        dxf.start("LTYPE", &table, &["AcDbSymbolTableRecord", "AcDbLinetypeTableRecord"]);
+        dxf.pair(2, name);
+        dxf.pair(70, 0);
+        dxf.pair(3, description);
+        dxf.pair(72, 65);
+        dxf.pair(73, 0);
+        dxf.number(40, 0.0);
+    }
+    dxf.pair(0, "ENDTAB");
+
+    // Layer 0 always exists, then one layer per color
+    let table = dxf.start_table("LAYER", colors.len() + 1);
+    write_layer(dxf, &table, "0", None);
+    for color in colors {
+        write_layer(dxf, &table, &layer_name(*color), Some(*color));
+    }
+    dxf.pair(0, "ENDTAB");
+
+    if !r2000 {
+        dxf.pair(0, "ENDSEC");
+        return None;
+    }
+
+    let table = dxf.start_table("STYLE", 1);
+    dxf.start("STYLE", &table, &["AcDbSymbolTableRecord", "AcDbTextStyleTableRecord"]);
+    dxf.pair(2, "Standard");
+    dxf.pair(70, 0);
+    dxf.number(40, 0.0);
+    dxf.number(41, 1.0);
+    dxf.number(50, 0.0);
+    dxf.pair(71, 0);
+    dxf.number(42, 2.5);
+    dxf.pair(3, "txt");
+    dxf.pair(4, "");
+    dxf.pair(0, "ENDTAB");
+
+    let table = dxf.start_table("APPID", 1);
+    dxf.start("APPID", &table, &["AcDbSymbolTableRecord", "AcDbRegAppTableRecord"]);
+    dxf.pair(2, "ACAD");
+    dxf.pair(70, 0);
+    dxf.pair(0, "ENDTAB");
+
+    dxf.start_table("DIMSTYLE", 0);
+    dxf.pair(100, "AcDbDimStyleTable");
+    dxf.pair(0, "ENDTAB");
+
+    // Every block, including the two layouts, needs a record here
+    let table = dxf.start_table("BLOCK_RECORD", 2);
+    let mut block_record = |name: &str| {
+        let handle = dxf.start("BLOCK_RECORD", &table, &["AcDbSymbolTableRecord", "AcDbBlockTableRecord"]);
+        dxf.pair(2, name);
+        handle
+    };
+    let model_space = block_record("*Model_Space");
+    let paper_space = block_record("*Paper_Space");
+    dxf.pair(0, "ENDTAB");
+
+    dxf.pair(0, "ENDSEC");
+    Some((model_space, paper_space))
+}
+
+fn write_layer(dxf: &mut DxfWriter, table: &str, name: &str, color: Option<[u8; 3]>) {
+    dxf.start("LAYER", table, &["AcDbSymbolTableRecord", "AcDbLayerTableRecord"]);
+    dxf.pair(2, name);
+    dxf.pair(70, 0);
+    dxf.pair(62, color.map(nearest_aci).unwrap_or(7));
+    dxf.pair(6, "CONTINUOUS");
+
+    // R2000 can also carry the exact color
+    if let (Some([r, g, b]), DxfVersion::R2000) = (color, dxf.version) {
+        dxf.pair(420, (r as u32) << 16 | (g as u32) << 8 | b as u32);
+    }
+}
+
+// Empty blocks for the two layouts; the entities themselves live in the ENTITIES section
+fn write_blocks(dxf: &mut DxfWriter, model_space: &str, paper_space: &str) {
+    dxf.pair(0, "SECTION");
+    dxf.pair(2, "BLOCKS");
+
+    for (name, record) in [("*Model_Space", model_space), ("*Paper_Space", paper_space)] {
+        dxf.start("BLOCK", record, &["AcDbEntity"]);
+        if name == "*Paper_Space" {
+            dxf.pair(67, 1);
+        }
+        dxf.pair(8, "0");
+        dxf.pair(100, "AcDbBlockBegin");
+        dxf.pair(2, name);
+        dxf.pair(70, 0);
+        dxf.point(0.0, 0.0);
+        dxf.pair(3, name);
+        dxf.pair(1, "");
+
+        dxf.start("ENDBLK", record, &["AcDbEntity"]);
+        if name == "*Paper_Space" {
+            dxf.pair(67, 1);
+        }
+        dxf.pair(8, "0");
+        dxf.pair(100, "AcDbBlockEnd");
+    }
+
+    dxf.pair(0, "ENDSEC");
+}
+
+// The root dictionary, which the drawing owns, with the group dictionary readers look for
+fn write_objects(dxf: &mut DxfWriter) {
+    dxf.pair(0, "SECTION");
+    dxf.pair(2, "OBJECTS");
+
+    let root = dxf.start("DICTIONARY", "0", &["AcDbDictionary"]);
+    let groups = dxf.handle();
+    dxf.pair(3, "ACAD_GROUP");
+    dxf.pair(350, &groups);
+
+    dxf.pair(0, "DICTIONARY");
+    dxf.pair(5, &groups);
+    dxf.pair(330, &root);
+    dxf.pair(100, "AcDbDictionary");
+
+    dxf.pair(0, "ENDSEC");
+}
+
+fn write_polyline(dxf: &mut DxfWriter, layer: &str, points: &[(f64, f64)], closed: bool) {
+    // A closed polyline does not repeat its first point
+    let points = if closed && points.len() > 1 && points.first() == points.last() {
+        &points[..points.len() - 1]
+    } else {
+        points
+    };
+
+    if points.len() < 2 {
+        return;
+    }
+
+    match dxf.version {
+        DxfVersion::R12 => {
+            dxf.pair(0, "POLYLINE");
+            dxf.pair(8, layer);
+            dxf.pair(66, 1);
+            dxf.pair(70, if closed { 1 } else { 0 });
+            dxf.point(0.0, 0.0);
+            for &(x, y) in points {
+                dxf.pair(0, "VERTEX");
+                dxf.pair(8, layer);
+                dxf.point(x, y);
+            }
+            dxf.pair(0, "SEQEND");
+            dxf.pair(8, layer);
+        }
+        DxfVersion::R2000 => {
+            dxf.start_entity("LWPOLYLINE");
+            dxf.pair(8, layer);
+            dxf.pair(100, "AcDbPolyline");
+            dxf.pair(90, points.len());
+            dxf.pair(70, if closed { 1 } else { 0 });
+            for &(x, y) in points {
+                dxf.number(10, x);
+                dxf.number(20, y);
+            }
+        }
+    }
+}
+
+// Write a chain of cubic Béziers as one clamped cubic B-spline
+fn write_spline<F: Fn(Point) -> (f64, f64)>(dxf: &mut DxfWriter, layer: &str, subpath: &Subpath, to_units: &F) {
+    let mut control_points = vec![subpath.start];
+    let mut current = subpath.start;
+
+    let mut segments = subpath.segments.clone();
+    if subpath.closed && segments.last().map(Segment::end) != Some(subpath.start) {
+        segments.push(Segment::Line { to: subpath.start });
+    }
+
+    for segment in &segments {
+        match segment {
+            // Straight lines become cubics with evenly spaced control points
+            Segment::Line { to } => {
+                control_points.push(Point::new(
+                    current.x + (to.x - current.x) / 3.0,
+                    current.y + (to.y - current.y) / 3.0,
+                ));
+                control_points.push(Point::new(
+                    current.x + (to.x - current.x) * 2.0 / 3.0,
+                    current.y + (to.y - current.y) * 2.0 / 3.0,
+                ));
+                control_points.push(*to);
+                current = *to;
+            }
+            Segment::Cubic { c1, c2, to } => {
+                control_points.extend([*c1, *c2, *to]);
+                current = *to;
+            }
+        }
+    }
+
+    // Each Bézier joint is a knot of multiplicity 3, clamped with multiplicity 4 at the ends
+    let curves = segments.len();
+    let mut knots = vec![0.0; 4];
+    for index in 1..curves {
+        knots.extend([index as f64; 3]);
+    }
+    knots.extend([curves as f64; 4]);
+
+    dxf.start_entity("SPLINE");
+    dxf.pair(8, layer);
+    dxf.pair(100, "AcDbSpline");
+    // Normal vector of the drawing plane
+    dxf.number(210, 0.0);
+    dxf.number(220, 0.0);
+    dxf.number(230, 1.0);
+    // 8 = planar
+    dxf.pair(70, 8);
+    dxf.pair(71, 3);
+    dxf.pair(72, knots.len());
+    dxf.pair(73, control_points.len());
+    dxf.pair(74, 0);
+    for knot in knots {
+        dxf.number(40, knot);
+    }
+    for point in control_points {
+        let (x, y) = to_units(point);
+        dxf.point(x, y);
+    }
+}
+
+fn layer_name([r, g, b]: [u8; 3]) -> String {
+    format!("COLOR_{:02X}{:02X}{:02X}", r, g, b)
+}
+
+fn nearest_aci(color: [u8; 3]) -> i32 {
+    ACI_COLORS
+        .iter()
+        .min_by_key(|(_, aci)| {
+            (0..3)
+                .map(|channel| (color[channel] as i32 - aci[channel] as i32).pow(2))
+                .sum::<i32>()
+        })
+        .map(|(index, _)| *index)
+        .unwrap_or(7)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Segment, VectorPath};
+
+    fn image() -> VectorImage {
+        let square = Subpath {
+            start: Point::new(0.0, 0.0),
+            segments: vec![
+                Segment::Line { to: Point::new(10.0, 0.0) },
+                Segment::Line { to: Point::new(10.0, 10.0) },
+                Segment::Line { to: Point::new(0.0, 10.0) },
+            ],
+            closed: true,
+        };
+        let curve = Subpath {
+            start: Point::new(0.0, 0.0),
+            segments: vec![Segment::Cubic {
+                c1: Point::new(5.0, 0.0),
+                c2: Point::new(10.0, 5.0),
+                to: Point::new(10.0, 10.0),
+            }],
+            closed: false,
+        };
+
+        VectorImage {
+            width: 20,
+            height: 20,
+            paths: vec![
+                VectorPath { color: [0, 0, 0], subpaths: vec![square], stroke_width: None },
+                VectorPath { color: [255, 0, 0], subpaths: vec![curve], stroke_width: None },
+            ],
+            pixel_grid: false,
+        }
+    }
+
+    // Group code / value pairs of a written drawing
+    fn pairs(version: DxfVersion) -> Vec<(i32, String)> {
+        let options = ExportOptions { dxf_version: version, ..ExportOptions::default() };
+        let dxf = String::from_utf8(write_dxf(&image(), &options)).unwrap();
+        let lines: Vec<&str> = dxf.lines().collect();
+        lines
+            .chunks(2)
+            .map(|pair| (pair[0].trim().parse().unwrap(), pair[1].to_string()))
+            .collect()
+    }
+
+    fn sections(pairs: &[(i32, String)]) -> Vec<&str> {
+        pairs
+            .windows(2)
+            .filter(|window| window[0] == (0, "SECTION".to_string()))
+            .map(|window| window[1].1.as_str())
+            .collect()
+    }
+
+    // Value of the first `code` after the object that starts at `index`
+    fn value_after(pairs: &[(i32, String)], index: usize, code: i32) -> Option<&str> {
+        pairs[index + 1..]
+            .iter()
+            .take_while(|(c, _)| *c != 0)
+            .find(|(c, _)| *c == code)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn r12_has_no_handles_or_blocks() {
+        let pairs = pairs(DxfVersion::R12);
+
+        assert_eq!(sections(&pairs), ["HEADER", "TABLES", "ENTITIES"]);
+        assert!(pairs.iter().all(|(code, _)| ![5, 100, 330].contains(code)));
+        assert!(pairs.contains(&(0, "POLYLINE".to_string())));
+        assert_eq!(pairs.last(), Some(&(0, "EOF".to_string())));
+    }
+
+    #[test]
+    fn r2000_has_block_records_blocks_and_owned_handles() {
+        let pairs = pairs(DxfVersion::R2000);
+
+        assert_eq!(sections(&pairs), ["HEADER", "CLASSES", "TABLES", "BLOCKS", "ENTITIES", "OBJECTS"]);
+
+        // Every handle is unique and below the seed
+        let seed_index = pairs.iter().position(|pair| *pair == (9, "$HANDSEED".to_string())).unwrap();
+        let seed = u32::from_str_radix(&pairs[seed_index + 1].1, 16).unwrap();
+        let handles: Vec<&str> = pairs[seed_index + 2..]
+            .iter()
+            .filter(|(code, _)| *code == 5)
+            .map(|(_, value)| value.as_str())
+            .collect();
+        let mut unique = handles.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), handles.len());
+        assert!(handles.iter().all(|handle| u32::from_str_radix(handle, 16).unwrap() < seed));
+
+        // Every owner and dictionary entry refers to an object in the file, or to the drawing itself
+        for (code, value) in &pairs {
+            if *code == 330 || *code == 350 {
+                assert!(value == "0" || handles.contains(&value.as_str()), "{} {}", code, value);
+            }
+        }
+
+        // Both layouts have a block record and a block owned by it
+        let record = |name: &str| {
+            let index = pairs
+                .iter()
+                .enumerate()
+                .position(|(index, pair)| {
+                    pair.1 == "BLOCK_RECORD" && pair.0 == 0 && value_after(&pairs, index, 2) == Some(name)
+                })
+                .unwrap();
+            value_after(&pairs, index, 5).unwrap().to_string()
+        };
+        let model_space = record("*Model_Space");
+        let paper_space = record("*Paper_Space");
+        for (index, (code, value)) in pairs.iter().enumerate() {
+            if *code == 0 && (value == "BLOCK" || value == "ENDBLK") {
+                let owner = value_after(&pairs, index, 330).unwrap();
+                assert!(owner == model_space || owner == paper_space);
+            }
+        }
+
+        // Drawn entities belong to model space
+        for kind in ["LWPOLYLINE", "SPLINE"] {
+            let index = pairs.iter().position(|pair| *pair == (0, kind.to_string())).unwrap();
+            assert_eq!(value_after(&pairs, index, 330), Some(model_space.as_str()), "{}", kind);
+        }
+
+        // The root dictionary is the first object and is owned by the drawing
+        let objects = pairs.iter().position(|pair| *pair == (2, "OBJECTS".to_string())).unwrap();
+        assert_eq!(pairs[objects + 1], (0, "DICTIONARY".to_string()));
+        assert_eq!(value_after(&pairs, objects + 1, 330), Some("0"));
+        assert_eq!(value_after(&pairs, objects + 1, 3), Some("ACAD_GROUP"));
+    }
+}
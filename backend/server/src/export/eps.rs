@@ -0,0 +1,106 @@
+// src/export/eps.rs
+use super::{format_number, ExportOptions};
+use crate::geometry::{Segment, VectorImage};
+use std::fmt::Write;
+
+// PostScript user space is measured in points, 72 to the inch
+const POINTS_PER_INCH: f64 = 72.0;
+
+// Write the traced paths as PostScript; `encapsulated` produces an EPS for placing in other documents
+pub fn write_postscript(image: &VectorImage, options: &ExportOptions, encapsulated: bool) -> Vec<u8> {
+    let scale = POINTS_PER_INCH / options.dpi;
+    let page_width = image.width as f64 * scale;
+    let page_height = image.height as f64 * scale;
+
+    let mut ps = String::new();
+
+    // Header comments
+    if encapsulated {
+        ps.push_str("%!PS-Adobe-3.0 EPSF-3.0\n");
+    } else {
+        ps.push_str("%!PS-Adobe-3.0\n");
+    }
+    let _ = writeln!(ps, "%%BoundingBox: 0 0 {} {}", page_width.ceil() as i64, page_height.ceil() as i64);
+    let _ = writeln!(ps, "%%HiResBoundingBox: 0 0 {} {}", format_number(page_width), format_number(page_height));
+    ps.push_str("%%Creator: Vectorise.Me\n");
+    ps.push_str("%%LanguageLevel: 2\n");
+    ps.push_str("%%Pages: 1\n");
+    ps.push_str("%%EndComments\n");
+
+    // Short names keep large files smaller
+    ps.push_str("/m { moveto } bind def\n");
+    ps.push_str("/l { lineto } bind def\n");
+    ps.push_str("/c { curveto } bind def\n");
+    ps.push_str("/h { closepath } bind def\n");
+    ps.push_str("%%Page: 1 1\n");
+
+    // A full PostScript document sets its own page size
+    if !encapsulated {
+        let _ = writeln!(
+            ps,
+            "<< /PageSize [{} {}] >> setpagedevice",
+            format_number(page_width),
+            format_number(page_height)
+        );
+    }
+
+    // Scale pixels to points and flip the y axis, since PostScript y points up
+    ps.push_str("gsave\n");
+    let _ = writeln!(ps, "[{} 0 0 {} 0 {}] concat", scale, -scale, page_height);
+
+    for path in image.paths.iter().filter(|path| !path.subpaths.is_empty()) {
+        let [r, g, b] = path.color;
+        let _ = writeln!(
+            ps,
+            "{} {} {} setrgbcolor",
+            format_number(r as f64 / 255.0),
+            format_number(g as f64 / 255.0),
+            format_number(b as f64 / 255.0)
+        );
+        if let Some(width) = path.stroke_width {
+            // Round caps and joins, as in the SVG
+            let _ = writeln!(ps, "{} setlinewidth 1 setlinecap 1 setlinejoin", format_number(width));
+        }
+
+        ps.push_str("newpath\n");
+        for subpath in &path.subpaths {
+            let _ = writeln!(ps, "{} {} m", format_number(subpath.start.x), format_number(subpath.start.y));
+            for segment in &subpath.segments {
+                match segment {
+                    Segment::Line { to } => {
+                        let _ = writeln!(ps, "{} {} l", format_number(to.x), format_number(to.y));
+                    }
+                    Segment::Cubic { c1, c2, to } => {
+                        let _ = writeln!(
+                            ps,
+                            "{} {} {} {} {} {} c",
+                            format_number(c1.x),
+                            format_number(c1.y),
+                            format_number(c2.x),
+                            format_number(c2.y),
+                            format_number(to.x),
+                            format_number(to.y)
+                        );
+                    }
+                }
+            }
+            if subpath.closed {
+                ps.push_str("h\n");
+            }
+        }
+
+        // Centerlines are stroked with the width set above; `fill` rather than `eofill` keeps the
+        // SVG's non-zero fill rule
+        if path.stroke_width.is_some() {
+            ps.push_str("stroke\n");
+        } else {
+            ps.push_str("fill\n");
+        }
+    }
+
+    ps.push_str("grestore\n");
+    ps.push_str("showpage\n");
+    ps.push_str("%%EOF\n");
+
+    ps.into_bytes()
+}
@@ -0,0 +1,252 @@
+// src/export/gcode.rs
+use super::{format_number, group_toolpaths, order_toolpaths, ExportOptions, ToolLift, Units};
+use crate::geometry::VectorImage;
+use std::fmt::Write;
+
+// Machine defaults in millimetres, converted when the output is in inches
+const DEFAULT_FEED_RATE_MM: f64 = 1000.0;
+const DEFAULT_Z_UP_MM: f64 = 5.0;
+const DEFAULT_Z_DOWN_MM: f64 = 0.0;
+
+// Travel and cutting heights in `units`, with the machine defaults for unset values
+pub fn z_heights(options: &ExportOptions) -> (f64, f64) {
+    let from_mm = from_mm(options.units);
+    (
+        options.z_up.unwrap_or(DEFAULT_Z_UP_MM * from_mm),
+        options.z_down.unwrap_or(DEFAULT_Z_DOWN_MM * from_mm),
+    )
+}
+
+fn from_mm(units: Units) -> f64 {
+    match units {
+        Units::Mm => 1.0,
+        Units::Inch => 1.0 / 25.4,
+    }
+}
+
+// Write the traced outlines as toolpaths for pen plotters and CNC machines
+pub fn write_gcode(image: &VectorImage, options: &ExportOptions) -> Vec<u8> {
+    let scale = options.units.per_pixel(options.dpi);
+    let height = image.height as f64;
+    let tolerance = options.tolerance_px();
+
+    let feed_rate = options.feed_rate.unwrap_or(DEFAULT_FEED_RATE_MM * from_mm(options.units));
+    let (z_up, z_down) = z_heights(options);
+
+    // Machine y points up
+    let mut groups = group_toolpaths(image, tolerance, |point| (point.x * scale, (height - point.y) * scale));
+
+    // Order every group from wherever the previous one left the tool
+    let mut position = (0.0, 0.0);
+    let mut travel = 0.0;
+    let mut toolpath_count = 0;
+    for (_, toolpaths) in groups.iter_mut() {
+        order_toolpaths(toolpaths, &mut position, &mut travel);
+        toolpath_count += toolpaths.len();
+    }
+
+    let unit_name = match options.units {
+        Units::Mm => "mm",
+        Units::Inch => "inch",
+    };
+
+    let mut gcode = String::new();
+
+    // Header
+    gcode.push_str("; Generated by Vectorise.Me\n");
+    let _ = writeln!(
+        gcode,
+        "; {} x {} {}, {} toolpaths, {} {} of travel",
+        format_number(image.width as f64 * scale),
+        format_number(height * scale),
+        unit_name,
+        toolpath_count,
+        format_number(travel),
+        unit_name
+    );
+    match options.units {
+        Units::Mm => gcode.push_str("G21 ; millimetres\n"),
+        Units::Inch => gcode.push_str("G20 ; inches\n"),
+    }
+    gcode.push_str("G90 ; absolute positioning\n");
+    tool_up(&mut gcode, options.lift, z_up);
+
+    for (color, toolpaths) in &groups {
+        let [r, g, b] = *color;
+        let _ = writeln!(gcode, "; color #{:02x}{:02x}{:02x}", r, g, b);
+
+        for toolpath in toolpaths {
+            let (x, y) = toolpath.start();
+            let _ = writeln!(gcode, "G0 X{} Y{}", format_number(x), format_number(y));
+            tool_down(&mut gcode, options.lift, z_down, feed_rate);
+
+            // The feed rate is modal: a Z plunge has already set it, otherwise the first cutting move gives it
+            for (index, &(x, y)) in toolpath.points[1..].iter().enumerate() {
+                let _ = write!(gcode, "G1 X{} Y{}", format_number(x), format_number(y));
+                if index == 0 && options.lift == ToolLift::Pen {
+                    let _ = write!(gcode, " F{}", format_number(feed_rate));
+                }
+                gcode.push('\n');
+            }
+
+            tool_up(&mut gcode, options.lift, z_up);
+        }
+    }
+
+    // Park at the origin and end the program
+    gcode.push_str("G0 X0 Y0\n");
+    gcode.push_str("M2\n");
+
+    gcode.into_bytes()
+}
+
+fn tool_up(gcode: &mut String, lift: ToolLift, z_up: f64) {
+    match lift {
+        ToolLift::Z => {
+            let _ = writeln!(gcode, "G0 Z{}", format_number(z_up));
+        }
+        ToolLift::Pen => gcode.push_str("M5 ; pen up\n"),
+    }
+}
+
+fn tool_down(gcode: &mut String, lift: ToolLift, z_down: f64, feed_rate: f64) {
+    match lift {
+        ToolLift::Z => {
+            let _ = writeln!(gcode, "G1 Z{} F{}", format_number(z_down), format_number(feed_rate));
+        }
+        ToolLift::Pen => gcode.push_str("M3 ; pen down\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{distance, Toolpath};
+    use crate::geometry::{Point, Segment, Subpath, VectorPath};
+
+    // Open lines across a 96 x 96 pixel image, one inch square at the default 96 dpi
+    fn image() -> VectorImage {
+        let line = |from: (f64, f64), to: (f64, f64)| Subpath {
+            start: Point::new(from.0, from.1),
+            segments: vec![Segment::Line { to: Point::new(to.0, to.1) }],
+            closed: false,
+        };
+
+        VectorImage {
+            width: 96,
+            height: 96,
+            paths: vec![
+                VectorPath {
+                    color: [0, 0, 0],
+                    subpaths: vec![line((0.0, 96.0), (48.0, 96.0)), line((96.0, 0.0), (48.0, 0.0))],
+                    stroke_width: Some(1.0),
+                },
+                VectorPath { color: [255, 0, 0], subpaths: vec![line((0.0, 0.0), (0.0, 48.0))], stroke_width: Some(1.0) },
+            ],
+            pixel_grid: false,
+        }
+    }
+
+    fn gcode(options: &ExportOptions) -> Vec<String> {
+        String::from_utf8(write_gcode(&image(), options))
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn header_sets_units_and_absolute_positioning() {
+        let mm = gcode(&ExportOptions::default());
+        assert!(mm.contains(&"G21 ; millimetres".to_string()));
+        assert!(mm.contains(&"G90 ; absolute positioning".to_string()));
+        assert!(mm[1].starts_with("; 25.4 x 25.4 mm, 3 toolpaths"), "{}", mm[1]);
+
+        let inch = gcode(&ExportOptions { units: Units::Inch, ..ExportOptions::default() });
+        assert!(inch.contains(&"G20 ; inches".to_string()));
+        assert!(!inch.iter().any(|line| line.starts_with("G21")));
+        assert!(inch[1].starts_with("; 1 x 1 inch"), "{}", inch[1]);
+
+        // The program parks the tool and ends
+        assert_eq!(mm[mm.len() - 2..], ["G0 X0 Y0", "M2"]);
+    }
+
+    #[test]
+    fn feed_rate_is_given_once_per_cut() {
+        for lift in [ToolLift::Z, ToolLift::Pen] {
+            let options = ExportOptions { lift, feed_rate: Some(600.0), ..ExportOptions::default() };
+            let lines = gcode(&options);
+
+            let mut cuts = 0;
+            let mut feeds_in_cut = None;
+            for line in &lines {
+                if line.starts_with("G1 Z") || line.starts_with("M3") {
+                    feeds_in_cut = Some(0);
+                    cuts += 1;
+                }
+                if line.contains(" F") {
+                    assert!(line.ends_with(" F600"), "{}", line);
+                    *feeds_in_cut.as_mut().expect("feed rate outside a cut") += 1;
+                }
+                if line.starts_with("G0 Z") || line.starts_with("M5") {
+                    if let Some(feeds) = feeds_in_cut.take() {
+                        assert_eq!(feeds, 1, "{:?}", lift);
+                    }
+                }
+            }
+            assert_eq!(cuts, 3, "{:?}", lift);
+        }
+    }
+
+    #[test]
+    fn tool_is_lifted_by_z_moves_or_pen_commands() {
+        let options = ExportOptions { z_up: Some(3.0), z_down: Some(-1.5), ..ExportOptions::default() };
+        let z = gcode(&options);
+        assert_eq!(z.iter().filter(|line| *line == "G0 Z3").count(), 4);
+        assert_eq!(z.iter().filter(|line| *line == "G1 Z-1.5 F1000").count(), 3);
+        assert!(!z.iter().any(|line| line.starts_with("M3") || line.starts_with("M5")));
+
+        let pen = gcode(&ExportOptions { lift: ToolLift::Pen, ..options });
+        assert_eq!(pen.iter().filter(|line| *line == "M5 ; pen up").count(), 4);
+        assert_eq!(pen.iter().filter(|line| *line == "M3 ; pen down").count(), 3);
+        assert!(!pen.iter().any(|line| line.contains('Z')));
+
+        // Inches convert the default heights
+        let inch = gcode(&ExportOptions { units: Units::Inch, ..ExportOptions::default() });
+        assert!(inch.contains(&"G0 Z0.197".to_string()));
+    }
+
+    #[test]
+    fn ordering_toolpaths_shortens_travel() {
+        let toolpath = |points: &[(f64, f64)], closed| Toolpath { points: points.to_vec(), closed };
+        let traced = || {
+            vec![
+                toolpath(&[(100.0, 0.0), (90.0, 0.0)], false),
+                toolpath(&[(10.0, 0.0), (20.0, 0.0), (20.0, 10.0), (10.0, 0.0)], true),
+                toolpath(&[(50.0, 0.0), (60.0, 0.0)], false),
+                toolpath(&[(40.0, 0.0), (30.0, 0.0)], false),
+            ]
+        };
+
+        // Travel in the traced order, from the origin
+        let mut position = (0.0, 0.0);
+        let mut unordered = 0.0;
+        for toolpath in traced() {
+            unordered += distance(position, toolpath.start());
+            position = toolpath.end();
+        }
+
+        let mut toolpaths = traced();
+        let mut position = (0.0, 0.0);
+        let mut travel = 0.0;
+        order_toolpaths(&mut toolpaths, &mut position, &mut travel);
+
+        assert!(travel < unordered, "{} >= {}", travel, unordered);
+        assert_eq!(travel, 10.0 + 20.0 + 10.0 + 30.0);
+        assert_eq!(position, (100.0, 0.0));
+
+        // Open toolpaths are reversed to start at their nearest end, closed ones are not
+        let starts: Vec<(f64, f64)> = toolpaths.iter().map(|toolpath| toolpath.start()).collect();
+        assert_eq!(starts, [(10.0, 0.0), (30.0, 0.0), (50.0, 0.0), (90.0, 0.0)]);
+    }
+}
@@ -0,0 +1,259 @@
+// src/export/hpgl.rs
+use super::{distance, group_toolpaths, order_toolpaths, ExportOptions, Mirror, Toolpath, Units};
+use crate::geometry::{Point, VectorImage};
+use std::fmt::Write;
+
+// HPGL plotter units: 40 to the millimetre
+const PLOTTER_UNITS_PER_MM: f64 = 40.0;
+
+// Direction changes smaller than this are cut straight through without swivelling the blade
+const MIN_SWIVEL_ANGLE: f64 = 10.0 * std::f64::consts::PI / 180.0;
+
+// Largest angle covered by one line of a flattened swivel arc
+const SWIVEL_STEP: f64 = 15.0 * std::f64::consts::PI / 180.0;
+
+// Write the traced outlines as HPGL for vinyl cutters and pen plotters
+pub fn write_hpgl(image: &VectorImage, options: &ExportOptions) -> Vec<u8> {
+    let to_plotter_units = match options.units {
+        Units::Mm => PLOTTER_UNITS_PER_MM,
+        Units::Inch => PLOTTER_UNITS_PER_MM * 25.4,
+    };
+    let scale = options.units.per_pixel(options.dpi) * to_plotter_units;
+    let width = image.width as f64 * scale;
+    let height = image.height as f64 * scale;
+
+    let origin = (options.origin_x * to_plotter_units, options.origin_y * to_plotter_units);
+
+    let to_plotter = |point: Point| {
+        // Plotter y points up
+        let (mut x, mut y) = (point.x * scale, height - point.y * scale);
+
+        match options.mirror {
+            Mirror::None => {}
+            Mirror::X => x = width - x,
+            Mirror::Y => y = height - y,
+        }
+
+        // Rotate counterclockwise, then shift back into the positive quadrant
+        let (x, y) = match options.rotation {
+            90 => (height - y, x),
+            180 => (width - x, height - y),
+            270 => (y, width - x),
+            _ => (x, y),
+        };
+
+        (x + origin.0, y + origin.1)
+    };
+
+    let mut groups = group_toolpaths(image, options.tolerance_px(), to_plotter);
+
+    let mut position = origin;
+    let mut travel = 0.0;
+    for (_, toolpaths) in groups.iter_mut() {
+        order_toolpaths(toolpaths, &mut position, &mut travel);
+    }
+
+    let blade_offset = options.blade_offset.map(|offset| offset * to_plotter_units);
+
+    let mut hpgl = String::new();
+    hpgl.push_str("IN;SP1;\n");
+
+    for (_, toolpaths) in &groups {
+        for toolpath in toolpaths {
+            let points = match blade_offset {
+                Some(offset) if offset > 0.0 => compensate_blade_offset(toolpath, offset),
+                _ => toolpath.points.clone(),
+            };
+
+            let (x, y) = points[0];
+            let _ = writeln!(hpgl, "PU{},{};", x.round() as i64, y.round() as i64);
+
+            hpgl.push_str("PD");
+            for (index, &(x, y)) in points[1..].iter().enumerate() {
+                if index > 0 {
+                    hpgl.push(',');
+                }
+                let _ = write!(hpgl, "{},{}", x.round() as i64, y.round() as i64);
+            }
+            hpgl.push_str(";\n");
+        }
+    }
+
+    // Lift the tool, park at the origin and deselect the pen
+    let _ = writeln!(hpgl, "PU{},{};", origin.0.round() as i64, origin.1.round() as i64);
+    hpgl.push_str("SP0;\n");
+
+    hpgl.into_bytes()
+}
+
+// A drag knife's tip trails its axis by `offset`. Move the axis ahead of the wanted cut along each
+// line and swivel it around the corners so the tip follows the outline instead of rounding it off.
+fn compensate_blade_offset(toolpath: &Toolpath, offset: f64) -> Vec<(f64, f64)> {
+    // Directions of the non-degenerate segments, each with the point it ends at
+    let mut segments: Vec<((f64, f64), (f64, f64))> = Vec::new();
+    let mut previous = toolpath.start();
+    for &point in &toolpath.points[1..] {
+        let length = distance(previous, point);
+        if length > 1e-9 {
+            let direction = ((point.0 - previous.0) / length, (point.1 - previous.1) / length);
+            segments.push((direction, point));
+            previous = point;
+        }
+    }
+
+    if segments.is_empty() {
+        return toolpath.points.clone();
+    }
+
+    let ahead = |point: (f64, f64), direction: (f64, f64)| {
+        (point.0 + direction.0 * offset, point.1 + direction.1 * offset)
+    };
+
+    let mut points = vec![ahead(toolpath.start(), segments[0].0)];
+    for (index, &(direction, end)) in segments.iter().enumerate() {
+        points.push(ahead(end, direction));
+
+        // Closed outlines swivel back to the first direction to finish the last corner
+        let next = match segments.get(index + 1) {
+            Some(&(next, _)) => next,
+            None if toolpath.closed => segments[0].0,
+            None => break,
+        };
+        swivel(&mut points, end, direction, next, offset);
+    }
+
+    points
+}
+
+// Turn the blade around `corner` from one direction to the next along an arc of radius `offset`
+fn swivel(points: &mut Vec<(f64, f64)>, corner: (f64, f64), from: (f64, f64), to: (f64, f64), offset: f64) {
+    let start_angle = from.1.atan2(from.0);
+    let mut turn = to.1.atan2(to.0) - start_angle;
+    if turn > std::f64::consts::PI {
+        turn -= 2.0 * std::f64::consts::PI;
+    } else if turn < -std::f64::consts::PI {
+        turn += 2.0 * std::f64::consts::PI;
+    }
+
+    if turn.abs() < MIN_SWIVEL_ANGLE {
+        return;
+    }
+
+    let steps = (turn.abs() / SWIVEL_STEP).ceil() as usize;
+    for step in 1..=steps {
+        let angle = start_angle + turn * step as f64 / steps as f64;
+        points.push((corner.0 + angle.cos() * offset, corner.1 + angle.sin() * offset));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Segment, Subpath, VectorPath};
+
+    // A closed right triangle in a 10 x 20 pixel image: (0, 0), (10, 0) and (0, 5)
+    fn triangle() -> VectorImage {
+        let outline = Subpath {
+            start: Point::new(0.0, 0.0),
+            segments: vec![
+                Segment::Line { to: Point::new(10.0, 0.0) },
+                Segment::Line { to: Point::new(0.0, 5.0) },
+            ],
+            closed: true,
+        };
+
+        VectorImage {
+            width: 10,
+            height: 20,
+            paths: vec![VectorPath { color: [0, 0, 0], subpaths: vec![outline], stroke_width: None }],
+            pixel_grid: false,
+        }
+    }
+
+    // The toolpath lines between the initialisation and the final park, at 1 pixel = 1 mm (40 plotter units)
+    fn toolpaths(options: ExportOptions) -> String {
+        let options = ExportOptions { dpi: 25.4, ..options };
+        let hpgl = String::from_utf8(write_hpgl(&triangle(), &options)).unwrap();
+        let lines: Vec<&str> = hpgl.lines().collect();
+        lines[1..lines.len() - 2].join("\n")
+    }
+
+    #[test]
+    fn document_selects_the_pen_and_parks_at_the_origin() {
+        let hpgl = String::from_utf8(write_hpgl(&triangle(), &ExportOptions { dpi: 25.4, ..ExportOptions::default() })).unwrap();
+        assert_eq!(hpgl, "IN;SP1;\nPU0,800;\nPD400,800,0,600,0,800;\nPU0,0;\nSP0;\n");
+    }
+
+    #[test]
+    fn coordinates_are_converted_to_plotter_units() {
+        // 96 dpi in inches: 1016 plotter units to the inch, 10.583 per pixel
+        let options = ExportOptions { units: Units::Inch, ..ExportOptions::default() };
+        let hpgl = String::from_utf8(write_hpgl(&triangle(), &options)).unwrap();
+        assert_eq!(hpgl, "IN;SP1;\nPU0,212;\nPD106,212,0,159,0,212;\nPU0,0;\nSP0;\n");
+    }
+
+    #[test]
+    fn rotation_mirroring_and_origin_place_the_outline() {
+        let cases = [
+            (ExportOptions::default(), "PU0,800;\nPD400,800,0,600,0,800;"),
+            (ExportOptions { rotation: 90, ..ExportOptions::default() }, "PU0,0;\nPD0,400,200,0,0,0;"),
+            (ExportOptions { rotation: 180, ..ExportOptions::default() }, "PU400,0;\nPD0,0,400,200,400,0;"),
+            (ExportOptions { rotation: 270, ..ExportOptions::default() }, "PU800,400;\nPD800,0,600,400,800,400;"),
+            (ExportOptions { mirror: Mirror::X, ..ExportOptions::default() }, "PU400,800;\nPD0,800,400,600,400,800;"),
+            (ExportOptions { mirror: Mirror::Y, ..ExportOptions::default() }, "PU0,0;\nPD400,0,0,200,0,0;"),
+            // Mirrored first, then rotated
+            (
+                ExportOptions { mirror: Mirror::X, rotation: 90, ..ExportOptions::default() },
+                "PU0,400;\nPD0,0,200,400,0,400;",
+            ),
+            // The origin is in `units`, 40 plotter units to the millimetre
+            (
+                ExportOptions { origin_x: 5.0, origin_y: 2.5, ..ExportOptions::default() },
+                "PU200,900;\nPD600,900,200,700,200,900;",
+            ),
+        ];
+
+        for (options, expected) in cases {
+            let description = format!("{:?} {:?} ({}, {})", options.rotation, options.mirror, options.origin_x, options.origin_y);
+            assert_eq!(toolpaths(options), expected, "{}", description);
+        }
+    }
+
+    #[test]
+    fn blade_offset_leads_each_line_and_swivels_around_corners() {
+        let corner = Toolpath { points: vec![(0.0, 0.0), (100.0, 0.0), (100.0, 100.0)], closed: false };
+        let points = compensate_blade_offset(&corner, 10.0);
+
+        // The axis runs the offset ahead of the start and past the corner
+        assert_eq!(points[..2], [(10.0, 0.0), (110.0, 0.0)]);
+        assert_eq!(points[points.len() - 1], (100.0, 110.0));
+
+        // Then swivels a quarter turn around the corner in 15 degree steps
+        let arc = &points[2..points.len() - 1];
+        assert_eq!(arc.len(), 6);
+        for &point in arc {
+            assert!((distance(point, (100.0, 0.0)) - 10.0).abs() < 1e-9, "{:?}", point);
+        }
+        assert!(distance(arc[5], (100.0, 10.0)) < 1e-9);
+
+        // Slight bends are cut straight through
+        let bend = Toolpath { points: vec![(0.0, 0.0), (100.0, 0.0), (200.0, 5.0)], closed: false };
+        assert_eq!(compensate_blade_offset(&bend, 10.0).len(), 3);
+
+        // A closed outline swivels at its start too: a square turns four quarter turns
+        let square = Toolpath {
+            points: vec![(0.0, 0.0), (100.0, 0.0), (100.0, 100.0), (0.0, 100.0), (0.0, 0.0)],
+            closed: true,
+        };
+        assert_eq!(compensate_blade_offset(&square, 10.0).len(), 1 + 4 + 4 * 6);
+    }
+
+    #[test]
+    fn blade_offset_is_given_in_units() {
+        let options = ExportOptions { blade_offset: Some(0.25), ..ExportOptions::default() };
+        let lines = toolpaths(options);
+
+        // 0.25 mm is 10 plotter units ahead of the first corner, along the first edge
+        assert!(lines.starts_with("PU10,800;\nPD410,800,"), "{}", lines);
+    }
+}
@@ -0,0 +1,264 @@
+// src/export/mod.rs
+use crate::geometry::{Point, VectorImage};
+
+pub mod dxf;
+pub mod eps;
+pub mod gcode;
+pub mod hpgl;
+pub mod pdf;
+pub mod svg;
+
+// File formats the traced output can be downloaded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Svg,
+    Pdf,
+    Eps,
+    Ps,
+    Dxf,
+    Gcode,
+    Hpgl,
+}
+
+impl ExportFormat {
+    pub const ALL: &'static [ExportFormat] = &[
+        ExportFormat::Svg,
+        ExportFormat::Pdf,
+        ExportFormat::Eps,
+        ExportFormat::Ps,
+        ExportFormat::Dxf,
+        ExportFormat::Gcode,
+        ExportFormat::Hpgl,
+    ];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.extension() == value.to_ascii_lowercase())
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Svg => "svg",
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Eps => "eps",
+            ExportFormat::Ps => "ps",
+            ExportFormat::Dxf => "dxf",
+            ExportFormat::Gcode => "gcode",
+            ExportFormat::Hpgl => "hpgl",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Svg => "image/svg+xml",
+            ExportFormat::Pdf => "application/pdf",
+            ExportFormat::Eps | ExportFormat::Ps => "application/postscript",
+            ExportFormat::Dxf => "image/vnd.dxf",
+            ExportFormat::Gcode => "text/x-gcode",
+            ExportFormat::Hpgl => "application/vnd.hp-hpgl",
+        }
+    }
+}
+
+// Physical units for CAD and machine formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Units {
+    Mm,
+    Inch,
+}
+
+impl Units {
+    // Output units per source pixel at the given resolution
+    pub fn per_pixel(self, dpi: f64) -> f64 {
+        match self {
+            Units::Mm => 25.4 / dpi,
+            Units::Inch => 1.0 / dpi,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DxfVersion {
+    R12,
+    R2000,
+}
+
+// How curves are written by formats that can do either
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurveOutput {
+    Spline,
+    Polyline,
+}
+
+// How a plotter or CNC machine lifts its tool between toolpaths
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolLift {
+    // Move the Z axis between `z_up` and `z_down`
+    Z,
+    // Spindle commands (M3/M5) driving a pen servo
+    Pen,
+}
+
+// Mirroring applied by cutters, e.g. for cutting the back of transfer vinyl
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirror {
+    None,
+    // Flip left to right
+    X,
+    // Flip top to bottom
+    Y,
+}
+
+// Options shared by the export writers
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    // Source pixels per inch, used to size the output in physical units
+    pub dpi: f64,
+    pub units: Units,
+    pub dxf_version: DxfVersion,
+    pub curves: CurveOutput,
+    // Largest distance between a curve and its flattened lines, in output units
+    pub tolerance: Option<f64>,
+    // G-code only; unset values fall back to machine defaults in `units`
+    pub lift: ToolLift,
+    pub feed_rate: Option<f64>,
+    pub z_up: Option<f64>,
+    pub z_down: Option<f64>,
+    // HPGL only: placement on the cutter in `units`, counterclockwise rotation in degrees and mirroring
+    pub origin_x: f64,
+    pub origin_y: f64,
+    pub rotation: u16,
+    pub mirror: Mirror,
+    // HPGL only: distance a drag knife's tip trails its axis, in `units`
+    pub blade_offset: Option<f64>,
+}
+
+impl ExportOptions {
+    // Flattening tolerance converted to source pixels
+    pub fn tolerance_px(&self) -> f64 {
+        let tolerance = self.tolerance.unwrap_or(match self.units {
+            Units::Mm => 0.05,
+            Units::Inch => 0.002,
+        });
+        tolerance / self.units.per_pixel(self.dpi)
+    }
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            dpi: 96.0,
+            units: Units::Mm,
+            dxf_version: DxfVersion::R2000,
+            curves: CurveOutput::Spline,
+            tolerance: None,
+            lift: ToolLift::Z,
+            feed_rate: None,
+            z_up: None,
+            z_down: None,
+            origin_x: 0.0,
+            origin_y: 0.0,
+            rotation: 0,
+            mirror: Mirror::None,
+            blade_offset: None,
+        }
+    }
+}
+
+// Format a coordinate compactly: at most three decimals and no trailing zeros
+pub fn format_number(value: f64) -> String {
+    let formatted = format!("{:.3}", value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+
+    match trimmed {
+        "" | "-" | "-0" => "0".to_string(),
+        other => other.to_string(),
+    }
+}
+
+// One outline for a plotter or cutter to follow, flattened into machine coordinates
+pub struct Toolpath {
+    pub points: Vec<(f64, f64)>,
+    pub closed: bool,
+}
+
+impl Toolpath {
+    pub fn start(&self) -> (f64, f64) {
+        self.points[0]
+    }
+
+    pub fn end(&self) -> (f64, f64) {
+        self.points[self.points.len() - 1]
+    }
+}
+
+// Flatten every outline with `to_machine` and group the results by color so each pen is used once
+pub fn group_toolpaths<F: Fn(Point) -> (f64, f64)>(
+    image: &VectorImage,
+    tolerance_px: f64,
+    to_machine: F,
+) -> Vec<([u8; 3], Vec<Toolpath>)> {
+    let mut groups: Vec<([u8; 3], Vec<Toolpath>)> = Vec::new();
+
+    for path in &image.paths {
+        let toolpaths: Vec<Toolpath> = path
+            .subpaths
+            .iter()
+            .map(|subpath| Toolpath {
+                points: subpath.flatten(tolerance_px).into_iter().map(&to_machine).collect(),
+                closed: subpath.closed,
+            })
+            .filter(|toolpath| toolpath.points.len() > 1)
+            .collect();
+
+        if toolpaths.is_empty() {
+            continue;
+        }
+
+        match groups.iter_mut().find(|(color, _)| *color == path.color) {
+            Some((_, existing)) => existing.extend(toolpaths),
+            None => groups.push((path.color, toolpaths)),
+        }
+    }
+
+    groups
+}
+
+// Greedy nearest-neighbour ordering to cut down on travel moves between toolpaths.
+// Open toolpaths may be drawn in either direction; closed ones start where they were traced.
+pub fn order_toolpaths(toolpaths: &mut Vec<Toolpath>, position: &mut (f64, f64), travel: &mut f64) {
+    let mut remaining = std::mem::take(toolpaths);
+
+    while !remaining.is_empty() {
+        let mut best = (0, false, f64::INFINITY);
+
+        for (index, toolpath) in remaining.iter().enumerate() {
+            let to_start = distance(*position, toolpath.start());
+            if to_start < best.2 {
+                best = (index, false, to_start);
+            }
+            if !toolpath.closed {
+                let to_end = distance(*position, toolpath.end());
+                if to_end < best.2 {
+                    best = (index, true, to_end);
+                }
+            }
+        }
+
+        let (index, reverse, length) = best;
+        let mut toolpath = remaining.swap_remove(index);
+        if reverse {
+            toolpath.points.reverse();
+        }
+
+        *travel += length;
+        *position = toolpath.end();
+        toolpaths.push(toolpath);
+    }
+}
+
+pub fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
@@ -0,0 +1,182 @@
+// src/export/pdf.rs
+use super::{format_number, ExportOptions};
+use crate::geometry::{Segment, VectorImage};
+use std::fmt::Write;
+
+// PDF user space is measured in points, 72 to the inch
+const POINTS_PER_INCH: f64 = 72.0;
+
+// Write the traced paths as a single-page vector PDF
+pub fn write_pdf(image: &VectorImage, options: &ExportOptions) -> Vec<u8> {
+    let scale = POINTS_PER_INCH / options.dpi;
+    let page_width = image.width as f64 * scale;
+    let page_height = image.height as f64 * scale;
+
+    let content = content_stream(image, scale);
+
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Contents 4 0 R /Resources << >> >>",
+            format_number(page_width),
+            format_number(page_height)
+        ),
+        format!("<< /Length {} >>\nstream\n{}\nendstream", content.len(), content),
+    ];
+
+    // Header, numbered objects, then the cross-reference table pointing at each object
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        let _ = write!(pdf, "{} 0 obj\n{}\nendobj\n", index + 1, object);
+    }
+
+    let xref_offset = pdf.len();
+    let _ = write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(pdf, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(
+        pdf,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref_offset
+    );
+
+    pdf.into_bytes()
+}
+
+// Drawing operators for every path, in image pixel coordinates
+fn content_stream(image: &VectorImage, scale: f64) -> String {
+    let mut content = String::new();
+
+    // Scale pixels to points and flip the y axis, since PDF y points up. The matrix keeps full
+    // precision: rounded like the coordinates, it would stretch the artwork off the MediaBox.
+    let _ = writeln!(content, "{} 0 0 {} 0 {} cm", scale, -scale, image.height as f64 * scale);
+
+    for path in image.paths.iter().filter(|path| !path.subpaths.is_empty()) {
+        let [r, g, b] = path.color;
+        let _ = writeln!(
+            content,
+            "{} {} {} {}",
+            format_number(r as f64 / 255.0),
+            format_number(g as f64 / 255.0),
+            format_number(b as f64 / 255.0),
+            if path.stroke_width.is_some() { "RG" } else { "rg" }
+        );
+        if let Some(width) = path.stroke_width {
+            // Round caps and joins, as in the SVG
+            let _ = writeln!(content, "{} w 1 J 1 j", format_number(width));
+        }
+
+        for subpath in &path.subpaths {
+            let _ = writeln!(content, "{} {} m", format_number(subpath.start.x), format_number(subpath.start.y));
+            for segment in &subpath.segments {
+                match segment {
+                    Segment::Line { to } => {
+                        let _ = writeln!(content, "{} {} l", format_number(to.x), format_number(to.y));
+                    }
+                    Segment::Cubic { c1, c2, to } => {
+                        let _ = writeln!(
+                            content,
+                            "{} {} {} {} {} {} c",
+                            format_number(c1.x),
+                            format_number(c1.y),
+                            format_number(c2.x),
+                            format_number(c2.y),
+                            format_number(to.x),
+                            format_number(to.y)
+                        );
+                    }
+                }
+            }
+            if subpath.closed {
+                content.push_str("h\n");
+            }
+        }
+
+        // `f` fills by the non-zero winding rule, so holes need their own winding as in the SVG
+        if path.stroke_width.is_some() {
+            content.push_str("S\n");
+        } else {
+            content.push_str("f\n");
+        }
+    }
+
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Point, Subpath, VectorPath};
+
+    fn image(width: u32, height: u32) -> VectorImage {
+        let outline = Subpath {
+            start: Point::new(0.0, 0.0),
+            segments: vec![
+                Segment::Line { to: Point::new(width as f64, 0.0) },
+                Segment::Cubic {
+                    c1: Point::new(width as f64, height as f64 / 2.0),
+                    c2: Point::new(width as f64 / 2.0, height as f64),
+                    to: Point::new(0.0, height as f64),
+                },
+            ],
+            closed: true,
+        };
+
+        VectorImage {
+            width,
+            height,
+            paths: vec![VectorPath { color: [255, 0, 0], subpaths: vec![outline], stroke_width: None }],
+            pixel_grid: false,
+        }
+    }
+
+    fn numbers(text: &str) -> Vec<f64> {
+        text.split_whitespace().map(|value| value.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn cross_reference_table_points_at_every_object() {
+        let pdf = String::from_utf8(write_pdf(&image(40, 30), &ExportOptions::default())).unwrap();
+
+        let startxref: usize = pdf.split("startxref\n").nth(1).unwrap().lines().next().unwrap().parse().unwrap();
+        assert!(pdf[startxref..].starts_with("xref\n0 5\n"));
+
+        let entries: Vec<&str> = pdf[startxref..].lines().skip(3).take(4).collect();
+        for (index, entry) in entries.iter().enumerate() {
+            assert!(entry.ends_with(" 00000 n "), "{:?}", entry);
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj\n", index + 1)), "object {}", index + 1);
+        }
+
+        // The stream length covers exactly the content between the stream keywords
+        let length: usize = pdf.split("/Length ").nth(1).unwrap().split(' ').next().unwrap().parse().unwrap();
+        let start = pdf.find(">>\nstream\n").unwrap() + ">>\nstream\n".len();
+        assert_eq!(&pdf[start + length..start + length + 10], "\nendstream");
+    }
+
+    #[test]
+    fn artwork_fills_the_media_box_at_any_dpi() {
+        for (dpi, width, height) in [(96.0, 40, 30), (350.0, 350, 700), (300.0, 1234, 567)] {
+            let options = ExportOptions { dpi, ..ExportOptions::default() };
+            let pdf = String::from_utf8(write_pdf(&image(width, height), &options)).unwrap();
+
+            let media_box = numbers(pdf.split("/MediaBox [").nth(1).unwrap().split(']').next().unwrap());
+            let (page_width, page_height) = (width as f64 * 72.0 / dpi, height as f64 * 72.0 / dpi);
+            assert_eq!(media_box[..2], [0.0, 0.0]);
+            assert!((media_box[2] - page_width).abs() < 1e-3, "{} at {} dpi", media_box[2], dpi);
+            assert!((media_box[3] - page_height).abs() < 1e-3, "{} at {} dpi", media_box[3], dpi);
+
+            // The image corners land on the page corners
+            let matrix = numbers(pdf.lines().find(|line| line.ends_with(" cm")).unwrap().trim_end_matches(" cm"));
+            let (a, d, f) = (matrix[0], matrix[3], matrix[5]);
+            assert!((a * width as f64 - page_width).abs() < 1e-9, "{} dpi", dpi);
+            assert!((f - page_height).abs() < 1e-9, "{} dpi", dpi);
+            assert!((d * height as f64 + f).abs() < 1e-9, "{} dpi", dpi);
+        }
+    }
+}
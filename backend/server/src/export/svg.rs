@@ -0,0 +1,70 @@
+// src/export/svg.rs
+use super::format_number;
+use crate::geometry::{Segment, VectorImage};
+use std::fmt::Write;
+
+// Write the traced geometry as SVG, in source pixel units
+pub fn write_svg(image: &VectorImage) -> String {
+    let mut svg = String::new();
+
+    svg.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        svg,
+        "<svg version=\"1.1\" xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\"{}>",
+        image.width,
+        image.height,
+        image.width,
+        image.height,
+        if image.pixel_grid { " shape-rendering=\"crispEdges\"" } else { "" }
+    );
+
+    for path in image.paths.iter().filter(|path| !path.subpaths.is_empty()) {
+        let mut data = String::new();
+        for subpath in &path.subpaths {
+            let _ = write!(data, "M{},{} ", format_number(subpath.start.x), format_number(subpath.start.y));
+            for segment in &subpath.segments {
+                match segment {
+                    Segment::Line { to } => {
+                        let _ = write!(data, "L{},{} ", format_number(to.x), format_number(to.y));
+                    }
+                    Segment::Cubic { c1, c2, to } => {
+                        let _ = write!(
+                            data,
+                            "C{},{} {},{} {},{} ",
+                            format_number(c1.x),
+                            format_number(c1.y),
+                            format_number(c2.x),
+                            format_number(c2.y),
+                            format_number(to.x),
+                            format_number(to.y)
+                        );
+                    }
+                }
+            }
+            if subpath.closed {
+                data.push_str("Z ");
+            }
+        }
+
+        let [r, g, b] = path.color;
+        match path.stroke_width {
+            Some(width) => {
+                let _ = writeln!(
+                    svg,
+                    "<path d=\"{}\" stroke=\"#{:02x}{:02x}{:02x}\" stroke-width=\"{}\" stroke-linecap=\"round\" stroke-linejoin=\"round\" fill=\"none\"/>",
+                    data.trim_end(),
+                    r,
+                    g,
+                    b,
+                    format_number(width)
+                );
+            }
+            None => {
+                let _ = writeln!(svg, "<path d=\"{}\" fill=\"#{:02x}{:02x}{:02x}\"/>", data.trim_end(), r, g, b);
+            }
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
@@ -0,0 +1,300 @@
+// src/geometry.rs
+use crate::blob::{BlobError, BLOB_STORE};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// Traced output as plain geometry, kept next to the SVG so other formats can be written later
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Segment {
+    Line { to: Point },
+    Cubic { c1: Point, c2: Point, to: Point },
+}
+
+impl Segment {
+    pub fn end(&self) -> Point {
+        match self {
+            Segment::Line { to } | Segment::Cubic { to, .. } => *to,
+        }
+    }
+}
+
+// One continuous outline starting at `start`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subpath {
+    pub start: Point,
+    pub segments: Vec<Segment>,
+    pub closed: bool,
+}
+
+// A filled shape of a single color; holes are subpaths wound the other way.
+// Paths with a stroke width are drawn as lines of that width instead of being filled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VectorPath {
+    pub color: [u8; 3],
+    pub subpaths: Vec<Subpath>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stroke_width: Option<f64>,
+}
+
+// Everything traced from one image, in source pixel coordinates with y pointing down
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VectorImage {
+    pub width: u32,
+    pub height: u32,
+    pub paths: Vec<VectorPath>,
+    // Every edge lies on the pixel grid, so renderers should not antialias them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pixel_grid: bool,
+}
+
+impl VectorImage {
+    // The geometry as it is stored, as JSON
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Paths only hold numbers and colors, which always serialize
+        serde_json::to_vec(self).unwrap()
+    }
+
+    pub fn load(key: &str) -> Result<Self, BlobError> {
+        let data = BLOB_STORE.get(key)?;
+        serde_json::from_slice(&data).map_err(|e| BlobError::Corrupt(key.to_string(), e.to_string()))
+    }
+
+    // The same geometry stretched to a `width` x `height` image, e.g. to show a trace of a downscaled copy at full size
+    pub fn scaled_to(&self, width: u32, height: u32) -> VectorImage {
+        let sx = width as f64 / self.width.max(1) as f64;
+        let sy = height as f64 / self.height.max(1) as f64;
+        let scale = |point: &Point| Point::new(point.x * sx, point.y * sy);
+
+        VectorImage {
+            width,
+            height,
+            paths: self
+                .paths
+                .iter()
+                .map(|path| VectorPath {
+                    color: path.color,
+                    subpaths: path
+                        .subpaths
+                        .iter()
+                        .map(|subpath| Subpath {
+                            start: scale(&subpath.start),
+                            segments: subpath
+                                .segments
+                                .iter()
+                                .map(|segment| match segment {
+                                    Segment::Line { to } => Segment::Line { to: scale(to) },
+                                    Segment::Cubic { c1, c2, to } => Segment::Cubic {
+                                        c1: scale(c1),
+                                        c2: scale(c2),
+                                        to: scale(to),
+                                    },
+                                })
+                                .collect(),
+                            closed: subpath.closed,
+                        })
+                        .collect(),
+                    stroke_width: path.stroke_width.map(|stroke_width| stroke_width * (sx * sy).sqrt()),
+                })
+                .collect(),
+            pixel_grid: self.pixel_grid,
+        }
+    }
+
+    // Distinct colors of the paths that draw anything, in drawing order
+    pub fn palette(&self) -> Vec<[u8; 3]> {
+        let mut palette: Vec<[u8; 3]> = Vec::new();
+        for path in self.paths.iter().filter(|path| !path.subpaths.is_empty()) {
+            if !palette.contains(&path.color) {
+                palette.push(path.color);
+            }
+        }
+        palette
+    }
+}
+
+// Key the geometry for an SVG output is stored under
+pub fn geometry_key(output_key: &str) -> String {
+    Path::new(output_key).with_extension("json").to_string_lossy().to_string()
+}
+
+impl Subpath {
+    // The outline as straight lines, with curves split until within `tolerance` pixels
+    pub fn flatten(&self, tolerance: f64) -> Vec<Point> {
+        let mut points = vec![self.start];
+        let mut current = self.start;
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Line { to } => {
+                    points.push(*to);
+                    current = *to;
+                }
+                Segment::Cubic { c1, c2, to } => {
+                    points.extend(flatten_cubic(current, *c1, *c2, *to, tolerance));
+                    current = *to;
+                }
+            }
+        }
+
+        if self.closed && points.first() != points.last() {
+            points.push(self.start);
+        }
+
+        points
+    }
+
+    pub fn has_curves(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Cubic { .. }))
+    }
+}
+
+// Split a cubic Bézier into points no further than `tolerance` from the curve
+pub fn flatten_cubic(from: Point, c1: Point, c2: Point, to: Point, tolerance: f64) -> Vec<Point> {
+    // The second differences of the control points bound how far a chord can stray
+    let dd1 = (from.x - 2.0 * c1.x + c2.x).hypot(from.y - 2.0 * c1.y + c2.y);
+    let dd2 = (c1.x - 2.0 * c2.x + to.x).hypot(c1.y - 2.0 * c2.y + to.y);
+    let bound = 0.75 * dd1.max(dd2);
+    let steps = ((bound / tolerance.max(1e-6)).sqrt().ceil() as usize).clamp(1, 1024);
+
+    (1..=steps)
+        .map(|step| {
+            let t = step as f64 / steps as f64;
+            let mt = 1.0 - t;
+            let a = mt * mt * mt;
+            let b = 3.0 * mt * mt * t;
+            let c = 3.0 * mt * t * t;
+            let d = t * t * t;
+            Point::new(
+                a * from.x + b * c1.x + c * c2.x + d * to.x,
+                a * from.y + b * c1.y + c * c2.y + d * to.y,
+            )
+        })
+        .collect()
+}
+
+// Ramer-Douglas-Peucker simplification: drop points closer than `epsilon` to the simplified line
+pub fn simplify_polyline(points: &[Point], closed: bool, epsilon: f64) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    // A loop is simplified as a line that returns to its start
+    let mut line = points.to_vec();
+    if closed {
+        line.push(points[0]);
+    }
+
+    let mut keep = vec![false; line.len()];
+    keep[0] = true;
+    keep[line.len() - 1] = true;
+
+    let mut ranges = vec![(0, line.len() - 1)];
+    while let Some((first, last)) = ranges.pop() {
+        let mut farthest = (0, 0.0);
+        for index in first + 1..last {
+            let distance = distance_to_segment(line[index], line[first], line[last]);
+            if distance > farthest.1 {
+                farthest = (index, distance);
+            }
+        }
+
+        if farthest.1 > epsilon {
+            keep[farthest.0] = true;
+            ranges.push((first, farthest.0));
+            ranges.push((farthest.0, last));
+        }
+    }
+
+    let mut simplified: Vec<Point> = line
+        .into_iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(point, _)| point)
+        .collect();
+    if closed {
+        simplified.pop();
+    }
+    simplified
+}
+
+fn distance_to_segment(point: Point, a: Point, b: Point) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length_squared = dx * dx + dy * dy;
+    if length_squared == 0.0 {
+        return (point.x - a.x).hypot(point.y - a.y);
+    }
+
+    let t = (((point.x - a.x) * dx + (point.y - a.y) * dy) / length_squared).clamp(0.0, 1.0);
+    (point.x - (a.x + t * dx)).hypot(point.y - (a.y + t * dy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattened_curves_stay_within_tolerance() {
+        let (from, c1, c2, to) = (Point::new(0.0, 0.0), Point::new(0.0, 10.0), Point::new(10.0, 10.0), Point::new(10.0, 0.0));
+
+        for tolerance in [1.0, 0.1, 0.01] {
+            let points = flatten_cubic(from, c1, c2, to, tolerance);
+            assert_eq!(points.last(), Some(&to));
+
+            // Every chord midpoint lies close to the curve, whose top is at y = 7.5
+            let mut previous = from;
+            for point in &points {
+                let middle = Point::new((previous.x + point.x) / 2.0, (previous.y + point.y) / 2.0);
+                assert!(middle.y <= 7.5 + tolerance, "{:?} at {}", middle, tolerance);
+                previous = *point;
+            }
+            let top = points.iter().map(|point| point.y).fold(0.0, f64::max);
+            assert!(7.5 - top <= tolerance, "top {} at {}", top, tolerance);
+        }
+    }
+
+    #[test]
+    fn closed_outlines_flatten_back_to_their_start() {
+        let mut triangle = Subpath {
+            start: Point::new(0.0, 0.0),
+            segments: vec![Segment::Line { to: Point::new(4.0, 0.0) }, Segment::Line { to: Point::new(0.0, 3.0) }],
+            closed: true,
+        };
+        assert_eq!(triangle.flatten(0.1).len(), 4);
+        assert_eq!(triangle.flatten(0.1).last(), Some(&triangle.start));
+        assert!(!triangle.has_curves());
+
+        triangle.closed = false;
+        assert_eq!(triangle.flatten(0.1).len(), 3);
+    }
+
+    #[test]
+    fn simplification_drops_points_near_the_line() {
+        let points: Vec<Point> = [(0.0, 0.0), (5.0, 0.2), (10.0, 0.0), (10.0, 10.0), (5.0, 9.0), (0.0, 10.0)]
+            .iter()
+            .map(|&(x, y)| Point::new(x, y))
+            .collect();
+
+        let open = simplify_polyline(&points, false, 0.5);
+        assert_eq!(open, [points[0], points[2], points[3], points[4], points[5]]);
+
+        // Closing the loop does not drop its start, and larger tolerances drop more
+        let closed = simplify_polyline(&points, true, 1.5);
+        assert_eq!(closed, [points[0], points[2], points[3], points[5]]);
+    }
+}
@@ -1,18 +1,39 @@
 // src/handlers.rs
-use actix_multipart::Multipart;
-use actix_web::{get, post, web, Error, HttpResponse, Responder};
+use actix_multipart::{Field, Multipart};
+use actix_web::{delete, get, post, web, Error, HttpResponse, Responder};
+use futures::future::join_all;
 use futures::{StreamExt, TryStreamExt};
+use chrono::{DateTime, Utc};
 use log::{error, info};
 use serde_json::json;
-use std::fs;
-use std::io::Write;
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Write};
 use std::path::Path;
-use uuid::Uuid;
+use tokio::sync::broadcast::error::RecvError;
 use zip::write::FileOptions;
 
-use crate::models::{BatchDownloadRequest, ConversionResponse, ConversionSettings, ErrorResponse, HealthResponse};
-use crate::utils::{generate_file_id, get_file_metadata, store_file_metadata, OUTPUT_DIR, UPLOAD_DIR};
-use crate::vectorizer::convert_image_to_svg;
+use crate::analysis;
+use crate::blob::{BlobError, BLOB_STORE};
+use crate::cache;
+use crate::config::config;
+use crate::export::{dxf, eps, gcode, hpgl, pdf, ExportFormat, ExportOptions};
+use crate::geometry::{geometry_key, VectorImage};
+use crate::jobs::{get_job, run_on_worker, submit_job, subscribe_to_job};
+use crate::models::{
+    BatchConversionResponse, BatchConversionResult, BatchDownloadRequest, ConversionResponse,
+    DownloadQuery, ErrorResponse, FieldError, HealthResponse, JobResponse, JobStatus, JobStatusResponse, MetricsResponse, PreviewResponse,
+    RetentionQuery, ValidatedSettings,
+};
+use crate::preview::render_preview;
+use crate::utils::{
+    converted_file_metadata, default_retention_secs, format_hex_color, generate_file_id, get_file_metadata,
+    record_file_revision, remove_file_and_outputs, reserve_file_revision, revision_output_path, store_file_metadata,
+    upload_dir,
+};
+use crate::validation::{
+    parse_settings, settings_to_request, validate_download_query, validate_retention_query, validate_settings,
+};
+use crate::vectorizer::{convert_image_to_svg, decode_image, ConversionOutput, VectorizerError};
 
 #[get("/api/health")]
 pub async fn health_check() -> impl Responder {
@@ -24,15 +45,140 @@ pub async fn health_check() -> impl Responder {
     HttpResponse::Ok().json(response)
 }
 
-#[post("/api/convert")]
-pub async fn convert_image(mut payload: Multipart) -> Result<HttpResponse, Error> {
-    // Generate a unique file ID
-    let file_id = generate_file_id();
+#[get("/api/metrics")]
+pub async fn metrics() -> impl Responder {
+    let response = MetricsResponse {
+        result_cache: cache::stats(),
+    };
+    
+    HttpResponse::Ok().json(response)
+}
+
+// Uploaded image and settings read from a conversion request
+struct ConversionUpload {
+    file_name: String,
+    file_path: String,
+    settings: Result<ValidatedSettings, Vec<FieldError>>,
+    // Set when the image was sent but could not be stored
+    upload_error: Option<UploadError>,
+}
+
+// Why an uploaded image was not stored
+enum UploadError {
+    // Over limits.max_upload_bytes
+    TooLarge,
+    // The multipart stream broke off
+    Interrupted(String),
+    Storage(BlobError),
+}
+
+// Key an uploaded image is stored under, keeping the extension of its original name
+fn upload_path(file_id: &str, original_name: &str) -> String {
+    let extension = Path::new(original_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("png");
     
+    format!("{}/{}.{}", upload_dir(), file_id, extension)
+}
+
+// Store the content of an "image" field in the blob store
+async fn save_image_field(field: &mut Field, file_path: &str) -> Result<(), UploadError> {
+    let max_bytes = config().limits.max_upload_bytes;
+    let mut data = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk.map_err(|e| UploadError::Interrupted(e.to_string()))?;
+        if data.len() + chunk.len() > max_bytes {
+            // Read the rest so the following fields can still be parsed
+            while field.next().await.is_some() {}
+            return Err(UploadError::TooLarge);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    
+    let key = file_path.to_string();
+    on_blob_store(move || BLOB_STORE.put(&key, &data))
+        .await
+        .map_err(UploadError::Storage)
+}
+
+// Run a blob store call off the async runtime threads, since S3 requests block
+async fn on_blob_store<T, F>(call: F) -> Result<T, BlobError>
+where
+    F: FnOnce() -> Result<T, BlobError> + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(call)
+        .await
+        .unwrap_or_else(|e| Err(BlobError::Backend(e.to_string())))
+}
+
+// Whether a blob is stored, counting one that can't be checked as missing
+async fn blob_exists(key: &str) -> bool {
+    let key = key.to_string();
+    on_blob_store(move || BLOB_STORE.exists(&key)).await.unwrap_or(false)
+}
+
+// Delete an upload that nothing will refer to, e.g. a "rejected" or "interrupted" one
+async fn delete_upload(file_path: &str, reason: &str) {
+    let key = file_path.to_string();
+    if let Err(e) = on_blob_store(move || BLOB_STORE.delete(&key)).await {
+        error!("Failed to delete {} upload {}: {}", reason, file_path, e);
+    }
+}
+
+// Error body for an image that was not stored
+fn upload_error_body(upload_error: UploadError) -> ErrorResponse {
+    match upload_error {
+        UploadError::TooLarge => ErrorResponse {
+            error: "File too large".to_string(),
+            details: Some(format!("Images are limited to {} bytes", config().limits.max_upload_bytes)),
+            fields: Vec::new(),
+        },
+        UploadError::Interrupted(details) => ErrorResponse {
+            error: "Upload interrupted".to_string(),
+            details: Some(details),
+            fields: Vec::new(),
+        },
+        UploadError::Storage(e) => ErrorResponse {
+            error: "Failed to store upload".to_string(),
+            details: Some(e.to_string()),
+            fields: Vec::new(),
+        },
+    }
+}
+
+// 400 when no image was sent or the stream broke off, 413 when it was too large, 500 when it could not be stored
+fn missing_upload_response(upload_error: Option<UploadError>) -> HttpResponse {
+    match upload_error {
+        Some(UploadError::TooLarge) => HttpResponse::PayloadTooLarge().json(upload_error_body(UploadError::TooLarge)),
+        Some(e @ UploadError::Interrupted(_)) => HttpResponse::BadRequest().json(upload_error_body(e)),
+        Some(e) => HttpResponse::InternalServerError().json(upload_error_body(e)),
+        None => HttpResponse::BadRequest().json(ErrorResponse {
+            error: "No file uploaded".to_string(),
+            details: None,
+            fields: Vec::new(),
+        }),
+    }
+}
+
+// Read a text field such as the settings JSON
+async fn read_text_field(field: &mut Field) -> Result<String, UploadError> {
+    let mut text = String::new();
+    while let Some(chunk) = field.next().await {
+        let data = chunk.map_err(|e| UploadError::Interrupted(e.to_string()))?;
+        text.push_str(std::str::from_utf8(&data).unwrap_or(""));
+    }
+    Ok(text)
+}
+
+// Read the "image" and "settings" fields of a conversion request
+async fn read_conversion_upload(payload: &mut Multipart, file_id: &str) -> ConversionUpload {
     // Default settings
-    let mut settings = ConversionSettings::default();
+    let mut settings = validate_settings(&config().conversion.defaults);
     let mut file_name = String::new();
     let mut file_path = String::new();
+    let mut upload_error = None;
     
     // Process multipart form data
     while let Ok(Some(mut field)) = payload.try_next().await {
@@ -42,52 +188,39 @@ pub async fn convert_image(mut payload: Multipart) -> Result<HttpResponse, Error
         match field_name {
             "image" => {
                 // Get original filename
-                let original_name = content_disposition
+                file_name = content_disposition
                     .get_filename()
                     .unwrap_or("unknown.png")
                     .to_string();
                 
-                file_name = original_name.clone();
-                
-                // Create file path
-                let file_id_str = file_id.clone();
-                let extension = Path::new(&original_name)
-                    .extension()
-                    .and_then(|ext| ext.to_str())
-                    .unwrap_or("png");
-                
-                file_path = format!("{}/{}.{}", UPLOAD_DIR, file_id_str, extension);
-                
-                // Create file
-                let mut file = web::block(|| std::fs::File::create(&file_path))
-                    .await
-                    .unwrap();
-                
-                // Write file content
-                while let Some(chunk) = field.next().await {
-                    let data = chunk.unwrap();
-                    file = web::block(move || file.write_all(&data).map(|_| file))
-                        .await
-                        .unwrap();
+                let key = upload_path(file_id, &file_name);
+                match save_image_field(&mut field, &key).await {
+                    Ok(()) => file_path = key,
+                    Err(e) => {
+                        if let UploadError::Storage(e) = &e {
+                            error!("Failed to store upload {}: {}", key, e);
+                        }
+                        upload_error = Some(e);
+                    }
                 }
             }
             "settings" => {
                 // Parse settings JSON
-                let mut settings_str = String::new();
-                while let Some(chunk) = field.next().await {
-                    let data = chunk.unwrap();
-                    settings_str.push_str(std::str::from_utf8(&data).unwrap_or(""));
-                }
-                
-                if !settings_str.is_empty() {
-                    match serde_json::from_str::<ConversionSettings>(&settings_str) {
-                        Ok(parsed_settings) => {
-                            settings = parsed_settings;
-                        }
-                        Err(e) => {
-                            error!("Failed to parse settings: {}", e);
+                let settings_str = match read_text_field(&mut field).await {
+                    Ok(settings_str) => settings_str,
+                    Err(e) => {
+                        // The request is incomplete; drop an image that was already stored
+                        if !file_path.is_empty() {
+                            delete_upload(&file_path, "interrupted").await;
+                            file_path.clear();
                         }
+                        upload_error = Some(e);
+                        break;
                     }
+                };
+                
+                if !settings_str.is_empty() {
+                    settings = parse_settings(&settings_str);
                 }
             }
             _ => {
@@ -97,64 +230,821 @@ pub async fn convert_image(mut payload: Multipart) -> Result<HttpResponse, Error
         }
     }
     
+    ConversionUpload {
+        file_name,
+        file_path,
+        settings,
+        upload_error,
+    }
+}
+
+// 422 response listing every invalid field
+fn invalid_fields_response(message: &str, errors: Vec<FieldError>) -> HttpResponse {
+    HttpResponse::UnprocessableEntity().json(ErrorResponse {
+        error: message.to_string(),
+        details: Some(format!("{} invalid field(s)", errors.len())),
+        fields: errors,
+    })
+}
+
+#[post("/api/convert")]
+pub async fn convert_image(query: web::Query<RetentionQuery>, mut payload: Multipart) -> Result<HttpResponse, Error> {
+    let retention_secs = match validate_retention_query(&query) {
+        Ok(retention_secs) => retention_secs.unwrap_or_else(default_retention_secs),
+        Err(errors) => return Ok(invalid_fields_response("Invalid retention", errors)),
+    };
+    
+    // Generate a unique file ID
+    let file_id = generate_file_id();
+    
+    let ConversionUpload { file_name, file_path, settings, upload_error } =
+        read_conversion_upload(&mut payload, &file_id).await;
+    
     // Check if we have a file
     if file_path.is_empty() {
-        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
-            error: "No file uploaded".to_string(),
-            details: None,
-        }));
+        return Ok(missing_upload_response(upload_error));
     }
     
+    // Reject invalid settings before doing any work
+    let settings = match settings {
+        Ok(settings) => settings,
+        Err(errors) => {
+            // Nothing will be converted, so drop the upload straight away
+            delete_upload(&file_path, "rejected").await;
+            return Ok(invalid_fields_response("Invalid settings", errors));
+        }
+    };
+    
     // Create output path
-    let output_path = format!("{}/{}.svg", OUTPUT_DIR, file_id);
+    let output_path = revision_output_path(&file_id, 1);
     
     // Store file metadata
-    store_file_metadata(&file_id, &file_name, &file_path, &output_path);
+    store_file_metadata(&file_id, &file_name, &file_path, &output_path, retention_secs);
     
     // Convert image to SVG
-    match convert_image_to_svg(
-        Path::new(&file_path),
-        Path::new(&output_path),
-        &settings,
-    ) {
-        Ok(svg_data) => {
-            // Return success response
-            let response = ConversionResponse {
-                file_id: file_id.clone(),
-                svg_data,
-                message: "Conversion successful".to_string(),
-            };
-            
-            Ok(HttpResponse::Ok().json(response))
+    match convert_on_worker(&file_path, &output_path, &settings).await {
+        Ok(output) => {
+            let expires_at = converted_expiry(&file_id, &output_path).await;
+            Ok(HttpResponse::Ok().json(conversion_response(&file_id, 1, &settings, output, expires_at)))
         }
         Err(e) => {
             error!("Conversion error: {}", e);
             
             // Return error response
-            Ok(HttpResponse::InternalServerError().json(ErrorResponse {
-                error: "Conversion failed".to_string(),
+            Ok(conversion_error_response(e))
+        }
+    }
+}
+
+// Why a conversion produced no output
+enum ConversionError {
+    // Settings the image turned out not to suit
+    InvalidSettings(Vec<FieldError>),
+    Failed(String),
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::InvalidSettings(errors) => write!(f, "{} invalid field(s)", errors.len()),
+            ConversionError::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+// Convert on the worker pool shared with queued jobs, within the conversion timeout
+async fn convert_on_worker(
+    input_key: &str,
+    output_key: &str,
+    settings: &ValidatedSettings,
+) -> Result<ConversionOutput, ConversionError> {
+    let (input_key, output_key, settings) = (input_key.to_string(), output_key.to_string(), settings.clone());
+    
+    match run_on_worker(move || convert_image_to_svg(&input_key, &output_key, &settings)).await {
+        Ok(Ok(output)) => Ok(output),
+        Ok(Err(VectorizerError::InvalidSettings(errors))) => Err(ConversionError::InvalidSettings(errors)),
+        Ok(Err(e)) => Err(ConversionError::Failed(e.to_string())),
+        Err(e) => Err(ConversionError::Failed(e)),
+    }
+}
+
+// Error body for a conversion that produced no output
+fn conversion_error_body(e: ConversionError) -> ErrorResponse {
+    match e {
+        ConversionError::InvalidSettings(errors) => ErrorResponse {
+            error: "Invalid settings".to_string(),
+            details: Some(format!("{} invalid field(s)", errors.len())),
+            fields: errors,
+        },
+        ConversionError::Failed(e) => ErrorResponse {
+            error: "Conversion failed".to_string(),
+            details: Some(e),
+            fields: Vec::new(),
+        },
+    }
+}
+
+// 422 for settings the image does not suit, 500 for anything else
+fn conversion_error_response(e: ConversionError) -> HttpResponse {
+    match e {
+        ConversionError::InvalidSettings(errors) => invalid_fields_response("Invalid settings", errors),
+        e => HttpResponse::InternalServerError().json(conversion_error_body(e)),
+    }
+}
+
+// Settings sent as a JSON body on their own; an empty body uses the defaults
+fn settings_from_body(body: &str) -> Result<ValidatedSettings, Vec<FieldError>> {
+    if body.trim().is_empty() {
+        validate_settings(&config().conversion.defaults)
+    } else {
+        parse_settings(body)
+    }
+}
+
+// When a file whose first conversion just finished expires; deleted in the meantime, it already has
+async fn converted_expiry(file_id: &str, output_path: &str) -> DateTime<Utc> {
+    let (file_id, output_path) = (file_id.to_string(), output_path.to_string());
+    web::block(move || converted_file_metadata(&file_id, &output_path))
+        .await
+        .ok()
+        .flatten()
+        .map(|metadata| metadata.expires_at())
+        .unwrap_or_else(Utc::now)
+}
+
+// Response for a finished conversion
+fn conversion_response(
+    file_id: &str,
+    revision: u32,
+    settings: &ValidatedSettings,
+    output: ConversionOutput,
+    expires_at: DateTime<Utc>,
+) -> ConversionResponse {
+    ConversionResponse {
+        file_id: file_id.to_string(),
+        svg_data: output.svg_data,
+        palette: output.palette.into_iter().map(format_hex_color).collect(),
+        quality: output.quality,
+        settings: settings
+            .auto_budget_ms
+            .map(|_| settings_to_request(&output.settings)),
+        revision,
+        cached: output.cached,
+        expires_at,
+        message: "Conversion successful".to_string(),
+    }
+}
+
+// An image of a batch conversion, stored under its own file ID
+struct BatchUpload {
+    file_id: String,
+    file_name: String,
+    file_path: String,
+    // Set when the image could not be stored
+    upload_error: Option<UploadError>,
+}
+
+// Index of a per-file settings field, named "settings[N]" after the Nth image
+fn override_index(field_name: &str) -> Option<usize> {
+    field_name
+        .strip_prefix("settings[")
+        .and_then(|rest| rest.strip_suffix(']'))
+        .and_then(|index| index.parse().ok())
+}
+
+// Settings for one image of a batch: the fields of its override replace the shared ones
+fn batch_file_settings(
+    shared_json: Option<&str>,
+    shared: &ValidatedSettings,
+    override_json: Option<&str>,
+) -> Result<ValidatedSettings, Vec<FieldError>> {
+    let override_json = match override_json {
+        Some(json) => json,
+        None => return Ok(shared.clone()),
+    };
+    
+    match (
+        shared_json.map(serde_json::from_str::<serde_json::Value>),
+        serde_json::from_str::<serde_json::Value>(override_json),
+    ) {
+        (Some(Ok(serde_json::Value::Object(mut fields))), Ok(serde_json::Value::Object(overrides))) => {
+            fields.extend(overrides);
+            parse_settings(&serde_json::Value::Object(fields).to_string())
+        }
+        // Without shared settings, or when the override is not an object, it is parsed on its own
+        _ => parse_settings(override_json),
+    }
+}
+
+// Why a batch is turned away before any of it is converted
+#[derive(Debug)]
+enum BatchRejection {
+    NoFiles,
+    // More images than max_batch_files
+    TooManyFiles,
+    // Invalid shared settings, or overrides for images that weren't uploaded
+    InvalidSettings(Vec<FieldError>),
+}
+
+// The settings of each image in a batch, or the errors that reject that image alone
+type BatchFileSettings = Vec<Result<ValidatedSettings, Vec<FieldError>>>;
+
+// Settings for each of the `uploaded` images of a batch, from the shared settings and the overrides
+// keyed by image index. An image with invalid overrides gets its own errors; the rest still convert.
+fn batch_settings(
+    uploaded: usize,
+    max_files: usize,
+    shared_json: Option<&str>,
+    overrides: &HashMap<usize, String>,
+) -> Result<BatchFileSettings, BatchRejection> {
+    if uploaded == 0 {
+        return Err(BatchRejection::NoFiles);
+    }
+    if uploaded > max_files {
+        return Err(BatchRejection::TooManyFiles);
+    }
+    
+    let shared = match shared_json {
+        Some(json) => parse_settings(json),
+        None => validate_settings(&config().conversion.defaults),
+    };
+    let mut errors = shared.as_ref().err().cloned().unwrap_or_default();
+    let mut stray: Vec<usize> = overrides.keys().copied().filter(|&index| index >= uploaded).collect();
+    stray.sort_unstable();
+    errors.extend(stray.into_iter().map(|index| FieldError {
+        field: format!("settings[{}]", index),
+        code: "out_of_range".to_string(),
+        message: format!("There is no image {} in this batch", index),
+        allowed: Some(format!("settings[0] to settings[{}]", uploaded - 1)),
+    }));
+    let shared = match shared {
+        Ok(shared) if errors.is_empty() => shared,
+        _ => return Err(BatchRejection::InvalidSettings(errors)),
+    };
+    
+    Ok((0..uploaded)
+        .map(|index| batch_file_settings(shared_json, &shared, overrides.get(&index).map(String::as_str)))
+        .collect())
+}
+
+// Delete the uploads of a batch that is rejected as a whole
+async fn discard_uploads(uploads: &[BatchUpload]) {
+    for upload in uploads {
+        delete_upload(&upload.file_path, "rejected").await;
+    }
+}
+
+// 400 for a batch whose multipart stream broke off; nothing of it is converted
+async fn interrupted_batch_response(uploads: &[BatchUpload], upload_error: UploadError) -> HttpResponse {
+    discard_uploads(uploads).await;
+    HttpResponse::BadRequest().json(upload_error_body(upload_error))
+}
+
+#[post("/api/convert-batch")]
+pub async fn convert_batch(query: web::Query<RetentionQuery>, mut payload: Multipart) -> Result<HttpResponse, Error> {
+    // Every image of the batch is kept for the same time
+    let retention_secs = match validate_retention_query(&query) {
+        Ok(retention_secs) => retention_secs.unwrap_or_else(default_retention_secs),
+        Err(errors) => return Ok(invalid_fields_response("Invalid retention", errors)),
+    };
+    
+    let max_files = config().limits.max_batch_files;
+    let mut uploads: Vec<BatchUpload> = Vec::new();
+    let mut shared_json: Option<String> = None;
+    let mut overrides: HashMap<usize, String> = HashMap::new();
+    let mut extra_files = 0;
+    
+    // Process multipart form data
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let content_disposition = field.content_disposition();
+        let field_name = content_disposition.get_name().unwrap_or("").to_string();
+        
+        match field_name.as_str() {
+            "image" if uploads.len() < max_files => {
+                let file_id = generate_file_id();
+                let file_name = content_disposition
+                    .get_filename()
+                    .unwrap_or("unknown.png")
+                    .to_string();
+                let file_path = upload_path(&file_id, &file_name);
+                let upload_error = match save_image_field(&mut field, &file_path).await {
+                    Ok(()) => None,
+                    Err(e) => {
+                        if let UploadError::Storage(e) = &e {
+                            error!("Failed to store upload {}: {}", file_path, e);
+                        }
+                        Some(e)
+                    }
+                };
+                
+                uploads.push(BatchUpload {
+                    file_id,
+                    file_name,
+                    file_path,
+                    upload_error,
+                });
+            }
+            "image" => {
+                // Counted but not stored; the batch is rejected below
+                extra_files += 1;
+                while field.next().await.is_some() {}
+            }
+            "settings" => {
+                let settings_str = match read_text_field(&mut field).await {
+                    Ok(settings_str) => settings_str,
+                    Err(e) => return Ok(interrupted_batch_response(&uploads, e).await),
+                };
+                if !settings_str.is_empty() {
+                    shared_json = Some(settings_str);
+                }
+            }
+            name => match override_index(name) {
+                Some(index) => {
+                    let settings_str = match read_text_field(&mut field).await {
+                        Ok(settings_str) => settings_str,
+                        Err(e) => return Ok(interrupted_batch_response(&uploads, e).await),
+                    };
+                    if !settings_str.is_empty() {
+                        overrides.insert(index, settings_str);
+                    }
+                }
+                None => {
+                    // Skip other fields
+                    while field.next().await.is_some() {}
+                }
+            },
+        }
+    }
+    
+    // Shared settings and overrides for images that weren't uploaded reject the whole batch
+    let uploaded = uploads.len() + extra_files;
+    let file_settings = match batch_settings(uploaded, max_files, shared_json.as_deref(), &overrides) {
+        Ok(file_settings) => file_settings,
+        Err(rejection) => {
+            discard_uploads(&uploads).await;
+            return Ok(match rejection {
+                BatchRejection::NoFiles => HttpResponse::BadRequest().json(ErrorResponse {
+                    error: "No file uploaded".to_string(),
+                    details: None,
+                    fields: Vec::new(),
+                }),
+                BatchRejection::TooManyFiles => HttpResponse::PayloadTooLarge().json(ErrorResponse {
+                    error: "Too many files".to_string(),
+                    details: Some(format!(
+                        "{} images uploaded, at most {} are converted per batch",
+                        uploaded, max_files
+                    )),
+                    fields: Vec::new(),
+                }),
+                BatchRejection::InvalidSettings(errors) => invalid_fields_response("Invalid settings", errors),
+            });
+        }
+    };
+    
+    info!("Converting a batch of {} images", uploads.len());
+    
+    let mut results: Vec<BatchConversionResult> = Vec::with_capacity(uploads.len());
+    let mut conversions = Vec::new();
+    for (index, (upload, settings)) in uploads.into_iter().zip(file_settings).enumerate() {
+        if let Some(e) = upload.upload_error {
+            results.push(BatchConversionResult {
+                index,
+                file_name: upload.file_name,
+                result: None,
+                error: Some(upload_error_body(e)),
+            });
+            continue;
+        }
+        
+        let settings = match settings {
+            Ok(settings) => settings,
+            Err(errors) => {
+                // Only this image is rejected; nothing will refer to its upload
+                delete_upload(&upload.file_path, "rejected").await;
+                results.push(BatchConversionResult {
+                    index,
+                    file_name: upload.file_name,
+                    result: None,
+                    error: Some(ErrorResponse {
+                        error: "Invalid settings".to_string(),
+                        details: Some(format!("{} invalid field(s)", errors.len())),
+                        fields: errors,
+                    }),
+                });
+                continue;
+            }
+        };
+        
+        let output_path = revision_output_path(&upload.file_id, 1);
+        store_file_metadata(&upload.file_id, &upload.file_name, &upload.file_path, &output_path, retention_secs);
+        
+        // Every conversion waits for a slot in the worker pool shared with queued jobs
+        conversions.push(async move {
+            match convert_on_worker(&upload.file_path, &output_path, &settings).await {
+                Ok(output) => {
+                    let expires_at = converted_expiry(&upload.file_id, &output_path).await;
+                    
+                    BatchConversionResult {
+                        index,
+                        result: Some(conversion_response(&upload.file_id, 1, &settings, output, expires_at)),
+                        file_name: upload.file_name,
+                        error: None,
+                    }
+                }
+                Err(e) => {
+                    error!("Conversion error for {} in batch: {}", upload.file_id, e);
+                    
+                    BatchConversionResult {
+                        index,
+                        file_name: upload.file_name,
+                        result: None,
+                        error: Some(conversion_error_body(e)),
+                    }
+                }
+            }
+        });
+    }
+    
+    results.extend(join_all(conversions).await);
+    results.sort_by_key(|result| result.index);
+    
+    let succeeded = results.iter().filter(|result| result.result.is_some()).count();
+    let failed = results.len() - succeeded;
+    info!("Batch conversion finished: {} succeeded, {} failed", succeeded, failed);
+    
+    Ok(HttpResponse::Ok().json(BatchConversionResponse {
+        results,
+        succeeded,
+        failed,
+    }))
+}
+
+#[post("/api/convert/{file_id}")]
+pub async fn reconvert_image(
+    file_id: web::Path<String>,
+    query: web::Query<RetentionQuery>,
+    body: String,
+) -> HttpResponse {
+    let file_id = file_id.into_inner();
+    
+    // Unless a new one is asked for, the file keeps the retention it was stored with
+    let retention_secs = match validate_retention_query(&query) {
+        Ok(retention_secs) => retention_secs,
+        Err(errors) => return invalid_fields_response("Invalid retention", errors),
+    };
+    
+    let settings = match settings_from_body(&body) {
+        Ok(settings) => settings,
+        Err(errors) => return invalid_fields_response("Invalid settings", errors),
+    };
+    
+    // Checked before reserving a revision, so a request that can't convert leaves the file as it was
+    let input_path = match get_file_metadata(&file_id) {
+        Some(metadata) => metadata.input_path,
+        None => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "File not found".to_string(),
+                details: None,
+                fields: Vec::new(),
+            });
+        }
+    };
+    if !blob_exists(&input_path).await {
+        return HttpResponse::NotFound().json(ErrorResponse {
+            error: "Upload no longer available".to_string(),
+            details: Some("Upload the image again to convert it".to_string()),
+            fields: Vec::new(),
+        });
+    }
+    
+    // Reserving the revision also restarts the file's retention
+    let (metadata, revision) = match reserve_file_revision(&file_id, retention_secs) {
+        Some(reserved) => reserved,
+        // Deleted or expired since the check above
+        None => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "File not found".to_string(),
+                details: None,
+                fields: Vec::new(),
+            });
+        }
+    };
+    
+    let output_path = revision_output_path(&file_id, revision);
+    match convert_on_worker(&metadata.input_path, &output_path, &settings).await {
+        Ok(output) => {
+            // Deleted while converting, the file has already expired
+            let (record_id, record_path) = (file_id.clone(), output_path.clone());
+            let expires_at = web::block(move || record_file_revision(&record_id, revision, &record_path))
+                .await
+                .ok()
+                .flatten()
+                .map(|metadata| metadata.expires_at())
+                .unwrap_or_else(Utc::now);
+            info!("Converted revision {} of {}", revision, file_id);
+            
+            HttpResponse::Ok().json(conversion_response(&file_id, revision, &settings, output, expires_at))
+        }
+        Err(e) => {
+            error!("Conversion error for revision {} of {}: {}", revision, file_id, e);
+            
+            conversion_error_response(e)
+        }
+    }
+}
+
+#[post("/api/preview/{file_id}")]
+pub async fn preview_image(file_id: web::Path<String>, body: String) -> HttpResponse {
+    let file_id = file_id.into_inner();
+    
+    let settings = match settings_from_body(&body) {
+        Ok(settings) => settings,
+        Err(errors) => return invalid_fields_response("Invalid settings", errors),
+    };
+    
+    let metadata = match get_file_metadata(&file_id) {
+        Some(metadata) if blob_exists(&metadata.input_path).await => metadata,
+        _ => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "File not found".to_string(),
+                details: None,
+                fields: Vec::new(),
+            });
+        }
+    };
+    
+    // Tracing blocks, but previews stay off the worker pool so they don't queue behind conversions
+    let preview_id = file_id.clone();
+    let rendered = web::block(move || render_preview(&preview_id, &metadata.input_path, &settings))
+        .await
+        .unwrap_or_else(|e| Err(VectorizerError::ProcessingError(e.to_string())));
+    
+    match rendered {
+        Ok(preview) => HttpResponse::Ok().json(PreviewResponse {
+            file_id,
+            svg_data: preview.svg_data,
+            preview_width: preview.preview_width,
+            preview_height: preview.preview_height,
+        }),
+        Err(VectorizerError::InvalidSettings(errors)) => invalid_fields_response("Invalid settings", errors),
+        Err(e) => {
+            error!("Preview error for {}: {}", file_id, e);
+            
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Preview failed".to_string(),
                 details: Some(e.to_string()),
+                fields: Vec::new(),
+            })
+        }
+    }
+}
+
+#[post("/api/analyze")]
+pub async fn analyze_image(mut payload: Multipart) -> Result<HttpResponse, Error> {
+    let file_id = generate_file_id();
+    
+    // Settings sent along are ignored; only the image is looked at
+    let ConversionUpload { file_path, upload_error, .. } = read_conversion_upload(&mut payload, &file_id).await;
+    
+    // Check if we have a file
+    if file_path.is_empty() {
+        return Ok(missing_upload_response(upload_error));
+    }
+    
+    // Decoding and analysis are as heavy as a conversion, so they share the worker pool
+    let analyzed = run_on_worker(move || {
+        let decoded = decode_image(&file_path);
+        
+        // The upload is not kept, since nothing refers to it afterwards
+        if let Err(e) = BLOB_STORE.delete(&file_path) {
+            error!("Failed to delete analyzed upload {}: {}", file_path, e);
+        }
+        
+        decoded.map(|img| analysis::analyze_image(&img))
+    })
+    .await;
+    
+    match analyzed {
+        Ok(Ok(analysis)) => Ok(HttpResponse::Ok().json(analysis)),
+        Ok(Err(e)) => {
+            error!("Analysis error: {}", e);
+            
+            Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Analysis failed".to_string(),
+                details: Some(e.to_string()),
+                fields: Vec::new(),
             }))
         }
+        Err(e) => {
+            error!("Analysis did not finish: {}", e);
+            
+            Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Analysis failed".to_string(),
+                details: Some(e),
+                fields: Vec::new(),
+            }))
+        }
+    }
+}
+
+#[post("/api/jobs")]
+pub async fn create_job(query: web::Query<RetentionQuery>, mut payload: Multipart) -> Result<HttpResponse, Error> {
+    let retention_secs = match validate_retention_query(&query) {
+        Ok(retention_secs) => retention_secs.unwrap_or_else(default_retention_secs),
+        Err(errors) => return Ok(invalid_fields_response("Invalid retention", errors)),
+    };
+    
+    // Generate a unique file ID
+    let file_id = generate_file_id();
+    
+    let ConversionUpload { file_name, file_path, settings, upload_error } =
+        read_conversion_upload(&mut payload, &file_id).await;
+    
+    // Check if we have a file
+    if file_path.is_empty() {
+        return Ok(missing_upload_response(upload_error));
+    }
+    
+    // Reject invalid settings before doing any work
+    let settings = match settings {
+        Ok(settings) => settings,
+        Err(errors) => {
+            // Nothing will be converted, so drop the upload straight away
+            delete_upload(&file_path, "rejected").await;
+            return Ok(invalid_fields_response("Invalid settings", errors));
+        }
+    };
+    
+    // Create output path
+    let output_path = revision_output_path(&file_id, 1);
+    
+    // Store file metadata
+    let metadata = store_file_metadata(&file_id, &file_name, &file_path, &output_path, retention_secs);
+    
+    // Queue the conversion
+    let job_id = submit_job(
+        &file_id,
+        file_path,
+        output_path,
+        settings,
+    );
+    
+    info!("Queued conversion job {} for file {}", job_id, file_id);
+    
+    Ok(HttpResponse::Accepted().json(JobResponse {
+        job_id,
+        status: JobStatus::Queued,
+        expires_at: metadata.expires_at(),
+        message: "Conversion queued".to_string(),
+    }))
+}
+
+#[get("/api/jobs/{job_id}")]
+pub async fn get_job_status(job_id: web::Path<String>) -> impl Responder {
+    let job_id = job_id.into_inner();
+    
+    match get_job(&job_id) {
+        Some(job) => {
+            let queued_ms = job
+                .started_at
+                .map(|started| (started - job.created_at).num_milliseconds());
+            let running_ms = match (job.started_at, job.finished_at) {
+                (Some(started), Some(finished)) => Some((finished - started).num_milliseconds()),
+                _ => None,
+            };
+            let file_id = if job.status == JobStatus::Succeeded {
+                Some(job.file_id.clone())
+            } else {
+                None
+            };
+            let expires_at = file_id
+                .as_deref()
+                .and_then(get_file_metadata)
+                .map(|metadata| metadata.expires_at());
+            
+            HttpResponse::Ok().json(JobStatusResponse {
+                job_id,
+                status: job.status,
+                stage: job.stage,
+                progress: job.progress,
+                file_id,
+                created_at: job.created_at,
+                started_at: job.started_at,
+                finished_at: job.finished_at,
+                queued_ms,
+                running_ms,
+                expires_at,
+                error: job.error,
+            })
+        }
+        None => {
+            HttpResponse::NotFound().json(ErrorResponse {
+                error: "Job not found".to_string(),
+                details: None,
+                fields: Vec::new(),
+            })
+        }
     }
 }
 
+#[get("/api/jobs/{job_id}/events")]
+pub async fn job_events(job_id: web::Path<String>) -> impl Responder {
+    let job_id = job_id.into_inner();
+    
+    let (job, receiver) = match subscribe_to_job(&job_id) {
+        Some(subscription) => subscription,
+        None => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Job not found".to_string(),
+                details: None,
+                fields: Vec::new(),
+            });
+        }
+    };
+    
+    // Send the current state first, then every update until the job finishes
+    let stream = futures::stream::unfold(
+        (Some(job.progress_event()), Some(receiver)),
+        |(pending, mut receiver)| async move {
+            let event = match pending {
+                Some(event) => event,
+                None => loop {
+                    match receiver.as_mut()?.recv().await {
+                        Ok(event) => break event,
+                        // Skipped events are superseded by the next one
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => return None,
+                    }
+                },
+            };
+            
+            if event.status.is_finished() {
+                receiver = None;
+            }
+            
+            let frame = format!(
+                "event: progress\ndata: {}\n\n",
+                serde_json::to_string(&event).unwrap_or_default()
+            );
+            
+            Some((Ok::<_, Error>(web::Bytes::from(frame)), (None, receiver)))
+        },
+    );
+    
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .append_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
+
+// Read a converted file in the given format; the export writers block too, so they run along
+async fn read_output(output_path: &str, format: ExportFormat, options: &ExportOptions) -> Result<Vec<u8>, BlobError> {
+    let output_path = output_path.to_string();
+    let options = options.clone();
+    on_blob_store(move || {
+        let geometry = || VectorImage::load(&geometry_key(&output_path));
+        Ok(match format {
+            // Served as the vectorizer wrote it
+            ExportFormat::Svg => BLOB_STORE.get(&output_path)?,
+            ExportFormat::Pdf => pdf::write_pdf(&geometry()?, &options),
+            ExportFormat::Eps => eps::write_postscript(&geometry()?, &options, true),
+            ExportFormat::Ps => eps::write_postscript(&geometry()?, &options, false),
+            ExportFormat::Dxf => dxf::write_dxf(&geometry()?, &options),
+            ExportFormat::Gcode => gcode::write_gcode(&geometry()?, &options),
+            ExportFormat::Hpgl => hpgl::write_hpgl(&geometry()?, &options),
+        })
+    })
+    .await
+}
+
 #[get("/api/download/{file_id}")]
-pub async fn download_svg(web::Path(file_id): web::Path<String>) -> impl Responder {
+pub async fn download_svg(file_id: web::Path<String>, query: web::Query<DownloadQuery>) -> impl Responder {
+    let file_id = file_id.into_inner();
+    
+    // Work out the requested format and its options
+    let (format, options) = match validate_download_query(&query) {
+        Ok(export) => export,
+        Err(errors) => return invalid_fields_response("Invalid download options", errors),
+    };
+    
     // Get file metadata
     match get_file_metadata(&file_id) {
         Some(metadata) => {
-            // Check if output file exists
-            if !Path::new(&metadata.output_path).exists() {
-                return HttpResponse::NotFound().json(ErrorResponse {
-                    error: "File not found".to_string(),
-                    details: None,
-                });
-            }
+            // Check if output file exists, in the requested revision
+            let output_path = match metadata.revision_output(query.revision) {
+                Some(output_path) if blob_exists(output_path).await => output_path,
+                _ => {
+                    return HttpResponse::NotFound().json(ErrorResponse {
+                        error: "File not found".to_string(),
+                        details: query.revision.map(|revision| format!("No revision {} of this file", revision)),
+                        fields: Vec::new(),
+                    });
+                }
+            };
             
-            // Read SVG data
-            match fs::read(&metadata.output_path) {
-                Ok(svg_data) => {
+            // Read SVG data, or write it out in the requested format
+            match read_output(output_path, format, &options).await {
+                Ok(data) => {
                     // Get original filename without extension
                     let original_name = Path::new(&metadata.original_name)
                         .file_stem()
@@ -162,19 +1052,20 @@ pub async fn download_svg(web::Path(file_id): web::Path<String>) -> impl Respond
                         .unwrap_or("download");
                     
                     // Create download filename
-                    let download_name = format!("{}.svg", original_name);
+                    let download_name = format!("{}.{}", original_name, format.extension());
                     
-                    // Return SVG file
+                    // Return file
                     HttpResponse::Ok()
-                        .content_type("image/svg+xml")
+                        .content_type(format.content_type())
                         .append_header(("Content-Disposition", format!("attachment; filename=\"{}\"", download_name)))
-                        .body(svg_data)
+                        .body(data)
                 }
                 Err(e) => {
-                    error!("Failed to read SVG file: {}", e);
+                    error!("Failed to read {} file: {}", format.extension(), e);
                     HttpResponse::InternalServerError().json(ErrorResponse {
                         error: "Failed to read file".to_string(),
                         details: Some(e.to_string()),
+                        fields: Vec::new(),
                     })
                 }
             }
@@ -183,11 +1074,29 @@ pub async fn download_svg(web::Path(file_id): web::Path<String>) -> impl Respond
             HttpResponse::NotFound().json(ErrorResponse {
                 error: "File not found".to_string(),
                 details: None,
+                fields: Vec::new(),
             })
         }
     }
 }
 
+// Name of a file in a batch ZIP: its original name in the new format, numbered when an earlier
+// file already took that name
+fn zip_entry_name(original_name: &str, format: ExportFormat, taken: &mut HashSet<String>) -> String {
+    let stem = Path::new(original_name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("image");
+    
+    let mut name = format!("{}.{}", stem, format.extension());
+    let mut number = 2;
+    while !taken.insert(name.clone()) {
+        name = format!("{}-{}.{}", stem, number, format.extension());
+        number += 1;
+    }
+    name
+}
+
 #[post("/api/download-batch")]
 pub async fn download_batch(req: web::Json<BatchDownloadRequest>) -> impl Responder {
     let file_ids = &req.file_ids;
@@ -196,49 +1105,46 @@ pub async fn download_batch(req: web::Json<BatchDownloadRequest>) -> impl Respon
         return HttpResponse::BadRequest().json(ErrorResponse {
             error: "No file IDs provided".to_string(),
             details: None,
+            fields: Vec::new(),
         });
     }
     
-    // Create a temporary file for the zip
-    let temp_dir = tempfile::tempdir().unwrap();
-    let zip_path = temp_dir.path().join("vectorised-images.zip");
-    let zip_file = fs::File::create(&zip_path).unwrap();
+    // Work out the requested format and its options
+    let (format, export_options) = match validate_download_query(&req.options) {
+        Ok(export) => export,
+        Err(errors) => return invalid_fields_response("Invalid download options", errors),
+    };
     
-    // Create zip writer
-    let mut zip = zip::ZipWriter::new(zip_file);
+    // Build the zip in memory, since the outputs may not be on this machine either
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
     let options = FileOptions::default()
         .compression_method(zip::CompressionMethod::Deflated)
         .unix_permissions(0o644);
     
     let mut added_files = 0;
+    let mut entry_names = HashSet::new();
     
-    // Add each SVG to the zip
+    // Add each file to the zip in the requested format
     for file_id in file_ids {
         if let Some(metadata) = get_file_metadata(file_id) {
-            let output_path = Path::new(&metadata.output_path);
+            let output_path = metadata.output_path.as_str();
             
-            if output_path.exists() {
-                // Get original filename without extension
-                let original_name = Path::new(&metadata.original_name)
-                    .file_stem()
-                    .and_then(|stem| stem.to_str())
-                    .unwrap_or("image");
-                
-                // Create filename for zip
-                let zip_filename = format!("{}.svg", original_name);
+            if blob_exists(output_path).await {
+                // Read or export first, so a file that fails leaves no empty entry behind
+                let data = match read_output(output_path, format, &export_options).await {
+                    Ok(data) => data,
+                    Err(e) => {
+                        error!("Failed to read {} file: {}", format.extension(), e);
+                        continue;
+                    }
+                };
                 
                 // Add file to zip
+                let zip_filename = zip_entry_name(&metadata.original_name, format, &mut entry_names);
                 match zip.start_file(zip_filename, options) {
                     Ok(_) => {
-                        match fs::read(output_path) {
-                            Ok(data) => {
-                                if zip.write_all(&data).is_ok() {
-                                    added_files += 1;
-                                }
-                            }
-                            Err(e) => {
-                                error!("Failed to read SVG file: {}", e);
-                            }
+                        if zip.write_all(&data).is_ok() {
+                            added_files += 1;
                         }
                     }
                     Err(e) => {
@@ -251,38 +1157,199 @@ pub async fn download_batch(req: web::Json<BatchDownloadRequest>) -> impl Respon
     
     // Finish zip file
     match zip.finish() {
-        Ok(_) => {
+        Ok(zip_data) => {
             if added_files == 0 {
                 return HttpResponse::NotFound().json(ErrorResponse {
                     error: "No files found".to_string(),
                     details: None,
+                    fields: Vec::new(),
                 });
             }
             
-            // Read zip file
-            match fs::read(&zip_path) {
-                Ok(zip_data) => {
-                    // Return zip file
-                    HttpResponse::Ok()
-                        .content_type("application/zip")
-                        .append_header(("Content-Disposition", "attachment; filename=\"vectorised-images.zip\""))
-                        .body(zip_data)
-                }
-                Err(e) => {
-                    error!("Failed to read zip file: {}", e);
-                    HttpResponse::InternalServerError().json(ErrorResponse {
-                        error: "Failed to create zip file".to_string(),
-                        details: Some(e.to_string()),
-                    })
-                }
-            }
+            // Return zip file
+            HttpResponse::Ok()
+                .content_type("application/zip")
+                .append_header(("Content-Disposition", "attachment; filename=\"vectorised-images.zip\""))
+                .body(zip_data.into_inner())
         }
         Err(e) => {
             error!("Failed to create zip file: {}", e);
             HttpResponse::InternalServerError().json(ErrorResponse {
                 error: "Failed to create zip file".to_string(),
                 details: Some(e.to_string()),
+                fields: Vec::new(),
             })
         }
     }
 }
+
+#[delete("/api/files/{file_id}")]
+pub async fn delete_file(file_id: web::Path<String>) -> impl Responder {
+    let file_id = file_id.into_inner();
+    
+    match get_file_metadata(&file_id) {
+        Some(metadata) => {
+            // The upload and every revision go at once, without waiting for the file to expire
+            let removed_id = file_id.clone();
+            if let Err(e) = web::block(move || remove_file_and_outputs(&removed_id, &metadata)).await {
+                error!("Failed to delete files for {}: {}", file_id, e);
+            }
+            info!("Deleted files for {} on request", file_id);
+            
+            HttpResponse::NoContent().finish()
+        }
+        None => {
+            HttpResponse::NotFound().json(ErrorResponse {
+                error: "File not found".to_string(),
+                details: None,
+                fields: Vec::new(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ConversionResponse, FileMetadata};
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use actix_web::App;
+    use image::{DynamicImage, ImageBuffer, ImageOutputFormat, Rgba};
+
+    // Record an upload as the convert endpoint would, with or without its image in storage
+    fn stored_upload(with_image: bool) -> (String, FileMetadata) {
+        let file_id = generate_file_id();
+        let input_path = format!("{}/{}.png", upload_dir(), file_id);
+        if with_image {
+            let image = DynamicImage::ImageRgba8(ImageBuffer::from_fn(8, 8, |x, _| {
+                if x < 4 {
+                    Rgba([0, 0, 0, 255])
+                } else {
+                    Rgba([255, 255, 255, 255])
+                }
+            }));
+            let mut png = Cursor::new(Vec::new());
+            image.write_to(&mut png, ImageOutputFormat::Png).unwrap();
+            BLOB_STORE.put(&input_path, png.get_ref()).unwrap();
+        }
+
+        let metadata = store_file_metadata(&file_id, "logo.png", &input_path, &revision_output_path(&file_id, 1), 600);
+        (file_id, metadata)
+    }
+
+    #[actix_web::test]
+    async fn reconverting_without_the_upload_leaves_the_file_alone() {
+        let app = init_service(App::new().service(reconvert_image)).await;
+
+        let request = TestRequest::post().uri("/api/convert/unknown-file").to_request();
+        assert_eq!(call_service(&app, request).await.status(), StatusCode::NOT_FOUND);
+
+        // Recorded, but the upload has gone from storage
+        let (file_id, stored) = stored_upload(false);
+        let request = TestRequest::post()
+            .uri(&format!("/api/convert/{}?expires_in=7200", file_id))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: ErrorResponse = read_body_json(response).await;
+        assert_eq!(body.error, "Upload no longer available");
+
+        // No revision was used up and the retention was neither restarted nor replaced
+        let metadata = get_file_metadata(&file_id).unwrap();
+        assert_eq!(metadata.next_revision, stored.next_revision);
+        assert_eq!(metadata.timestamp, stored.timestamp);
+        assert_eq!(metadata.retention_secs, stored.retention_secs);
+    }
+
+    #[actix_web::test]
+    async fn reconverting_adds_a_revision() {
+        let app = init_service(App::new().service(reconvert_image)).await;
+
+        let (file_id, stored) = stored_upload(true);
+        let request = TestRequest::post()
+            .uri(&format!("/api/convert/{}?expires_in=7200", file_id))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: ConversionResponse = read_body_json(response).await;
+        assert_eq!(body.revision, 2);
+
+        let metadata = get_file_metadata(&file_id).unwrap();
+        assert_eq!(metadata.next_revision, stored.next_revision + 1);
+        assert_eq!(metadata.retention_secs, 7200);
+        assert_eq!(metadata.output_path, revision_output_path(&file_id, 2));
+    }
+
+    // The outcome of batch_settings in brief: the threshold and smoothing of each image, or why it was rejected
+    fn describe(outcome: Result<BatchFileSettings, BatchRejection>) -> String {
+        let fields = |errors: &[FieldError]| errors.iter().map(|error| error.field.clone()).collect::<Vec<_>>().join(",");
+        match outcome {
+            Ok(images) => images
+                .iter()
+                .map(|settings| match settings {
+                    Ok(settings) => format!("{}/{}", settings.threshold, settings.smoothing),
+                    Err(errors) => format!("invalid:{}", fields(errors)),
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            Err(BatchRejection::NoFiles) => "no files".to_string(),
+            Err(BatchRejection::TooManyFiles) => "too many files".to_string(),
+            Err(BatchRejection::InvalidSettings(errors)) => format!("rejected:{}", fields(&errors)),
+        }
+    }
+
+    #[test]
+    fn batch_settings_merge_overrides_into_the_shared_settings() {
+        let shared = r#"{"threshold": 100, "smoothing": 2}"#;
+        // Images uploaded, shared settings, overrides by image index and the outcome
+        type Case<'a> = (usize, Option<&'a str>, &'a [(usize, &'a str)], &'a str);
+        let cases: &[Case] = &[
+            // Defaults are threshold 128 and smoothing 5
+            (2, None, &[], "128/5 128/5"),
+            (2, Some(shared), &[], "100/2 100/2"),
+            // An override replaces only the fields it sets, and only for its own image
+            (3, Some(shared), &[(1, r#"{"threshold": 50}"#)], "100/2 50/2 100/2"),
+            (2, None, &[(0, r#"{"smoothing": 9}"#)], "128/9 128/5"),
+            (2, Some(shared), &[(0, "{}")], "100/2 100/2"),
+            // A bad override fails its own image only
+            (2, Some(shared), &[(1, r#"{"threshold": 300}"#)], "100/2 invalid:threshold"),
+            (2, Some(shared), &[(0, "[1]")], "invalid:settings 100/2"),
+            // Overrides for images that weren't sent and bad shared settings reject the batch
+            (2, Some(shared), &[(2, "{}"), (7, "{}"), (1, "{}")], "rejected:settings[2],settings[7]"),
+            (2, Some(r#"{"smoothing": 20}"#), &[(3, "{}")], "rejected:smoothing,settings[3]"),
+            (0, Some(shared), &[], "no files"),
+            (4, Some(shared), &[], "too many files"),
+        ];
+
+        for &(uploaded, shared_json, overrides, expected) in cases {
+            let overrides: HashMap<usize, String> =
+                overrides.iter().map(|&(index, json)| (index, json.to_string())).collect();
+            let outcome = describe(batch_settings(uploaded, 3, shared_json, &overrides));
+            assert_eq!(outcome, expected, "{} images, shared {:?}, overrides {:?}", uploaded, shared_json, overrides);
+        }
+    }
+
+    #[test]
+    fn batches_up_to_max_batch_files_are_accepted() {
+        let overrides = HashMap::new();
+        for (uploaded, max_files, accepted) in [(1, 1, true), (2, 1, false), (50, 50, true), (51, 50, false)] {
+            let outcome = batch_settings(uploaded, max_files, None, &overrides);
+            assert_eq!(outcome.is_ok(), accepted, "{} of {}", uploaded, max_files);
+            if !accepted {
+                assert!(matches!(outcome, Err(BatchRejection::TooManyFiles)));
+            }
+        }
+    }
+
+    #[test]
+    fn zip_entries_get_unique_names() {
+        let mut taken = HashSet::new();
+        let names: Vec<String> = ["logo.png", "logo.jpg", "photos/logo.png", "logo-2.png", "", "scan.tiff"]
+            .iter()
+            .map(|name| zip_entry_name(name, ExportFormat::Eps, &mut taken))
+            .collect();
+
+        assert_eq!(names, ["logo.eps", "logo-2.eps", "logo-3.eps", "logo-2-2.eps", "image.eps", "scan.eps"]);
+    }
+}
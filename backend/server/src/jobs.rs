@@ -0,0 +1,300 @@
+// src/jobs.rs
+use crate::config::config;
+use crate::models::{ConversionStage, JobProgressEvent, JobStatus, ValidatedSettings};
+use crate::vectorizer::convert_image_to_svg_with_progress;
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, OwnedSemaphorePermit, Semaphore};
+
+// Global storage for conversion jobs
+lazy_static::lazy_static! {
+    static ref JOB_STORE: Arc<Mutex<HashMap<String, Job>>> = Arc::new(Mutex::new(HashMap::new()));
+    static ref WORKER_POOL: Arc<Semaphore> = Arc::new(Semaphore::new(worker_count()));
+}
+
+// Progress events buffered per subscriber before old ones are dropped
+const EVENT_BUFFER: usize = 64;
+
+// How long a finished job can still be polled before it is forgotten
+const FINISHED_JOB_TTL_SECS: i64 = 3600;
+
+// A conversion job and its progress through the worker pool
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub file_id: String,
+    pub status: JobStatus,
+    pub stage: Option<ConversionStage>,
+    pub progress: u8,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    events: broadcast::Sender<JobProgressEvent>,
+}
+
+impl Job {
+    // Current state of the job as a progress event
+    pub fn progress_event(&self) -> JobProgressEvent {
+        JobProgressEvent {
+            status: self.status,
+            stage: self.stage,
+            progress: self.progress,
+            file_id: if self.status == JobStatus::Succeeded {
+                Some(self.file_id.clone())
+            } else {
+                None
+            },
+            error: self.error.clone(),
+        }
+    }
+}
+
+// Number of conversions allowed to run at the same time
+fn worker_count() -> usize {
+    match config().conversion.workers {
+        0 => std::thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(2),
+        count => count,
+    }
+}
+
+// Queue a conversion and return immediately with the job ID
+pub fn submit_job(
+    file_id: &str,
+    input_key: String,
+    output_key: String,
+    settings: ValidatedSettings,
+) -> String {
+    let job_id = crate::utils::generate_file_id();
+    let (events, _) = broadcast::channel(EVENT_BUFFER);
+
+    let job = Job {
+        file_id: file_id.to_string(),
+        status: JobStatus::Queued,
+        stage: None,
+        progress: 0,
+        created_at: Utc::now(),
+        started_at: None,
+        finished_at: None,
+        error: None,
+        events,
+    };
+
+    {
+        let mut store = JOB_STORE.lock().unwrap();
+        remove_finished_jobs(&mut store, Utc::now());
+        store.insert(job_id.clone(), job);
+    }
+
+    let worker_pool = WORKER_POOL.clone();
+    let job_key = job_id.clone();
+    let file_id = file_id.to_string();
+
+    tokio::spawn(async move {
+        // Wait for a free worker slot, held until the conversion has really ended
+        let permit = match worker_pool.acquire_owned().await {
+            Ok(permit) => permit,
+            Err(e) => {
+                error!("Worker pool closed: {}", e);
+                finish_job(&job_key, Err(e.to_string()));
+                return;
+            }
+        };
+
+        update_job(&job_key, |job| {
+            job.status = JobStatus::Running;
+            job.started_at = Some(Utc::now());
+        });
+        info!("Running conversion job {}", job_key);
+
+        let progress_key = job_key.clone();
+        let worker_output_key = output_key.clone();
+        let result = run_with_permit(permit, move || {
+            let mut on_progress = |stage: ConversionStage, progress: u8| {
+                update_job(&progress_key, |job| {
+                    // A conversion that timed out may carry on, but its job has already failed
+                    if job.status == JobStatus::Running {
+                        job.stage = Some(stage);
+                        job.progress = progress;
+                    }
+                });
+            };
+
+            convert_image_to_svg_with_progress(&input_key, &worker_output_key, &settings, &mut on_progress)
+                .map(|_| ())
+        })
+        .await;
+
+        match result {
+            Ok(Ok(())) => {
+                // Deleted while converting, the output goes too; deleting it blocks with S3 storage
+                let _ = tokio::task::spawn_blocking(move || {
+                    crate::utils::converted_file_metadata(&file_id, &output_key)
+                })
+                .await;
+                finish_job(&job_key, Ok(()));
+            }
+            Ok(Err(e)) => {
+                error!("Conversion job {} failed: {}", job_key, e);
+                finish_job(&job_key, Err(e.to_string()));
+            }
+            Err(e) => {
+                error!("Conversion job {} did not finish: {}", job_key, e);
+                finish_job(&job_key, Err(e));
+            }
+        }
+    });
+
+    job_id
+}
+
+// Run a conversion on the worker pool, waiting for a free slot first
+pub async fn run_on_worker<T, F>(work: F) -> Result<T, String>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let permit = WORKER_POOL
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|e| {
+            error!("Worker pool closed: {}", e);
+            e.to_string()
+        })?;
+
+    run_with_permit(permit, work).await
+}
+
+// Run a conversion off the async runtime threads, since tracing is CPU-bound. Past the
+// conversion timeout the caller gets an error, but the worker slot is only released once the
+// conversion has really ended, so timed out conversions still count against the pool.
+async fn run_with_permit<T, F>(permit: OwnedSemaphorePermit, work: F) -> Result<T, String>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let handle = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        work()
+    });
+
+    let timeout_secs = config().conversion.timeout_secs;
+    let joined = if timeout_secs == 0 {
+        handle.await
+    } else {
+        match tokio::time::timeout(Duration::from_secs(timeout_secs), handle).await {
+            Ok(joined) => joined,
+            Err(_) => {
+                error!("Conversion timed out after {}s", timeout_secs);
+                return Err(format!("Conversion timed out after {} seconds", timeout_secs));
+            }
+        }
+    };
+
+    joined.map_err(|e| {
+        error!("Conversion worker panicked: {}", e);
+        "Conversion worker crashed".to_string()
+    })
+}
+
+// Get a snapshot of a job
+pub fn get_job(job_id: &str) -> Option<Job> {
+    let store = JOB_STORE.lock().unwrap();
+    store.get(job_id).cloned()
+}
+
+// Get the current state of a job along with a receiver for every later event
+pub fn subscribe_to_job(job_id: &str) -> Option<(Job, broadcast::Receiver<JobProgressEvent>)> {
+    // Subscribe while holding the lock so no event falls between the snapshot and the receiver
+    let store = JOB_STORE.lock().unwrap();
+    store
+        .get(job_id)
+        .map(|job| (job.clone(), job.events.subscribe()))
+}
+
+// Remove jobs whose file has been cleaned up
+pub fn remove_jobs_for_file(file_id: &str) {
+    let mut store = JOB_STORE.lock().unwrap();
+    store.retain(|_, job| job.file_id != file_id);
+}
+
+// Forget jobs that finished more than the TTL ago, whether or not their file is still stored
+pub fn remove_expired_jobs() {
+    let mut store = JOB_STORE.lock().unwrap();
+    remove_finished_jobs(&mut store, Utc::now());
+}
+
+fn remove_finished_jobs(store: &mut HashMap<String, Job>, now: DateTime<Utc>) {
+    let ttl = chrono::Duration::seconds(FINISHED_JOB_TTL_SECS);
+    store.retain(|_, job| match job.finished_at {
+        Some(finished_at) => now - finished_at <= ttl,
+        None => true,
+    });
+}
+
+// Apply a change to a job and notify its subscribers
+fn update_job<F: FnOnce(&mut Job)>(job_id: &str, update: F) {
+    let mut store = JOB_STORE.lock().unwrap();
+    if let Some(job) = store.get_mut(job_id) {
+        update(job);
+
+        // Sending only fails when nobody is listening
+        let _ = job.events.send(job.progress_event());
+    }
+}
+
+fn finish_job(job_id: &str, result: Result<(), String>) {
+    update_job(job_id, |job| {
+        job.finished_at = Some(Utc::now());
+        match result {
+            Ok(()) => {
+                job.status = JobStatus::Succeeded;
+                job.progress = 100;
+            }
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(finished_at: Option<DateTime<Utc>>) -> Job {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        Job {
+            file_id: "file".to_string(),
+            status: if finished_at.is_some() { JobStatus::Succeeded } else { JobStatus::Running },
+            stage: None,
+            progress: 0,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at,
+            error: None,
+            events,
+        }
+    }
+
+    #[test]
+    fn finished_jobs_are_removed_after_the_ttl() {
+        let now = Utc::now();
+        let mut store = HashMap::new();
+        store.insert("old".to_string(), job(Some(now - chrono::Duration::seconds(FINISHED_JOB_TTL_SECS + 1))));
+        store.insert("recent".to_string(), job(Some(now - chrono::Duration::seconds(60))));
+        store.insert("running".to_string(), job(None));
+
+        remove_finished_jobs(&mut store, now);
+
+        let mut left: Vec<&str> = store.keys().map(String::as_str).collect();
+        left.sort_unstable();
+        assert_eq!(left, ["recent", "running"]);
+    }
+}
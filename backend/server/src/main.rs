@@ -2,12 +2,30 @@
 use actix_cors::Cors;
 use actix_web::{middleware, App, HttpServer};
 use dotenv::dotenv;
-use log::info;
-use std::env;
+use log::{error, info};
 
+mod analysis;
+mod blob;
+mod cache;
+mod centerline;
+mod config;
+mod contour;
+mod export;
+mod geometry;
 mod handlers;
+mod jobs;
 mod models;
+mod pixel;
+mod potrace;
+mod preview;
+mod quality;
+mod quantize;
+mod raster;
+mod store;
+mod tracer;
+mod tuning;
 mod utils;
+mod validation;
 mod vectorizer;
 
 #[actix_web::main]
@@ -18,27 +36,37 @@ async fn main() -> std::io::Result<()> {
     // Initialize logger
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
     
-    // Get port from environment or use default
-    let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-    let address = format!("0.0.0.0:{}", port);
+    // Read the config file and environment, and stop on anything invalid before starting
+    let config = match config::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    info!("Effective configuration:\n{}", config.describe());
     
-    // Get frontend URL for CORS
-    let frontend_url = env::var("FRONTEND_URL").unwrap_or_else(|_| "*".to_string());
+    // Open the blob store for uploads and outputs
+    info!("Storing files in {}", blob::BLOB_STORE.describe());
     
-    // Create upload and output directories
-    utils::create_directories().expect("Failed to create directories");
+    // Pick up files stored before a restart, and delete what nothing refers to
+    utils::reconcile_files();
     
     // Start cleanup task
     utils::start_cleanup_task();
     
-    info!("Starting server at: {}", address);
+    let address = (config.server.host.as_str(), config.server.port);
+    info!("Starting server at: {}:{}", address.0, address.1);
     
     // Start HTTP server
     HttpServer::new(move || {
         // Configure CORS
-        let cors = Cors::default()
-            .allowed_origin(&frontend_url)
-            .allowed_methods(vec!["GET", "POST", "OPTIONS"])
+        let cors = config
+            .server
+            .cors_origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+            .allowed_methods(vec!["GET", "POST", "DELETE", "OPTIONS"])
             .allowed_headers(vec!["Content-Type", "Authorization"])
             .max_age(3600);
         
@@ -46,9 +74,18 @@ async fn main() -> std::io::Result<()> {
             .wrap(middleware::Logger::default())
             .wrap(cors)
             .service(handlers::health_check)
+            .service(handlers::metrics)
             .service(handlers::convert_image)
+            .service(handlers::convert_batch)
+            .service(handlers::reconvert_image)
+            .service(handlers::preview_image)
+            .service(handlers::analyze_image)
+            .service(handlers::create_job)
+            .service(handlers::get_job_status)
+            .service(handlers::job_events)
             .service(handlers::download_svg)
             .service(handlers::download_batch)
+            .service(handlers::delete_file)
     })
     .bind(address)?
     .run()
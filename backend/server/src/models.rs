@@ -1,22 +1,44 @@
 // src/models.rs
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+// Settings as sent by the client; missing fields fall back to the defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ConversionSettings {
-    // Output mode: "bw" or "color"
+    // Output mode: "bw", "color", "centerline" or "pixel_art"
     pub output_mode: String,
+    // Tracing engine: "vtracer", "potrace", "centerline" or "pixel"; follows output_mode when unset
+    pub engine: Option<String>,
     
     // B/W mode settings
     pub threshold: Option<u8>,
+    // Binarization method: "fixed", "otsu", "sauvola", "niblack" or "per_channel"
+    pub binarization: Option<String>,
+    // Window size in pixels and k factor for the adaptive methods
+    pub adaptive_window: Option<u32>,
+    pub adaptive_k: Option<f32>,
     
     // Color mode settings
     pub color_count: Option<u8>,
+    // Color reduction before tracing: "kmeans", "median_cut", "octree" or "none"
+    pub quantization: Option<String>,
+    // Fixed palette of hex colors; every pixel is snapped to the nearest one instead of quantizing
+    pub palette: Option<Vec<String>>,
+    
+    // Centerline mode settings: stroke width in pixels, estimated from the image when unset
+    pub stroke_width: Option<f32>,
     
     // Common settings
     pub smoothing: Option<u8>,
     pub path_simplification: Option<u8>,
     pub noise_reduction: Option<u8>,
     pub background_transparency: Option<bool>,
+    // Background handling: "keep", "remove" (detected from the edges) or "color"
+    pub background: Option<String>,
+    // Hex color removed when background is "color", e.g. "#ffffff"
+    pub background_color: Option<String>,
+    // Largest per-channel difference still counted as background
+    pub background_tolerance: Option<u8>,
     
     // Advanced settings
     pub brightness: Option<i8>,
@@ -24,38 +46,246 @@ pub struct ConversionSettings {
     pub gamma: Option<f32>,
     pub corner_threshold: Option<u8>,
     pub curve_mode: Option<String>,
+    
+    // Search corner_threshold, path_simplification and noise_reduction for the best result
+    pub auto: Option<bool>,
+    // Time allowed for the search in milliseconds
+    pub auto_time_budget_ms: Option<u32>,
 }
 
 impl Default for ConversionSettings {
     fn default() -> Self {
         Self {
             output_mode: "bw".to_string(),
+            engine: None,
             threshold: Some(128),
+            binarization: Some("fixed".to_string()),
+            adaptive_window: Some(25),
+            adaptive_k: None,
             color_count: Some(8),
+            quantization: None,
+            palette: None,
+            stroke_width: None,
             smoothing: Some(5),
             path_simplification: Some(5),
             noise_reduction: Some(4),
             background_transparency: Some(false),
+            background: None,
+            background_color: None,
+            background_tolerance: Some(24),
             brightness: Some(0),
             contrast: Some(0),
             gamma: Some(1.0),
             corner_threshold: Some(60),
             curve_mode: Some("spline".to_string()),
+            auto: Some(false),
+            auto_time_budget_ms: Some(5000),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+    Bw,
+    Color,
+    // Single strokes along the middle of dark lines, for line art
+    Centerline,
+    // Exact pixel edges in every original color, for sprites and icons
+    PixelArt,
+}
+
+// Backend that turns the preprocessed image into paths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Engine {
+    Vtracer,
+    // Potrace-style black and white outlines
+    Potrace,
+    Centerline,
+    // Unsmoothed outlines along the pixel edges
+    Pixel,
+}
+
+// How the colors of the image are reduced to color_count before tracing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quantization {
+    #[serde(rename = "kmeans")]
+    KMeans,
+    MedianCut,
+    Octree,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveMode {
+    Polygon,
+    Spline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Binarization {
+    Fixed,
+    Otsu,
+    Sauvola,
+    Niblack,
+    PerChannel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackgroundMode {
+    Keep,
+    Remove,
+    // Remove this RGB color wherever it appears
+    Color([u8; 3]),
+}
+
+// Settings after validation: every value is present and within its allowed range
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidatedSettings {
+    pub output_mode: OutputMode,
+    pub engine: Engine,
+    pub threshold: u8,
+    pub binarization: Binarization,
+    pub adaptive_window: u32,
+    // None uses the default k of the chosen adaptive method
+    pub adaptive_k: Option<f32>,
+    pub color_count: u8,
+    // None leaves the colors as they are
+    pub quantization: Option<Quantization>,
+    // Fixed palette, used instead of quantization when set
+    pub palette: Option<Vec<[u8; 3]>>,
+    // None estimates the width of each stroke
+    pub stroke_width: Option<f32>,
+    pub smoothing: u8,
+    pub path_simplification: u8,
+    pub noise_reduction: u8,
+    pub background: BackgroundMode,
+    pub background_tolerance: u8,
+    pub brightness: i8,
+    pub contrast: i8,
+    pub gamma: f32,
+    pub corner_threshold: u8,
+    pub curve_mode: CurveMode,
+    // Time budget for tuning the settings before converting; None converts with them as they are
+    pub auto_budget_ms: Option<u32>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConversionResponse {
     pub file_id: String,
     pub svg_data: String,
+    // Colors of the traced paths as "#rrggbb", in the order they are drawn
+    pub palette: Vec<String>,
+    pub quality: QualityReport,
+    // Settings picked by auto tuning, in the same form as the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings: Option<ConversionSettings>,
+    // Revision of the file this conversion produced, starting at 1
+    pub revision: u32,
+    // Whether the result was served from the result cache instead of being traced again
+    #[serde(default)]
+    pub cached: bool,
+    // When the file and every revision of it are deleted
+    pub expires_at: chrono::DateTime<chrono::Utc>,
     pub message: String,
 }
 
+// Results of a batch conversion, one per uploaded image in upload order
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchConversionResponse {
+    pub results: Vec<BatchConversionResult>,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+// Outcome for one image of a batch; exactly one of result and error is set
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchConversionResult {
+    // Position of the image among the uploaded images, starting at 0
+    pub index: usize,
+    pub file_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<ConversionResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+// How closely the SVG, rendered back at the source size, matches the preprocessed image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QualityReport {
+    // Peak signal-to-noise ratio in dB, capped at 100 for identical images
+    pub psnr: f64,
+    // Structural similarity of the luma, from 0 to 1
+    pub ssim: f64,
+    // Coverage of every traced color in the source and in the rendered SVG
+    pub colors: Vec<ColorCoverage>,
+    pub paths: usize,
+    // Points in the path data, counting both ends of every segment once
+    pub nodes: usize,
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColorCoverage {
+    pub color: String,
+    // Shares of the image closest to this color
+    pub source_coverage: f64,
+    pub output_coverage: f64,
+    // Share of the image where only one of the two has this color
+    pub error: f64,
+}
+
+// Low-resolution trace of a stored upload
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreviewResponse {
+    pub file_id: String,
+    // SVG in the viewBox of the original image
+    pub svg_data: String,
+    // Size of the downscaled copy that was traced
+    pub preview_width: u32,
+    pub preview_height: u32,
+}
+
+// What kind of image an upload is, with settings suggested for it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnalysisResponse {
+    pub width: u32,
+    pub height: u32,
+    // Whether any pixel is not fully opaque
+    pub has_alpha: bool,
+    // Colors covering a meaningful share of the image
+    pub estimated_colors: usize,
+    // Nearly every pixel is one of two luma levels, so black and white output fits
+    pub bilevel: bool,
+    // Standard deviation of the noise, in luma levels out of 255
+    pub noise: f64,
+    // Color dominating the border, as "#rrggbb"
+    pub background_color: Option<String>,
+    pub suggested_settings: ConversionSettings,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
     pub details: Option<String>,
+    // Per-field problems, only present for validation errors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+}
+
+// A single invalid settings field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    // Machine-readable reason: "out_of_range", "unknown_value", "invalid_type", "invalid_format",
+    // "missing", "unsupported" or "invalid_json"
+    pub code: String,
+    pub message: String,
+    // Allowed range or values, e.g. "0 to 10" or "bw, color"
+    pub allowed: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -64,15 +294,174 @@ pub struct HealthResponse {
     pub message: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricsResponse {
+    pub result_cache: ResultCacheStats,
+}
+
+// Counters of the conversion result cache since the server started
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResultCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    // Results dropped for age or to make room
+    pub evictions: u64,
+    pub entries: usize,
+    // Size of the cached SVGs and geometry, against the configured maximum
+    pub bytes: usize,
+    pub max_bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Succeeded | JobStatus::Failed)
+    }
+}
+
+// Stages of a conversion, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversionStage {
+    Decoding,
+    // Trying settings on a downscaled copy, for auto conversions
+    Tuning,
+    Preprocessing,
+    Clustering,
+    PathTracing,
+    WritingSvg,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobResponse {
+    pub job_id: String,
+    pub status: JobStatus,
+    // When the uploaded file is deleted, whether or not the job has finished by then
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobStatusResponse {
+    pub job_id: String,
+    pub status: JobStatus,
+    pub stage: Option<ConversionStage>,
+    pub progress: u8,
+    // Only set once the job has succeeded
+    pub file_id: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    // Time spent waiting for a worker and running, in milliseconds
+    pub queued_ms: Option<i64>,
+    pub running_ms: Option<i64>,
+    // When the converted file is deleted, while it is still stored
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub error: Option<String>,
+}
+
+// Event sent over /api/jobs/{job_id}/events whenever a job moves forward
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobProgressEvent {
+    pub status: JobStatus,
+    pub stage: Option<ConversionStage>,
+    pub progress: u8,
+    pub file_id: Option<String>,
+    pub error: Option<String>,
+}
+
+// Query string of the endpoints that store a conversion
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionQuery {
+    // Seconds to keep the file after this conversion; the server default when unset
+    pub expires_in: Option<u64>,
+}
+
+// Query string of /api/download/{file_id}
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadQuery {
+    // "svg" (default), "pdf", "eps", "ps", "dxf", "gcode" or "hpgl"
+    pub format: Option<String>,
+    // Revision to download from /api/download/{file_id}; the latest when unset
+    pub revision: Option<u32>,
+    // Source pixels per inch for formats with physical page sizes
+    pub dpi: Option<f64>,
+    // "mm" (default) or "inch" for CAD and machine formats
+    pub units: Option<String>,
+    // DXF only: "r12" or "r2000" (default)
+    pub dxf_version: Option<String>,
+    // "spline" or "polyline"; R12 DXF only supports polylines
+    pub curves: Option<String>,
+    // Largest distance between a curve and its flattened lines, in `units`
+    pub tolerance: Option<f64>,
+    // G-code only: "z" (default) to lift the Z axis or "pen" for M3/M5 pen servos
+    pub lift: Option<String>,
+    // G-code only: cutting speed in `units` per minute
+    pub feed_rate: Option<f64>,
+    // G-code only: Z heights for travel and cutting, in `units`
+    pub z_up: Option<f64>,
+    pub z_down: Option<f64>,
+    // HPGL only: where the artwork's bottom left corner goes, in `units`
+    pub origin_x: Option<f64>,
+    pub origin_y: Option<f64>,
+    // HPGL only: counterclockwise rotation of 0, 90, 180 or 270 degrees
+    pub rotation: Option<u16>,
+    // HPGL only: "none" (default), "x" or "y"
+    pub mirror: Option<String>,
+    // HPGL only: drag knife blade offset in `units`, compensated at corners
+    pub blade_offset: Option<f64>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BatchDownloadRequest {
     pub file_ids: Vec<String>,
+    // Format and options applied to every file in the ZIP
+    #[serde(flatten)]
+    pub options: DownloadQuery,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileMetadata {
     pub original_name: String,
     pub input_path: String,
+    // Output of the latest revision
     pub output_path: String,
+    // Last time the file was converted; cleanup counts its retention from here
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    // Revision number and output of every finished revision in order, the first conversion being revision 1
+    pub revisions: Vec<(u32, String)>,
+    // Number the next re-conversion gets, reserved up front so concurrent ones don't collide
+    pub next_revision: u32,
+    // Seconds the file is kept after each conversion, as requested when it was uploaded or last converted
+    #[serde(default = "crate::utils::default_retention_secs")]
+    pub retention_secs: u64,
+}
+
+impl FileMetadata {
+    // When cleanup deletes the file, unless it is converted again before then
+    pub fn expires_at(&self) -> chrono::DateTime<chrono::Utc> {
+        self.timestamp + chrono::Duration::seconds(self.retention_secs as i64)
+    }
+    
+    // Output of a revision, or of the latest one when none is given
+    pub fn revision_output(&self, revision: Option<u32>) -> Option<&str> {
+        match revision {
+            None => Some(self.output_path.as_str()),
+            Some(revision) => self
+                .revisions
+                .iter()
+                .find(|(number, _)| *number == revision)
+                .map(|(_, output_path)| output_path.as_str()),
+        }
+    }
 }
@@ -0,0 +1,199 @@
+// src/pixel.rs
+use crate::contour::trace_contours;
+use crate::geometry::{Point, Segment, Subpath, VectorImage, VectorPath};
+use crate::models::{ConversionStage, OutputMode, ValidatedSettings};
+use crate::tracer::{TraceOutput, Tracer, TRACE_PROGRESS_END, TRACE_PROGRESS_START};
+use crate::vectorizer::binarize_image;
+use image::{DynamicImage, GenericImageView};
+use std::collections::HashMap;
+
+// Pixel-exact tracing: every region of one color becomes a polygon along the pixel edges, unsmoothed
+
+// Tracing runs once per color, so photos have to be reduced to a palette first
+pub const MAX_PIXEL_COLORS: usize = 1024;
+
+pub struct PixelTracer;
+
+impl Tracer for PixelTracer {
+    fn name(&self) -> &'static str {
+        "pixel"
+    }
+
+    fn trace(
+        &self,
+        img: &DynamicImage,
+        settings: &ValidatedSettings,
+        on_progress: &mut dyn FnMut(ConversionStage, u8),
+    ) -> Result<TraceOutput, String> {
+        let (width, height) = img.dimensions();
+
+        on_progress(ConversionStage::PathTracing, TRACE_PROGRESS_START);
+        let rgba = if settings.output_mode == OutputMode::Bw {
+            binarize_image(img, settings).to_rgba8()
+        } else {
+            img.to_rgba8()
+        };
+
+        // Bounding box of every opaque color, in the order the colors first appear
+        let mut colors: Vec<([u8; 3], [u32; 4])> = Vec::new();
+        let mut color_index: HashMap<[u8; 3], usize> = HashMap::new();
+        for (x, y, pixel) in rgba.enumerate_pixels() {
+            if pixel[3] < 128 {
+                continue;
+            }
+
+            let color = [pixel[0], pixel[1], pixel[2]];
+            match color_index.get(&color) {
+                Some(&index) => {
+                    let bounds = &mut colors[index].1;
+                    bounds[0] = bounds[0].min(x);
+                    bounds[1] = bounds[1].min(y);
+                    bounds[2] = bounds[2].max(x);
+                    bounds[3] = bounds[3].max(y);
+                }
+                None => {
+                    if colors.len() == MAX_PIXEL_COLORS {
+                        return Err(format!(
+                            "The pixel engine supports up to {} colors; reduce the colors of the image first",
+                            MAX_PIXEL_COLORS
+                        ));
+                    }
+                    color_index.insert(color, colors.len());
+                    colors.push((color, [x, y, x, y]));
+                }
+            }
+        }
+
+        // Black and white output only draws the black shapes, like vtracer's binary mode
+        if settings.output_mode == OutputMode::Bw {
+            colors.retain(|(color, _)| *color != [255, 255, 255]);
+        }
+
+        let total = colors.len().max(1);
+        let span = (TRACE_PROGRESS_END - TRACE_PROGRESS_START) as usize;
+        let mut paths = Vec::with_capacity(colors.len());
+        let mut last_percent = TRACE_PROGRESS_START;
+
+        for (done, (color, [left, top, right, bottom])) in colors.into_iter().enumerate() {
+            let contours = trace_contours(
+                left as i64,
+                top as i64,
+                (right - left + 1) as usize,
+                (bottom - top + 1) as usize,
+                |x, y| {
+                    let pixel = rgba.get_pixel(x as u32, y as u32);
+                    pixel[3] >= 128 && [pixel[0], pixel[1], pixel[2]] == color
+                },
+            );
+
+            paths.push(VectorPath {
+                color,
+                subpaths: contours.iter().map(|contour| polygon(contour)).collect(),
+                stroke_width: None,
+            });
+
+            let percent = TRACE_PROGRESS_START + ((done + 1) * span / total) as u8;
+            if percent != last_percent {
+                on_progress(ConversionStage::PathTracing, percent);
+                last_percent = percent;
+            }
+        }
+
+        // Keeps each pixel a crisp square when scaled up
+        let vector_image = VectorImage {
+            width,
+            height,
+            paths,
+            pixel_grid: true,
+        };
+
+        Ok(TraceOutput {
+            vector_image,
+            binarized: (settings.output_mode == OutputMode::Bw).then_some(DynamicImage::ImageRgba8(rgba)),
+        })
+    }
+}
+
+fn polygon(contour: &[(i64, i64)]) -> Subpath {
+    let point = |(x, y): (i64, i64)| Point::new(x as f64, y as f64);
+
+    Subpath {
+        start: point(contour[0]),
+        segments: contour[1..].iter().map(|&corner| Segment::Line { to: point(corner) }).collect(),
+        closed: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ConversionSettings;
+    use crate::validation::validate_settings;
+    use image::{ImageBuffer, Rgba};
+
+    fn trace(image: &DynamicImage) -> VectorImage {
+        let settings = validate_settings(&ConversionSettings {
+            output_mode: "pixel_art".to_string(),
+            ..ConversionSettings::default()
+        })
+        .unwrap();
+        PixelTracer.trace(image, &settings, &mut |_, _| {}).unwrap().vector_image
+    }
+
+    #[test]
+    fn ring_is_one_path_with_its_hole() {
+        // A red 6x6 square with a 2x2 hole, on transparent pixels
+        let ring = DynamicImage::ImageRgba8(ImageBuffer::from_fn(8, 8, |x, y| {
+            let in_square = (1..7).contains(&x) && (1..7).contains(&y);
+            let in_hole = (3..5).contains(&x) && (3..5).contains(&y);
+            if in_square && !in_hole {
+                Rgba([200, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        }));
+        let traced = trace(&ring);
+
+        assert!(traced.pixel_grid);
+        assert_eq!(traced.paths.len(), 1);
+        assert_eq!(traced.paths[0].color, [200, 0, 0]);
+
+        let subpaths = &traced.paths[0].subpaths;
+        assert_eq!(subpaths.len(), 2);
+        let corners = |subpath: &Subpath| {
+            let mut corners: Vec<(i64, i64)> = subpath.flatten(0.1)[1..]
+                .iter()
+                .map(|point| (point.x as i64, point.y as i64))
+                .collect();
+            corners.sort();
+            corners
+        };
+        assert_eq!(corners(&subpaths[0]), [(1, 1), (1, 7), (7, 1), (7, 7)]);
+        assert_eq!(corners(&subpaths[1]), [(3, 3), (3, 5), (5, 3), (5, 5)]);
+
+        // Every edge runs along the pixel grid
+        for subpath in subpaths {
+            assert!(subpath.closed && !subpath.has_curves());
+            for edge in subpath.flatten(0.1).windows(2) {
+                let (from, to) = (edge[0], edge[1]);
+                assert!(from.x == to.x || from.y == to.y, "{:?} to {:?}", from, to);
+                assert!([from.x, from.y].iter().all(|value| value.fract() == 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn each_color_gets_its_own_path() {
+        let stripes = DynamicImage::ImageRgba8(ImageBuffer::from_fn(6, 2, |x, _| match x / 2 {
+            0 => Rgba([255, 0, 0, 255]),
+            1 => Rgba([0, 255, 0, 255]),
+            _ => Rgba([255, 0, 0, 255]),
+        }));
+        let traced = trace(&stripes);
+
+        let colors: Vec<[u8; 3]> = traced.paths.iter().map(|path| path.color).collect();
+        assert_eq!(colors, [[255, 0, 0], [0, 255, 0]]);
+        assert_eq!(traced.paths[0].subpaths.len(), 2);
+        assert_eq!(traced.paths[1].subpaths.len(), 1);
+    }
+}
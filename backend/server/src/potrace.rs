@@ -0,0 +1,235 @@
+// src/potrace.rs
+use crate::contour::{contour_area, trace_contours};
+use crate::geometry::{simplify_polyline, Point, Segment, Subpath, VectorImage, VectorPath};
+use crate::models::{ConversionStage, CurveMode, ValidatedSettings};
+use crate::tracer::{TraceOutput, Tracer, TRACE_PROGRESS_END, TRACE_PROGRESS_START};
+use crate::vectorizer::binarize_image;
+use image::{DynamicImage, GenericImageView};
+
+// Black and white tracing after Potrace: follow the pixel boundaries, straighten them into a polygon,
+// then round off every vertex that is not a sharp corner
+
+// Potrace's default corner threshold, reached at the default corner_threshold of 60 degrees
+const DEFAULT_ALPHA_MAX: f64 = 1.0;
+const MAX_ALPHA_MAX: f64 = 4.0 / 3.0;
+
+// Potrace-style bilevel tracer
+pub struct PotraceTracer;
+
+impl Tracer for PotraceTracer {
+    fn name(&self) -> &'static str {
+        "potrace"
+    }
+
+    fn trace(
+        &self,
+        img: &DynamicImage,
+        settings: &ValidatedSettings,
+        on_progress: &mut dyn FnMut(ConversionStage, u8),
+    ) -> Result<TraceOutput, String> {
+        let (width, height) = img.dimensions();
+
+        on_progress(ConversionStage::PathTracing, TRACE_PROGRESS_START);
+        let binarized = binarize_image(img, settings);
+        let binary = binarized.to_rgba8();
+        let inside = |x: i64, y: i64| {
+            let pixel = binary.get_pixel(x as u32, y as u32);
+            pixel[3] >= 128 && (pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) < 384
+        };
+        let contours = trace_contours(0, 0, width as usize, height as usize, inside);
+
+        // Same speckle size vtracer would filter; holes that small are filled in
+        let min_area = settings.noise_reduction as i64 * 2;
+
+        // Larger path_simplification straightens longer staircases
+        let epsilon = 0.75 + settings.path_simplification as f64 * 0.1;
+
+        // Polygon mode makes every vertex a corner
+        let alpha_max = match settings.curve_mode {
+            CurveMode::Polygon => 0.0,
+            CurveMode::Spline => {
+                (settings.corner_threshold as f64 / 60.0 * DEFAULT_ALPHA_MAX).min(MAX_ALPHA_MAX)
+            }
+        };
+
+        let subpaths: Vec<Subpath> = contours
+            .iter()
+            .filter(|contour| contour_area(contour).abs() >= min_area.max(1))
+            .map(|contour| {
+                let points: Vec<Point> = contour.iter().map(|&(x, y)| Point::new(x as f64, y as f64)).collect();
+
+                // Outlines only a few pixels across would collapse entirely; keep those as they are
+                let polygon = simplify_polyline(&points, true, epsilon);
+                if polygon.len() < 3 {
+                    smooth_polygon(&points, alpha_max)
+                } else {
+                    smooth_polygon(&polygon, alpha_max)
+                }
+            })
+            .collect();
+
+        on_progress(ConversionStage::PathTracing, TRACE_PROGRESS_END);
+
+        let vector_image = VectorImage {
+            width,
+            height,
+            paths: vec![VectorPath {
+                color: [0, 0, 0],
+                subpaths,
+                stroke_width: None,
+            }],
+            pixel_grid: false,
+        };
+
+        Ok(TraceOutput {
+            vector_image,
+            binarized: Some(binarized),
+        })
+    }
+}
+
+// Potrace's smoothing step. The outline runs between the midpoints of the polygon's edges; at each
+// vertex it either keeps a corner or bends through a Bézier curve, depending on how far the vertex
+// sticks out compared to its neighbours.
+fn smooth_polygon(vertices: &[Point], alpha_max: f64) -> Subpath {
+    let count = vertices.len();
+    let midpoint = |a: Point, b: Point| Point::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0);
+    let towards = |from: Point, to: Point, t: f64| {
+        Point::new(from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t)
+    };
+
+    let start = midpoint(vertices[count - 1], vertices[0]);
+    let mut segments = Vec::with_capacity(count * 2);
+
+    for index in 0..count {
+        let previous = vertices[(index + count - 1) % count];
+        let vertex = vertices[index];
+        let next = vertices[(index + 1) % count];
+        let end = midpoint(vertex, next);
+
+        // Distance of the vertex from the line between its neighbours, measured against the
+        // L1 length of that line as Potrace does
+        let cross = (vertex.x - previous.x) * (next.y - previous.y)
+            - (vertex.y - previous.y) * (next.x - previous.x);
+        let denominator = (next.x - previous.x).abs() + (next.y - previous.y).abs();
+        let alpha = if denominator > 0.0 {
+            let distance = (cross / denominator).abs();
+            let alpha = if distance > 1.0 { 1.0 - 1.0 / distance } else { 0.0 };
+            alpha / 0.75
+        } else {
+            4.0 / 3.0
+        };
+
+        if alpha >= alpha_max {
+            segments.push(Segment::Line { to: vertex });
+            segments.push(Segment::Line { to: end });
+        } else {
+            let alpha = alpha.clamp(0.55, 1.0);
+            segments.push(Segment::Cubic {
+                c1: towards(previous, vertex, 0.5 + 0.5 * alpha),
+                c2: towards(next, vertex, 0.5 + 0.5 * alpha),
+                to: end,
+            });
+        }
+    }
+
+    Subpath {
+        start,
+        segments,
+        closed: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ConversionSettings;
+    use crate::validation::validate_settings;
+    use image::{ImageBuffer, Rgba};
+
+    fn settings(curve_mode: &str) -> ValidatedSettings {
+        validate_settings(&ConversionSettings {
+            engine: Some("potrace".to_string()),
+            noise_reduction: Some(0),
+            curve_mode: Some(curve_mode.to_string()),
+            ..ConversionSettings::default()
+        })
+        .unwrap()
+    }
+
+    // Black where `ink` holds, on white
+    fn drawing(ink: impl Fn(f64) -> bool) -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(40, 40, |x, y| {
+            let distance = (x as f64 + 0.5 - 20.0).hypot(y as f64 + 0.5 - 20.0);
+            if ink(distance) {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }))
+    }
+
+    fn trace(image: &DynamicImage, settings: &ValidatedSettings) -> VectorImage {
+        PotraceTracer.trace(image, settings, &mut |_, _| {}).unwrap().vector_image
+    }
+
+    // Twice the signed area of the flattened outline; positive runs clockwise on screen
+    fn winding(subpath: &Subpath) -> f64 {
+        let points = subpath.flatten(0.1);
+        points.windows(2).map(|pair| pair[0].x * pair[1].y - pair[1].x * pair[0].y).sum()
+    }
+
+    #[test]
+    fn square_keeps_its_corners() {
+        let square = DynamicImage::ImageRgba8(ImageBuffer::from_fn(20, 20, |x, y| {
+            if (5..15).contains(&x) && (5..15).contains(&y) {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }));
+
+        for curve_mode in ["polygon", "spline"] {
+            let traced = trace(&square, &settings(curve_mode));
+            assert_eq!(traced.paths.len(), 1);
+            assert_eq!(traced.paths[0].subpaths.len(), 1);
+
+            // Right angles are sharp corners even when smoothing, so the outline stays on the square's edges
+            let subpath = &traced.paths[0].subpaths[0];
+            assert!(!subpath.has_curves(), "{}", curve_mode);
+            for point in subpath.flatten(0.1) {
+                let on_edge = [point.x, point.y].iter().any(|&value| value == 5.0 || value == 15.0);
+                assert!(on_edge, "{:?} in {} mode", point, curve_mode);
+            }
+        }
+    }
+
+    #[test]
+    fn disc_is_smoothed_into_curves() {
+        let disc = drawing(|distance| distance < 12.0);
+
+        let spline = trace(&disc, &settings("spline"));
+        assert!(spline.paths[0].subpaths[0].has_curves());
+
+        let polygon = trace(&disc, &settings("polygon"));
+        assert!(!polygon.paths[0].subpaths[0].has_curves());
+    }
+
+    #[test]
+    fn holes_wind_against_their_outline() {
+        let ring = trace(&drawing(|distance| (8.0..14.0).contains(&distance)), &settings("spline"));
+
+        let subpaths = &ring.paths[0].subpaths;
+        assert_eq!(subpaths.len(), 2);
+        assert!(winding(&subpaths[0]) * winding(&subpaths[1]) < 0.0);
+    }
+
+    #[test]
+    fn specks_are_dropped_by_noise_reduction() {
+        let dot = drawing(|distance| distance < 1.5);
+        assert_eq!(trace(&dot, &settings("spline")).paths[0].subpaths.len(), 1);
+
+        let settings = ValidatedSettings { noise_reduction: 10, ..settings("spline") };
+        assert!(trace(&dot, &settings).paths[0].subpaths.is_empty());
+    }
+}
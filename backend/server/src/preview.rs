@@ -0,0 +1,182 @@
+// src/preview.rs
+use crate::export::svg::write_svg;
+use crate::models::{OutputMode, ValidatedSettings};
+use crate::vectorizer::{decode_image, trace_image, VectorizerError};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// Quick previews: trace a small copy of an upload and scale the result back up to its full size
+
+// Previews are traced from at most this many pixels
+const PREVIEW_MAX_PIXELS: u64 = 160_000;
+
+// Downscaled uploads kept in memory; the least recently used one is dropped beyond this
+const PREVIEW_CACHE_SIZE: usize = 32;
+
+// A decoded upload, scaled down for previews
+struct CachedPreview {
+    image: Arc<DynamicImage>,
+    // Size of the original upload
+    width: u32,
+    height: u32,
+    last_used: Instant,
+}
+
+// Keyed by file ID and whether the copy keeps hard pixel edges
+lazy_static::lazy_static! {
+    static ref PREVIEW_CACHE: Arc<Mutex<HashMap<(String, bool), CachedPreview>>> = Arc::new(Mutex::new(HashMap::new()));
+}
+
+// A traced preview
+pub struct Preview {
+    pub svg_data: String,
+    // Size of the copy that was traced
+    pub preview_width: u32,
+    pub preview_height: u32,
+}
+
+// Trace a preview of an upload, in the viewBox of the original image
+pub fn render_preview(file_id: &str, input_key: &str, settings: &ValidatedSettings) -> Result<Preview, VectorizerError> {
+    let pixel_art = settings.output_mode == OutputMode::PixelArt;
+    let (image, width, height) = preview_image(file_id, input_key, pixel_art)?;
+    let (preview_width, preview_height) = image.dimensions();
+
+    // Tuning would take longer than the preview itself
+    let settings = ValidatedSettings {
+        auto_budget_ms: None,
+        ..settings.clone()
+    };
+
+    let (_, vector_image) = trace_image((*image).clone(), &settings, &mut |_, _| {})?;
+    let vector_image = vector_image.scaled_to(width, height);
+
+    Ok(Preview {
+        svg_data: write_svg(&vector_image),
+        preview_width,
+        preview_height,
+    })
+}
+
+// The downscaled copy of an upload with the original size, decoding it on first use.
+// Pixel art is shrunk by dropping pixels so no blended colors appear between them.
+fn preview_image(
+    file_id: &str,
+    input_key: &str,
+    pixel_art: bool,
+) -> Result<(Arc<DynamicImage>, u32, u32), VectorizerError> {
+    let key = (file_id.to_string(), pixel_art);
+    {
+        let mut cache = PREVIEW_CACHE.lock().unwrap();
+        if let Some(cached) = cache.get_mut(&key) {
+            cached.last_used = Instant::now();
+            return Ok((cached.image.clone(), cached.width, cached.height));
+        }
+    }
+
+    // Decode outside the lock; two first previews of one file may both decode, which is harmless
+    let img = decode_image(input_key)?;
+    let (width, height) = img.dimensions();
+    let pixels = width as u64 * height as u64;
+    let image = if pixels > PREVIEW_MAX_PIXELS {
+        let scale = (PREVIEW_MAX_PIXELS as f64 / pixels as f64).sqrt();
+        let target_width = ((width as f64 * scale) as u32).max(1);
+        let target_height = ((height as f64 * scale) as u32).max(1);
+        let filter = if pixel_art { FilterType::Nearest } else { FilterType::Triangle };
+        Arc::new(img.resize_exact(target_width, target_height, filter))
+    } else {
+        Arc::new(img)
+    };
+
+    let mut cache = PREVIEW_CACHE.lock().unwrap();
+    if cache.len() >= PREVIEW_CACHE_SIZE && !cache.contains_key(&key) {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, cached)| cached.last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(
+        key,
+        CachedPreview {
+            image: image.clone(),
+            width,
+            height,
+            last_used: Instant::now(),
+        },
+    );
+
+    Ok((image, width, height))
+}
+
+// Drop the cached copies of an upload once its files are deleted
+pub fn forget_preview(file_id: &str) {
+    let mut cache = PREVIEW_CACHE.lock().unwrap();
+    cache.retain(|(cached_id, _), _| cached_id != file_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blob::BLOB_STORE;
+    use crate::models::ConversionSettings;
+    use crate::utils::{generate_file_id, upload_dir};
+    use crate::validation::validate_settings;
+    use image::{ImageBuffer, ImageOutputFormat, Rgba};
+    use std::collections::HashSet;
+    use std::io::Cursor;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    // Upload an 800x600 checkerboard of 3 pixel squares, more than a preview traces
+    fn stored_checkerboard() -> (String, String) {
+        let image = DynamicImage::ImageRgba8(ImageBuffer::from_fn(800, 600, |x, y| {
+            if (x / 3 + y / 3) % 2 == 0 {
+                RED
+            } else {
+                BLUE
+            }
+        }));
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, ImageOutputFormat::Png).unwrap();
+
+        let file_id = generate_file_id();
+        let input_key = format!("{}/{}.png", upload_dir(), file_id);
+        BLOB_STORE.put(&input_key, png.get_ref()).unwrap();
+        (file_id, input_key)
+    }
+
+    #[test]
+    fn previews_are_traced_small_and_drawn_at_full_size() {
+        let (file_id, input_key) = stored_checkerboard();
+        let settings = validate_settings(&ConversionSettings::default()).unwrap();
+        let preview = render_preview(&file_id, &input_key, &settings).unwrap();
+
+        assert!(preview.preview_width < 800 && preview.preview_height < 600);
+        assert!(preview.preview_width as u64 * preview.preview_height as u64 <= PREVIEW_MAX_PIXELS);
+        assert!(preview.svg_data.contains("viewBox=\"0 0 800 600\""), "{}", &preview.svg_data[..200]);
+    }
+
+    #[test]
+    fn pixel_art_is_shrunk_without_blending_colors() {
+        let (file_id, input_key) = stored_checkerboard();
+        let colors = |pixel_art: bool| -> HashSet<Rgba<u8>> {
+            let (image, width, height) = preview_image(&file_id, &input_key, pixel_art).unwrap();
+            assert_eq!((width, height), (800, 600));
+            image.to_rgba8().pixels().copied().collect()
+        };
+
+        assert_eq!(colors(true), HashSet::from([RED, BLUE]));
+        assert!(colors(false).len() > 2);
+
+        // Both copies are cached, and forgetting the upload drops them together
+        assert_eq!(PREVIEW_CACHE.lock().unwrap().keys().filter(|(cached_id, _)| *cached_id == file_id).count(), 2);
+        forget_preview(&file_id);
+        assert!(PREVIEW_CACHE.lock().unwrap().keys().all(|(cached_id, _)| *cached_id != file_id));
+    }
+}
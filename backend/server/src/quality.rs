@@ -0,0 +1,177 @@
+// src/quality.rs
+use crate::geometry::VectorImage;
+use crate::models::{ColorCoverage, QualityReport};
+use crate::raster::rasterize;
+use crate::utils::format_hex_color;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
+use std::collections::HashMap;
+
+// Objective scores for a conversion, comparing the traced geometry with the image it was traced from
+
+// PSNR reported for identical images, which would otherwise be infinite
+const MAX_PSNR: f64 = 100.0;
+
+// SSIM windows and the step between them, in pixels
+const SSIM_WINDOW: usize = 8;
+const SSIM_STEP: usize = 4;
+
+// SSIM stabilising constants for 8-bit values
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+// Score the traced geometry against the preprocessed source image
+pub fn measure_quality(source: &DynamicImage, image: &VectorImage, svg_data: &str) -> QualityReport {
+    let (width, height) = source.dimensions();
+    let rendered = rasterize(image, width, height);
+
+    // Transparent areas are compared as the white page they show up on
+    let source = on_white(&source.to_rgba8());
+    let rendered = on_white(&rendered);
+
+    let palette = image.palette();
+    let visible: Vec<_> = image.paths.iter().filter(|path| !path.subpaths.is_empty()).collect();
+
+    QualityReport {
+        psnr: round_to(psnr(&source, &rendered), 2),
+        ssim: round_to(ssim(&source, &rendered, width as usize, height as usize), 4),
+        colors: color_coverage(&source, &rendered, &palette),
+        paths: visible.len(),
+        nodes: visible
+            .iter()
+            .flat_map(|path| &path.subpaths)
+            .map(|subpath| subpath.segments.len() + 1)
+            .sum(),
+        bytes: svg_data.len(),
+    }
+}
+
+fn on_white(rgba: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Vec<[f64; 3]> {
+    rgba.pixels()
+        .map(|pixel| {
+            let alpha = pixel[3] as f64 / 255.0;
+            [0, 1, 2].map(|channel| pixel[channel] as f64 * alpha + 255.0 * (1.0 - alpha))
+        })
+        .collect()
+}
+
+fn round_to(value: f64, decimals: i32) -> f64 {
+    let scale = 10f64.powi(decimals);
+    (value * scale).round() / scale
+}
+
+fn psnr(source: &[[f64; 3]], rendered: &[[f64; 3]]) -> f64 {
+    if source.is_empty() {
+        return MAX_PSNR;
+    }
+
+    let squared_error: f64 = source
+        .iter()
+        .zip(rendered)
+        .map(|(a, b)| (0..3).map(|channel| (a[channel] - b[channel]).powi(2)).sum::<f64>())
+        .sum();
+    let mse = squared_error / (source.len() * 3) as f64;
+
+    if mse == 0.0 {
+        MAX_PSNR
+    } else {
+        (10.0 * (255.0 * 255.0 / mse).log10()).min(MAX_PSNR)
+    }
+}
+
+// Mean SSIM of the luma over overlapping square windows
+fn ssim(source: &[[f64; 3]], rendered: &[[f64; 3]], width: usize, height: usize) -> f64 {
+    let luma = |pixels: &[[f64; 3]]| -> Vec<f64> {
+        pixels
+            .iter()
+            .map(|[r, g, b]| 0.299 * r + 0.587 * g + 0.114 * b)
+            .collect()
+    };
+    let (a, b) = (luma(source), luma(rendered));
+
+    // Images smaller than a window are scored as one window
+    let window_width = SSIM_WINDOW.min(width);
+    let window_height = SSIM_WINDOW.min(height);
+    if window_width == 0 || window_height == 0 {
+        return 1.0;
+    }
+
+    let mut total = 0.0;
+    let mut windows = 0usize;
+    for top in (0..=height - window_height).step_by(SSIM_STEP) {
+        for left in (0..=width - window_width).step_by(SSIM_STEP) {
+            let indices = (top..top + window_height)
+                .flat_map(|y| (left..left + window_width).map(move |x| y * width + x));
+
+            let count = (window_width * window_height) as f64;
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for index in indices {
+                let (va, vb) = (a[index], b[index]);
+                sum_a += va;
+                sum_b += vb;
+                sum_aa += va * va;
+                sum_bb += vb * vb;
+                sum_ab += va * vb;
+            }
+
+            let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+            let variance_a = sum_aa / count - mean_a * mean_a;
+            let variance_b = sum_bb / count - mean_b * mean_b;
+            let covariance = sum_ab / count - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (variance_a + variance_b + SSIM_C2));
+            windows += 1;
+        }
+    }
+
+    total / windows as f64
+}
+
+// Assign every pixel of both images to the nearest traced color, or to the white page, and
+// compare where each color ends up
+fn color_coverage(source: &[[f64; 3]], rendered: &[[f64; 3]], palette: &[[u8; 3]]) -> Vec<ColorCoverage> {
+    let mut candidates: Vec<[f64; 3]> = palette.iter().map(|color| color.map(f64::from)).collect();
+    candidates.push([255.0; 3]);
+
+    let mut cache: HashMap<[u8; 3], usize> = HashMap::new();
+    let mut classify = |pixel: &[f64; 3]| -> usize {
+        let key = pixel.map(|channel| channel.round() as u8);
+        *cache.entry(key).or_insert_with(|| {
+            candidates
+                .iter()
+                .enumerate()
+                .map(|(index, candidate)| {
+                    let distance: f64 = (0..3).map(|channel| (candidate[channel] - pixel[channel]).powi(2)).sum();
+                    (index, distance)
+                })
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+                .map(|(index, _)| index)
+                .unwrap_or(0)
+        })
+    };
+
+    let mut source_counts = vec![0usize; palette.len() + 1];
+    let mut output_counts = vec![0usize; palette.len() + 1];
+    let mut mismatches = vec![0usize; palette.len() + 1];
+    for (a, b) in source.iter().zip(rendered) {
+        let (in_source, in_output) = (classify(a), classify(b));
+        source_counts[in_source] += 1;
+        output_counts[in_output] += 1;
+        if in_source != in_output {
+            mismatches[in_source] += 1;
+            mismatches[in_output] += 1;
+        }
+    }
+
+    let total = source.len().max(1) as f64;
+    palette
+        .iter()
+        .enumerate()
+        .map(|(index, color)| ColorCoverage {
+            color: format_hex_color(*color),
+            source_coverage: round_to(source_counts[index] as f64 / total, 4),
+            output_coverage: round_to(output_counts[index] as f64 / total, 4),
+            error: round_to(mismatches[index] as f64 / total, 4),
+        })
+        .collect()
+}
@@ -0,0 +1,432 @@
+// src/quantize.rs
+use crate::models::Quantization;
+use image::{DynamicImage, ImageBuffer, Rgba};
+use std::collections::HashMap;
+
+// Color quantisation: reduce an image to a palette of exactly N colors, or snap it to a given palette
+
+// Colors are clustered at 5 bits per channel, which keeps photos to at most 32768 distinct entries
+const CLUSTER_BITS: u32 = 5;
+
+// K-means stops after this many rounds even if the centers still move
+const KMEANS_ITERATIONS: usize = 16;
+
+// Squared distance a color needs from every palette entry to be added as a new one
+const MIN_FILL_DISTANCE: f64 = 3.0;
+
+// Octrees stop splitting after this many levels, one per bit of each channel
+const OCTREE_DEPTH: usize = 8;
+
+// A distinct color and how many pixels have it
+#[derive(Debug, Clone, Copy)]
+struct WeightedColor {
+    color: [f64; 3],
+    weight: f64,
+}
+
+// Reduce the opaque pixels of an image to at most `count` colors and return the palette used
+pub fn quantize_image(img: &DynamicImage, method: Quantization, count: usize) -> (DynamicImage, Vec<[u8; 3]>) {
+    let rgba = img.to_rgba8();
+    let colors = color_histogram(&rgba);
+    if colors.is_empty() {
+        return (img.clone(), Vec::new());
+    }
+
+    let count = count.min(colors.len()).max(1);
+    let palette = match method {
+        Quantization::KMeans => kmeans(&colors, count),
+        Quantization::MedianCut => median_cut(&colors, count),
+        Quantization::Octree => octree(&colors, count),
+    };
+
+    // Methods can end up short when colors merge; add the worst served colors until there are N
+    let palette = fill_palette(palette, &colors, count);
+    let palette: Vec<[u8; 3]> = palette.iter().map(|color| color.map(|channel| channel.round().clamp(0.0, 255.0) as u8)).collect();
+
+    snap_to_palette(&rgba, &palette)
+}
+
+// Replace every opaque pixel with the nearest palette color and return the colors actually used
+pub fn snap_image_to_palette(img: &DynamicImage, palette: &[[u8; 3]]) -> (DynamicImage, Vec<[u8; 3]>) {
+    snap_to_palette(&img.to_rgba8(), palette)
+}
+
+fn snap_to_palette(rgba: &ImageBuffer<Rgba<u8>, Vec<u8>>, palette: &[[u8; 3]]) -> (DynamicImage, Vec<[u8; 3]>) {
+    let mut output = rgba.clone();
+    let mut nearest_cache: HashMap<[u8; 3], usize> = HashMap::new();
+    let mut usage = vec![0u64; palette.len()];
+
+    for pixel in output.pixels_mut() {
+        if pixel[3] < 128 || palette.is_empty() {
+            continue;
+        }
+
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let index = *nearest_cache.entry(color).or_insert_with(|| {
+            nearest(palette.iter().map(|entry| entry.map(f64::from)), color.map(f64::from))
+        });
+
+        let [r, g, b] = palette[index];
+        *pixel = Rgba([r, g, b, pixel[3]]);
+        usage[index] += 1;
+    }
+
+    // Most used colors first
+    let mut used: Vec<([u8; 3], u64)> = palette
+        .iter()
+        .copied()
+        .zip(usage)
+        .filter(|(_, pixels)| *pixels > 0)
+        .collect();
+    used.sort_by_key(|(_, pixels)| std::cmp::Reverse(*pixels));
+
+    (
+        DynamicImage::ImageRgba8(output),
+        used.into_iter().map(|(color, _)| color).collect(),
+    )
+}
+
+// Distinct opaque colors, bucketed to CLUSTER_BITS per channel, each at the mean of its bucket
+fn color_histogram(rgba: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Vec<WeightedColor> {
+    let shift = 8 - CLUSTER_BITS;
+    let mut buckets: HashMap<[u8; 3], ([u64; 3], u64)> = HashMap::new();
+
+    for pixel in rgba.pixels().filter(|pixel| pixel[3] >= 128) {
+        let entry = buckets
+            .entry([pixel[0] >> shift, pixel[1] >> shift, pixel[2] >> shift])
+            .or_insert(([0; 3], 0));
+        for channel in 0..3 {
+            entry.0[channel] += pixel[channel] as u64;
+        }
+        entry.1 += 1;
+    }
+
+    let mut colors: Vec<WeightedColor> = buckets
+        .into_values()
+        .map(|(sums, pixels)| WeightedColor {
+            color: sums.map(|sum| sum as f64 / pixels as f64),
+            weight: pixels as f64,
+        })
+        .collect();
+
+    // HashMap order is random; sort so the same image always gives the same palette
+    colors.sort_by(|a, b| {
+        b.weight
+            .partial_cmp(&a.weight)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.color.partial_cmp(&b.color).unwrap_or(std::cmp::Ordering::Equal))
+    });
+    colors
+}
+
+fn distance_squared(a: [f64; 3], b: [f64; 3]) -> f64 {
+    (0..3).map(|channel| (a[channel] - b[channel]).powi(2)).sum()
+}
+
+// Index of the palette entry closest to `color`
+fn nearest<I: Iterator<Item = [f64; 3]>>(palette: I, color: [f64; 3]) -> usize {
+    palette
+        .enumerate()
+        .map(|(index, entry)| (index, distance_squared(entry, color)))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(index, _)| index)
+        .unwrap_or(0)
+}
+
+// Add the colors furthest from the palette, weighted by how many pixels they cover, until it has `count` entries
+fn fill_palette(palette: Vec<[f64; 3]>, colors: &[WeightedColor], count: usize) -> Vec<[f64; 3]> {
+    // Entries that round to the same color would leave the palette short
+    let rounded = |color: &[f64; 3]| color.map(|channel| channel.round() as i64);
+    let mut unique: Vec<[f64; 3]> = Vec::with_capacity(count);
+    for color in palette {
+        if !unique.iter().any(|existing| rounded(existing) == rounded(&color)) {
+            unique.push(color);
+        }
+    }
+    let mut palette = unique;
+
+    while palette.len() < count {
+        // Only colors at least one step away in some channel still round to a new entry
+        let worst = colors
+            .iter()
+            .filter_map(|entry| {
+                let index = nearest(palette.iter().copied(), entry.color);
+                let distance = palette
+                    .get(index)
+                    .map(|center| distance_squared(*center, entry.color))
+                    .unwrap_or(f64::MAX);
+                (distance >= MIN_FILL_DISTANCE).then_some((entry.color, distance * entry.weight))
+            })
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        match worst {
+            Some((color, _)) => palette.push(color),
+            None => break,
+        }
+    }
+
+    palette
+}
+
+// Weighted k-means, seeded with the most common color and then the colors furthest from the centers so far
+fn kmeans(colors: &[WeightedColor], count: usize) -> Vec<[f64; 3]> {
+    let mut centers = fill_palette(vec![colors[0].color], colors, count);
+    let mut assignments = vec![0usize; colors.len()];
+
+    for _ in 0..KMEANS_ITERATIONS {
+        for (entry, assignment) in colors.iter().zip(assignments.iter_mut()) {
+            *assignment = nearest(centers.iter().copied(), entry.color);
+        }
+
+        let mut sums = vec![([0.0; 3], 0.0); centers.len()];
+        for (entry, &assignment) in colors.iter().zip(&assignments) {
+            for channel in 0..3 {
+                sums[assignment].0[channel] += entry.color[channel] * entry.weight;
+            }
+            sums[assignment].1 += entry.weight;
+        }
+
+        // Empty clusters keep their center; fill_palette replaces any duplicates afterwards
+        let mut moved = 0.0f64;
+        for (center, (sum, weight)) in centers.iter_mut().zip(sums) {
+            if weight > 0.0 {
+                let updated = sum.map(|channel| channel / weight);
+                moved = moved.max(distance_squared(*center, updated));
+                *center = updated;
+            }
+        }
+
+        if moved < 0.25 {
+            break;
+        }
+    }
+
+    centers
+}
+
+// Median cut: keep splitting the box with the widest channel range at its weighted median
+fn median_cut(colors: &[WeightedColor], count: usize) -> Vec<[f64; 3]> {
+    let mut boxes: Vec<Vec<WeightedColor>> = vec![colors.to_vec()];
+
+    while boxes.len() < count {
+        // The box whose widest channel spans the most, among those that can still be split
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, entries)| entries.len() > 1)
+            .map(|(index, entries)| {
+                let (channel, range) = widest_channel(entries);
+                (index, channel, range)
+            })
+            .max_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal));
+
+        let (index, channel, _) = match widest {
+            Some(widest) => widest,
+            None => break,
+        };
+
+        let mut entries = boxes.swap_remove(index);
+        entries.sort_by(|a, b| a.color[channel].partial_cmp(&b.color[channel]).unwrap_or(std::cmp::Ordering::Equal));
+
+        // Split where half of the pixels fall on each side, keeping at least one color per side
+        let half = entries.iter().map(|entry| entry.weight).sum::<f64>() / 2.0;
+        let mut running = 0.0;
+        let mut split = 1;
+        for (position, entry) in entries.iter().enumerate() {
+            running += entry.weight;
+            if running >= half {
+                split = (position + 1).clamp(1, entries.len() - 1);
+                break;
+            }
+        }
+
+        let upper = entries.split_off(split);
+        boxes.push(entries);
+        boxes.push(upper);
+    }
+
+    boxes.iter().map(|entries| weighted_mean(entries)).collect()
+}
+
+fn widest_channel(entries: &[WeightedColor]) -> (usize, f64) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = entries.iter().fold((f64::MAX, f64::MIN), |(min, max), entry| {
+                (min.min(entry.color[channel]), max.max(entry.color[channel]))
+            });
+            (channel, max - min)
+        })
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .unwrap_or((0, 0.0))
+}
+
+fn weighted_mean(entries: &[WeightedColor]) -> [f64; 3] {
+    let weight: f64 = entries.iter().map(|entry| entry.weight).sum();
+    let mut mean = [0.0; 3];
+    for entry in entries {
+        for (channel, value) in mean.iter_mut().enumerate() {
+            *value += entry.color[channel] * entry.weight / weight;
+        }
+    }
+    mean
+}
+
+// A node of the octree: either a leaf holding a color sum, or up to 8 children
+#[derive(Default)]
+struct OctreeNode {
+    children: [Option<usize>; 8],
+    sum: [f64; 3],
+    weight: f64,
+    level: usize,
+    leaf: bool,
+}
+
+// Octree quantisation: insert every color, then fold the least used deepest nodes into their
+// parents until no more than `count` leaves remain
+fn octree(colors: &[WeightedColor], count: usize) -> Vec<[f64; 3]> {
+    let mut nodes = vec![OctreeNode::default()];
+
+    for entry in colors {
+        let color = entry.color.map(|channel| channel.round().clamp(0.0, 255.0) as u8);
+        for channel in 0..3 {
+            nodes[0].sum[channel] += entry.color[channel] * entry.weight;
+        }
+        nodes[0].weight += entry.weight;
+
+        let mut node = 0;
+        for level in 0..OCTREE_DEPTH {
+            let bit = 7 - level;
+            let child = (((color[0] >> bit) & 1) << 2 | ((color[1] >> bit) & 1) << 1 | ((color[2] >> bit) & 1)) as usize;
+
+            node = match nodes[node].children[child] {
+                Some(existing) => existing,
+                None => {
+                    nodes.push(OctreeNode {
+                        level: level + 1,
+                        leaf: level + 1 == OCTREE_DEPTH,
+                        ..OctreeNode::default()
+                    });
+                    let created = nodes.len() - 1;
+                    nodes[node].children[child] = Some(created);
+                    created
+                }
+            };
+
+            for channel in 0..3 {
+                nodes[node].sum[channel] += entry.color[channel] * entry.weight;
+            }
+            nodes[node].weight += entry.weight;
+        }
+    }
+
+    // Fold from the deepest level up; once a level is done every node below it is a leaf
+    let mut leaves = nodes.iter().filter(|node| node.leaf).count();
+    for level in (0..OCTREE_DEPTH).rev() {
+        if leaves <= count {
+            break;
+        }
+
+        let mut parents: Vec<usize> = (0..nodes.len())
+            .filter(|&index| nodes[index].level == level && !nodes[index].leaf)
+            .collect();
+        parents.sort_by(|&a, &b| nodes[a].weight.partial_cmp(&nodes[b].weight).unwrap_or(std::cmp::Ordering::Equal));
+
+        for index in parents {
+            if leaves <= count {
+                break;
+            }
+
+            let children: Vec<usize> = nodes[index].children.iter().flatten().copied().collect();
+            leaves = leaves + 1 - children.len();
+            for child in children {
+                nodes[child].leaf = false;
+                nodes[child].weight = 0.0;
+            }
+            nodes[index].children = [None; 8];
+            nodes[index].leaf = true;
+        }
+    }
+
+    nodes
+        .iter()
+        .filter(|node| node.leaf && node.weight > 0.0)
+        .map(|node| node.sum.map(|channel| channel / node.weight))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    // A smooth gradient with thousands of distinct colors
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(64, 64, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8, 255])
+        }))
+    }
+
+    fn distinct_colors(img: &DynamicImage) -> HashSet<[u8; 3]> {
+        img.to_rgba8()
+            .pixels()
+            .filter(|pixel| pixel[3] >= 128)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect()
+    }
+
+    #[test]
+    fn every_method_gives_exactly_the_color_count() {
+        for method in [Quantization::KMeans, Quantization::MedianCut, Quantization::Octree] {
+            for count in [1, 2, 5, 8, 16, 33] {
+                let (reduced, palette) = quantize_image(&gradient(), method, count);
+
+                assert_eq!(palette.len(), count, "{:?} to {}", method, count);
+                assert_eq!(distinct_colors(&reduced), palette.iter().copied().collect(), "{:?} to {}", method, count);
+            }
+        }
+    }
+
+    #[test]
+    fn images_with_fewer_colors_keep_them() {
+        let two_colors = DynamicImage::ImageRgba8(ImageBuffer::from_fn(8, 8, |x, _| {
+            if x < 4 {
+                Rgba([10, 20, 30, 255])
+            } else {
+                Rgba([200, 100, 0, 255])
+            }
+        }));
+
+        for method in [Quantization::KMeans, Quantization::MedianCut, Quantization::Octree] {
+            let (reduced, palette) = quantize_image(&two_colors, method, 8);
+            assert_eq!(palette.len(), 2, "{:?}", method);
+            assert_eq!(distinct_colors(&reduced), distinct_colors(&two_colors), "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn snapping_maps_pixels_onto_the_palette() {
+        let palette = [[0, 0, 0], [255, 255, 255], [255, 0, 0], [0, 0, 255]];
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(3, 2, |x, y| match (x, y) {
+            (0, 0) => Rgba([30, 20, 10, 255]),
+            (1, 0) => Rgba([230, 240, 250, 255]),
+            (2, 0) => Rgba([200, 40, 30, 255]),
+            (0, 1) => Rgba([190, 50, 40, 128]),
+            _ => Rgba([0, 0, 255, 0]),
+        }));
+
+        let (snapped, used) = snap_image_to_palette(&img, &palette);
+        let snapped = snapped.to_rgba8();
+
+        assert_eq!(snapped.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+        assert_eq!(snapped.get_pixel(1, 0), &Rgba([255, 255, 255, 255]));
+        assert_eq!(snapped.get_pixel(2, 0), &Rgba([255, 0, 0, 255]));
+        // Alpha is kept, and transparent pixels are left alone
+        assert_eq!(snapped.get_pixel(0, 1), &Rgba([255, 0, 0, 128]));
+        assert_eq!(snapped.get_pixel(1, 1), &Rgba([0, 0, 255, 0]));
+
+        // Only the entries in use are returned, most used first
+        assert_eq!(used[0], [255, 0, 0]);
+        assert_eq!(used.len(), 3);
+        assert!(!used.contains(&[0, 0, 255]));
+    }
+}
@@ -0,0 +1,190 @@
+// src/raster.rs
+use crate::geometry::{Point, VectorImage};
+use image::{ImageBuffer, Rgba};
+use std::f64::consts::PI;
+
+// Software rasteriser for traced geometry: draws the paths as the SVG shows them, with the
+// non-zero fill rule, round stroke caps and joins, and anti-aliased edges
+
+// Samples per pixel along each axis
+const SUBSAMPLES: usize = 4;
+
+// Largest distance between a curve and its flattened polyline, in pixels
+const FLATTEN_TOLERANCE: f64 = 0.1;
+
+// Sides of the polygons standing in for round caps and joins
+const ROUND_SIDES: usize = 16;
+
+// An edge of a polygon, stored top to bottom with the winding of its original direction
+struct Edge {
+    x0: f64,
+    y0: f64,
+    x1: f64,
+    y1: f64,
+    winding: i32,
+}
+
+// Render the paths over a transparent canvas, in the image's pixel units and cropped to `width` x `height`
+pub fn rasterize(image: &VectorImage, width: u32, height: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let (canvas_width, canvas_height) = (width, height);
+    let (width, height) = (width as usize, height as usize);
+    let mut canvas = vec![[0.0f32; 4]; width * height];
+
+    for path in &image.paths {
+        let polygons = match path.stroke_width {
+            Some(stroke_width) => stroke_polygons(
+                path.subpaths.iter().map(|subpath| subpath.flatten(FLATTEN_TOLERANCE)),
+                stroke_width,
+            ),
+            None => path.subpaths.iter().map(|subpath| subpath.flatten(FLATTEN_TOLERANCE)).collect(),
+        };
+
+        let color = path.color.map(|channel| channel as f32 / 255.0);
+        fill_polygons(&polygons, width, height, |x, y, coverage| {
+            let pixel = &mut canvas[y * width + x];
+            for channel in 0..3 {
+                pixel[channel] = color[channel] * coverage + pixel[channel] * (1.0 - coverage);
+            }
+            pixel[3] = coverage + pixel[3] * (1.0 - coverage);
+        });
+    }
+
+    // The canvas holds premultiplied colors
+    ImageBuffer::from_fn(canvas_width, canvas_height, |x, y| {
+        let [r, g, b, a] = canvas[y as usize * width + x as usize];
+        let unpremultiply = |channel: f32| if a > 0.0 { channel / a } else { 0.0 };
+        let to_byte = |channel: f32| (channel * 255.0).round().clamp(0.0, 255.0) as u8;
+        Rgba([to_byte(unpremultiply(r)), to_byte(unpremultiply(g)), to_byte(unpremultiply(b)), to_byte(a)])
+    })
+}
+
+// Fill the polygons together with the non-zero rule, calling `blend` with the covered share of every touched pixel.
+// Each polygon is closed implicitly, as SVG does when filling.
+fn fill_polygons<F: FnMut(usize, usize, f32)>(polygons: &[Vec<Point>], width: usize, height: usize, mut blend: F) {
+    let scale = SUBSAMPLES as f64;
+    let mut edges: Vec<Edge> = Vec::new();
+    for polygon in polygons.iter().filter(|polygon| polygon.len() >= 2) {
+        for (index, from) in polygon.iter().enumerate() {
+            let to = polygon[(index + 1) % polygon.len()];
+            if from.y == to.y {
+                continue;
+            }
+
+            let (top, bottom, winding) = if from.y < to.y { (*from, to, 1) } else { (to, *from, -1) };
+            edges.push(Edge {
+                x0: top.x * scale,
+                y0: top.y * scale,
+                x1: bottom.x * scale,
+                y1: bottom.y * scale,
+                winding,
+            });
+        }
+    }
+    if edges.is_empty() {
+        return;
+    }
+    edges.sort_by(|a, b| a.y0.partial_cmp(&b.y0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let sample_width = width * SUBSAMPLES;
+    let first_row = (edges[0].y0.floor().max(0.0) as usize) / SUBSAMPLES;
+    let last_row = edges
+        .iter()
+        .map(|edge| edge.y1)
+        .fold(0.0f64, f64::max)
+        .ceil()
+        .min((height * SUBSAMPLES) as f64) as usize;
+
+    let mut next_edge = 0;
+    let mut active: Vec<usize> = Vec::new();
+    let mut crossings: Vec<(f64, i32)> = Vec::new();
+    let mut counts = vec![0u16; width];
+
+    for row in first_row..height {
+        if row * SUBSAMPLES >= last_row {
+            break;
+        }
+
+        let (mut left, mut right) = (usize::MAX, 0);
+        for sample_row in row * SUBSAMPLES..(row + 1) * SUBSAMPLES {
+            let y = sample_row as f64 + 0.5;
+
+            while next_edge < edges.len() && edges[next_edge].y0 <= y {
+                active.push(next_edge);
+                next_edge += 1;
+            }
+            active.retain(|&index| edges[index].y1 > y);
+
+            crossings.clear();
+            crossings.extend(active.iter().map(|&index| {
+                let edge = &edges[index];
+                (edge.x0 + (y - edge.y0) * (edge.x1 - edge.x0) / (edge.y1 - edge.y0), edge.winding)
+            }));
+            crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+            // Sample columns whose centers fall inside a span with non-zero winding
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                if winding == 0 {
+                    continue;
+                }
+
+                let start = (pair[0].0 - 0.5).ceil().max(0.0) as usize;
+                let end = ((pair[1].0 - 0.5).ceil().max(0.0) as usize).min(sample_width);
+                for column in start..end {
+                    counts[column / SUBSAMPLES] += 1;
+                }
+                if start < end {
+                    left = left.min(start / SUBSAMPLES);
+                    right = right.max((end - 1) / SUBSAMPLES + 1);
+                }
+            }
+        }
+
+        for (x, count) in counts.iter_mut().enumerate().take(right).skip(left.min(right)) {
+            if *count > 0 {
+                blend(x, row, *count as f32 / (SUBSAMPLES * SUBSAMPLES) as f32);
+                *count = 0;
+            }
+        }
+    }
+}
+
+// Outline a stroke as polygons of the same orientation, so filling them non-zero gives their union:
+// a rectangle for every segment and a round cap or join at every point
+fn stroke_polygons<I: Iterator<Item = Vec<Point>>>(lines: I, stroke_width: f64) -> Vec<Vec<Point>> {
+    let radius = stroke_width / 2.0;
+    let mut polygons = Vec::new();
+
+    for points in lines {
+        for point in &points {
+            polygons.push(
+                (0..ROUND_SIDES)
+                    .map(|side| {
+                        let angle = side as f64 * 2.0 * PI / ROUND_SIDES as f64;
+                        Point::new(point.x + radius * angle.cos(), point.y + radius * angle.sin())
+                    })
+                    .collect(),
+            );
+        }
+
+        for pair in points.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            let length = (to.x - from.x).hypot(to.y - from.y);
+            if length == 0.0 {
+                continue;
+            }
+
+            // Corners in the same clockwise order as the caps
+            let (nx, ny) = (-(to.y - from.y) / length * radius, (to.x - from.x) / length * radius);
+            polygons.push(vec![
+                Point::new(from.x - nx, from.y - ny),
+                Point::new(to.x - nx, to.y - ny),
+                Point::new(to.x + nx, to.y + ny),
+                Point::new(from.x + nx, from.y + ny),
+            ]);
+        }
+    }
+
+    polygons
+}
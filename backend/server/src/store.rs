@@ -0,0 +1,203 @@
+// src/store.rs
+use crate::config::{config, MetadataBackend};
+use crate::models::FileMetadata;
+use log::{error, info};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use thiserror::Error;
+
+// Where file metadata is kept, so that stored files outlive a restart
+
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("Metadata store error: {0}")]
+    Backend(String),
+
+    #[error("Corrupt metadata for {0}: {1}")]
+    Corrupt(String, String),
+}
+
+// Storage for the metadata of every stored file, keyed by file ID
+pub trait MetadataStore: Send + Sync {
+    fn get(&self, file_id: &str) -> Result<Option<FileMetadata>, StoreError>;
+
+    fn insert(&self, file_id: &str, metadata: &FileMetadata) -> Result<(), StoreError>;
+
+    // Change a record in place without racing other changes; returns the record after the change,
+    // or None when there is no such file
+    fn update(
+        &self,
+        file_id: &str,
+        change: &mut dyn FnMut(&mut FileMetadata),
+    ) -> Result<Option<FileMetadata>, StoreError>;
+
+    fn remove(&self, file_id: &str) -> Result<(), StoreError>;
+
+    fn list(&self) -> Result<Vec<(String, FileMetadata)>, StoreError>;
+}
+
+// Metadata kept in memory only, forgotten on restart
+pub struct MemoryMetadataStore {
+    files: Mutex<HashMap<String, FileMetadata>>,
+}
+
+impl MemoryMetadataStore {
+    pub fn new() -> Self {
+        Self {
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl MetadataStore for MemoryMetadataStore {
+    fn get(&self, file_id: &str) -> Result<Option<FileMetadata>, StoreError> {
+        let files = self.files.lock().unwrap();
+        Ok(files.get(file_id).cloned())
+    }
+
+    fn insert(&self, file_id: &str, metadata: &FileMetadata) -> Result<(), StoreError> {
+        let mut files = self.files.lock().unwrap();
+        files.insert(file_id.to_string(), metadata.clone());
+        Ok(())
+    }
+
+    fn update(
+        &self,
+        file_id: &str,
+        change: &mut dyn FnMut(&mut FileMetadata),
+    ) -> Result<Option<FileMetadata>, StoreError> {
+        let mut files = self.files.lock().unwrap();
+        Ok(files.get_mut(file_id).map(|metadata| {
+            change(metadata);
+            metadata.clone()
+        }))
+    }
+
+    fn remove(&self, file_id: &str) -> Result<(), StoreError> {
+        let mut files = self.files.lock().unwrap();
+        files.remove(file_id);
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<(String, FileMetadata)>, StoreError> {
+        let files = self.files.lock().unwrap();
+        Ok(files
+            .iter()
+            .map(|(file_id, metadata)| (file_id.clone(), metadata.clone()))
+            .collect())
+    }
+}
+
+// Metadata in an embedded sled database, stored as JSON per file ID
+pub struct SledMetadataStore {
+    db: sled::Db,
+}
+
+impl SledMetadataStore {
+    pub fn open(path: &str) -> Result<Self, StoreError> {
+        let db = sled::open(path).map_err(backend_error)?;
+        Ok(Self { db })
+    }
+}
+
+fn backend_error(e: sled::Error) -> StoreError {
+    StoreError::Backend(e.to_string())
+}
+
+fn decode(file_id: &str, bytes: &[u8]) -> Result<FileMetadata, StoreError> {
+    serde_json::from_slice(bytes).map_err(|e| StoreError::Corrupt(file_id.to_string(), e.to_string()))
+}
+
+fn encode(metadata: &FileMetadata) -> Vec<u8> {
+    // FileMetadata only holds strings, numbers and a timestamp, which always serialize
+    serde_json::to_vec(metadata).unwrap()
+}
+
+impl MetadataStore for SledMetadataStore {
+    fn get(&self, file_id: &str) -> Result<Option<FileMetadata>, StoreError> {
+        match self.db.get(file_id).map_err(backend_error)? {
+            Some(bytes) => decode(file_id, &bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn insert(&self, file_id: &str, metadata: &FileMetadata) -> Result<(), StoreError> {
+        self.db.insert(file_id, encode(metadata)).map_err(backend_error)?;
+        self.db.flush().map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn update(
+        &self,
+        file_id: &str,
+        change: &mut dyn FnMut(&mut FileMetadata),
+    ) -> Result<Option<FileMetadata>, StoreError> {
+        // sled retries the closure if another update gets in first, so each attempt starts from
+        // the stored bytes
+        let mut corrupt = None;
+        let updated = self
+            .db
+            .update_and_fetch(file_id, |old| {
+                let old = old?;
+                match decode(file_id, old) {
+                    Ok(mut metadata) => {
+                        change(&mut metadata);
+                        Some(encode(&metadata))
+                    }
+                    Err(e) => {
+                        // Leave a record that can't be read as it is
+                        corrupt = Some(e);
+                        Some(old.to_vec())
+                    }
+                }
+            })
+            .map_err(backend_error)?;
+
+        if let Some(e) = corrupt {
+            return Err(e);
+        }
+        self.db.flush().map_err(backend_error)?;
+
+        match updated {
+            Some(bytes) => decode(file_id, &bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn remove(&self, file_id: &str) -> Result<(), StoreError> {
+        self.db.remove(file_id).map_err(backend_error)?;
+        self.db.flush().map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<(String, FileMetadata)>, StoreError> {
+        let mut files = Vec::new();
+        for entry in self.db.iter() {
+            let (key, bytes) = entry.map_err(backend_error)?;
+            let file_id = String::from_utf8_lossy(&key).to_string();
+
+            // One unreadable record shouldn't hide every other file
+            match decode(&file_id, &bytes) {
+                Ok(metadata) => files.push((file_id, metadata)),
+                Err(e) => error!("{}", e),
+            }
+        }
+        Ok(files)
+    }
+}
+
+// Open the store picked by metadata.store: "sled" (default) at metadata.path, or "memory"
+pub fn open_metadata_store() -> Result<Box<dyn MetadataStore>, StoreError> {
+    let metadata = &config().metadata;
+
+    match metadata.store {
+        MetadataBackend::Memory => {
+            info!("Keeping file metadata in memory only");
+            Ok(Box::new(MemoryMetadataStore::new()))
+        }
+        MetadataBackend::Sled => {
+            info!("Opening file metadata store at {}", metadata.path);
+            Ok(Box::new(SledMetadataStore::open(&metadata.path)?))
+        }
+    }
+}
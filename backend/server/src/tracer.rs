@@ -0,0 +1,252 @@
+// src/tracer.rs
+use crate::centerline::CenterlineTracer;
+use crate::geometry::{Point, Segment, Subpath, VectorImage, VectorPath};
+use crate::models::{Binarization, ConversionStage, CurveMode, Engine, OutputMode, ValidatedSettings};
+use crate::pixel::PixelTracer;
+use crate::potrace::PotraceTracer;
+use crate::vectorizer::binarize_image;
+use image::{DynamicImage, GenericImageView};
+use visioncortex::{CompoundPathElement, PathSimplifyMode};
+use vtracer::{ColorImage, ColorMode, Config, SvgFile};
+
+// Share of the overall progress covered by tracing
+pub const TRACE_PROGRESS_START: u8 = 20;
+pub const TRACE_PROGRESS_END: u8 = 90;
+
+// A tracing backend: turns a preprocessed image into paths
+pub trait Tracer {
+    // Name used in logs
+    fn name(&self) -> &'static str;
+    
+    // Trace the image, which has been preprocessed but not binarized
+    fn trace(
+        &self,
+        img: &DynamicImage,
+        settings: &ValidatedSettings,
+        on_progress: &mut dyn FnMut(ConversionStage, u8),
+    ) -> Result<TraceOutput, String>;
+}
+
+// Paths traced by a tracer
+pub struct TraceOutput {
+    pub vector_image: VectorImage,
+    // The black and white image the paths were traced from, when the tracer binarized its input
+    pub binarized: Option<DynamicImage>,
+}
+
+// Get the tracer for an engine
+pub fn create_tracer(engine: Engine) -> Box<dyn Tracer> {
+    match engine {
+        Engine::Vtracer => Box::new(VtracerTracer),
+        Engine::Potrace => Box::new(PotraceTracer),
+        Engine::Centerline => Box::new(CenterlineTracer),
+        Engine::Pixel => Box::new(PixelTracer),
+    }
+}
+
+// Filled outlines traced by vtracer, in black and white or color
+pub struct VtracerTracer;
+
+impl Tracer for VtracerTracer {
+    fn name(&self) -> &'static str {
+        "vtracer"
+    }
+    
+    fn trace(
+        &self,
+        img: &DynamicImage,
+        settings: &ValidatedSettings,
+        on_progress: &mut dyn FnMut(ConversionStage, u8),
+    ) -> Result<TraceOutput, String> {
+        // Reduce to black and white ourselves so the threshold is a real luminance cutoff
+        let binarized = (settings.output_mode == OutputMode::Bw).then(|| binarize_image(img, settings));
+        
+        // Create vtracer config based on settings
+        let config = create_vtracer_config(settings);
+        
+        // Convert image to SVG
+        let svg_file = convert_with_vtracer(binarized.as_ref().unwrap_or(img), config, on_progress)?;
+        Ok(TraceOutput {
+            vector_image: to_vector_image(&svg_file),
+            binarized,
+        })
+    }
+}
+
+// Create vtracer config from settings
+fn create_vtracer_config(settings: &ValidatedSettings) -> Config {
+    let mut config = Config::default();
+    
+    // Set color mode based on output_mode
+    if settings.output_mode == OutputMode::Bw {
+        // The image is already binarized; per-channel thresholding leaves up to 8 colors
+        config.color_mode = if settings.binarization == Binarization::PerChannel {
+            ColorMode::Color
+        } else {
+            ColorMode::Binary
+        };
+    } else {
+        config.color_mode = ColorMode::Color;
+        
+        // A reduced image already has its final colors, so keep them all; otherwise map the
+        // color count to vtracer's significant bits per channel, 1-8
+        config.color_precision = if settings.quantization.is_some() || settings.palette.is_some() {
+            8
+        } else {
+            (settings.color_count as i32).clamp(1, 8)
+        };
+    }
+    
+    // Set path simplification
+    // Map 0-10 scale to vtracer's expected values
+    let factor = settings.path_simplification as f64 / 10.0;
+    config.path_precision = Some((100.0 - (factor * 90.0)) as u32); // Higher precision = less simplification
+    
+    // Set corner threshold
+    config.corner_threshold = settings.corner_threshold as i32;
+    
+    // Set curve mode
+    config.mode = match settings.curve_mode {
+        CurveMode::Polygon => PathSimplifyMode::Polygon,
+        CurveMode::Spline => PathSimplifyMode::Spline,
+    };
+    
+    // Set filter speckle (noise reduction)
+    // Map 0-10 scale to vtracer's expected values
+    config.filter_speckle = settings.noise_reduction as usize * 2;
+    
+    config
+}
+
+// Convert image using vtracer. It traces in one call, so progress is reported as it starts and ends.
+fn convert_with_vtracer(
+    img: &DynamicImage,
+    config: Config,
+    on_progress: &mut dyn FnMut(ConversionStage, u8),
+) -> Result<SvgFile, String> {
+    let (width, height) = img.dimensions();
+    let color_image = ColorImage {
+        pixels: img.to_rgba8().into_raw(),
+        width: width as usize,
+        height: height as usize,
+    };
+    
+    // Only color mode runs the clustering pass before tracing
+    let stage = if matches!(config.color_mode, ColorMode::Color) {
+        ConversionStage::Clustering
+    } else {
+        ConversionStage::PathTracing
+    };
+    on_progress(stage, TRACE_PROGRESS_START);
+    
+    let svg = vtracer::convert(color_image, config).map_err(|e| format!("Conversion error: {}", e))?;
+    on_progress(ConversionStage::PathTracing, TRACE_PROGRESS_END);
+    
+    Ok(svg)
+}
+
+// Copy vtracer's paths into our own geometry model
+fn to_vector_image(svg: &SvgFile) -> VectorImage {
+    let paths = svg
+        .paths
+        .iter()
+        .map(|svg_path| VectorPath {
+            color: [svg_path.color.r, svg_path.color.g, svg_path.color.b],
+            subpaths: svg_path.path.paths.iter().filter_map(to_subpath).collect(),
+            stroke_width: None,
+        })
+        .collect();
+    
+    VectorImage {
+        width: svg.width as u32,
+        height: svg.height as u32,
+        paths,
+        pixel_grid: false,
+    }
+}
+
+fn to_subpath(element: &CompoundPathElement) -> Option<Subpath> {
+    match element {
+        CompoundPathElement::PathI32(path) => {
+            polygon_subpath(path.path.iter().map(|p| Point::new(p.x as f64, p.y as f64)))
+        }
+        CompoundPathElement::PathF64(path) => {
+            polygon_subpath(path.path.iter().map(|p| Point::new(p.x, p.y)))
+        }
+        CompoundPathElement::Spline(spline) => {
+            // A spline is its start point followed by (control, control, end) triples
+            let mut points = spline.points.iter().map(|p| Point::new(p.x, p.y));
+            let start = points.next()?;
+            let rest: Vec<Point> = points.collect();
+            let segments = rest
+                .chunks_exact(3)
+                .map(|curve| Segment::Cubic {
+                    c1: curve[0],
+                    c2: curve[1],
+                    to: curve[2],
+                })
+                .collect();
+            
+            Some(Subpath {
+                start,
+                segments,
+                closed: true,
+            })
+        }
+    }
+}
+
+fn polygon_subpath<I: Iterator<Item = Point>>(mut points: I) -> Option<Subpath> {
+    let start = points.next()?;
+    Some(Subpath {
+        start,
+        segments: points.map(|to| Segment::Line { to }).collect(),
+        closed: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ConversionSettings;
+    use crate::validation::validate_settings;
+    use image::{ImageBuffer, Rgba};
+    
+    // A dark bar on white, small enough for every engine to trace quickly
+    fn bar() -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(16, 10, |x, y| {
+            if (3..13).contains(&x) && (3..7).contains(&y) {
+                Rgba([20, 20, 20, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }))
+    }
+    
+    #[test]
+    fn each_engine_traces_at_the_image_size() {
+        let cases = [("bw", "vtracer"), ("bw", "potrace"), ("centerline", "centerline"), ("bw", "pixel")];
+        for (output_mode, engine) in cases {
+            let settings = validate_settings(&ConversionSettings {
+                output_mode: output_mode.to_string(),
+                engine: Some(engine.to_string()),
+                noise_reduction: Some(0),
+                ..ConversionSettings::default()
+            })
+            .unwrap();
+            
+            let tracer = create_tracer(settings.engine);
+            assert_eq!(tracer.name(), engine);
+            
+            let mut stages = Vec::new();
+            let output = tracer
+                .trace(&bar(), &settings, &mut |stage, percent| stages.push((stage, percent)))
+                .unwrap();
+            let image = output.vector_image;
+            assert_eq!((image.width, image.height), (16, 10), "{}", engine);
+            assert!(image.paths.iter().any(|path| !path.subpaths.is_empty()), "{} traced nothing", engine);
+            assert_eq!(stages.last().map(|&(_, percent)| percent), Some(TRACE_PROGRESS_END), "{}", engine);
+            assert!(output.binarized.is_some(), "{} binarizes black and white input", engine);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+// src/tuning.rs
+use crate::export::svg::write_svg;
+use crate::models::{Engine, QualityReport, ValidatedSettings};
+use crate::quality::measure_quality;
+use crate::vectorizer::trace_image;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+use log::info;
+use std::time::{Duration, Instant};
+
+// Automatic settings: trace a downscaled copy with different corner_threshold, path_simplification
+// and noise_reduction values and keep whichever scores best
+
+// Longest side of the copy the candidates are traced on
+const TRIAL_SIZE: u32 = 256;
+
+// Values tried for each tuned setting
+const CORNER_THRESHOLDS: &[u8] = &[30, 60, 90, 120];
+const PATH_SIMPLIFICATIONS: &[u8] = &[2, 4, 6, 8];
+const NOISE_REDUCTIONS: &[u8] = &[1, 3, 5, 7];
+
+// Score lost per byte of SVG per source pixel, so a smaller file wins unless it looks worse
+const SIZE_WEIGHT: f64 = 0.05;
+
+// Share of the fidelity score taken from SSIM; the rest comes from PSNR
+const SSIM_WEIGHT: f64 = 0.7;
+
+// PSNR counted as a perfect match
+const PSNR_CEILING: f64 = 50.0;
+
+// One of the settings that can be tuned
+#[derive(Debug, Clone, Copy)]
+enum Knob {
+    CornerThreshold,
+    PathSimplification,
+    NoiseReduction,
+}
+
+impl Knob {
+    fn values(self) -> &'static [u8] {
+        match self {
+            Knob::CornerThreshold => CORNER_THRESHOLDS,
+            Knob::PathSimplification => PATH_SIMPLIFICATIONS,
+            Knob::NoiseReduction => NOISE_REDUCTIONS,
+        }
+    }
+
+    fn set(self, settings: &mut ValidatedSettings, value: u8) {
+        match self {
+            Knob::CornerThreshold => settings.corner_threshold = value,
+            Knob::PathSimplification => settings.path_simplification = value,
+            Knob::NoiseReduction => settings.noise_reduction = value,
+        }
+    }
+}
+
+// Find the best settings for an image within the time budget, starting from the given settings.
+// The result has tuning switched off so it can be used for the full size conversion.
+pub fn tune_settings(img: &DynamicImage, settings: &ValidatedSettings, budget: Duration) -> ValidatedSettings {
+    let started = Instant::now();
+    search(img, settings, budget, &mut || started.elapsed()).0
+}
+
+// The search behind tune_settings. `elapsed` gives the time spent so far and is checked against the
+// budget before every trial after the first. Also returns how many trials were run.
+fn search(
+    img: &DynamicImage,
+    settings: &ValidatedSettings,
+    budget: Duration,
+    elapsed: &mut dyn FnMut() -> Duration,
+) -> (ValidatedSettings, usize) {
+    let mut best_settings = ValidatedSettings {
+        auto_budget_ms: None,
+        ..settings.clone()
+    };
+
+    // Pixel tracing follows the pixels exactly, whatever the settings
+    if settings.engine == Engine::Pixel {
+        return (best_settings, 0);
+    }
+
+    let (width, height) = img.dimensions();
+    let trial_image = if width.max(height) > TRIAL_SIZE {
+        img.resize(TRIAL_SIZE, TRIAL_SIZE, FilterType::Triangle)
+    } else {
+        img.clone()
+    };
+
+    let mut best_score = match score(&trial_image, &best_settings) {
+        Some(score) => score,
+        None => return (best_settings, 1),
+    };
+    let mut trials = 1;
+
+    // Tune one setting at a time, going round again while anything still improves
+    let knobs = [Knob::CornerThreshold, Knob::PathSimplification, Knob::NoiseReduction];
+    let mut improved = true;
+    'search: while improved {
+        improved = false;
+        for knob in knobs {
+            for &value in knob.values() {
+                let mut candidate = best_settings.clone();
+                knob.set(&mut candidate, value);
+                if candidate == best_settings {
+                    continue;
+                }
+
+                if elapsed() >= budget {
+                    break 'search;
+                }
+
+                trials += 1;
+                if let Some(candidate_score) = score(&trial_image, &candidate) {
+                    if candidate_score > best_score {
+                        best_score = candidate_score;
+                        best_settings = candidate;
+                        improved = true;
+                    }
+                }
+            }
+        }
+    }
+
+    info!(
+        "Tuned settings in {} trials over {} ms: corner_threshold {}, path_simplification {}, noise_reduction {} (score {:.4})",
+        trials,
+        elapsed().as_millis(),
+        best_settings.corner_threshold,
+        best_settings.path_simplification,
+        best_settings.noise_reduction,
+        best_score
+    );
+
+    (best_settings, trials)
+}
+
+// Trace the trial image and weigh how faithful the result is against how large its SVG is
+fn score(img: &DynamicImage, settings: &ValidatedSettings) -> Option<f64> {
+    let (traced, vector_image) = trace_image(img.clone(), settings, &mut |_, _| {}).ok()?;
+    let svg_data = write_svg(&vector_image);
+    let quality = measure_quality(&traced, &vector_image, &svg_data);
+
+    let (width, height) = img.dimensions();
+    let pixels = (width as f64 * height as f64).max(1.0);
+    Some(fidelity(&quality) - SIZE_WEIGHT * quality.bytes as f64 / pixels)
+}
+
+fn fidelity(quality: &QualityReport) -> f64 {
+    SSIM_WEIGHT * quality.ssim + (1.0 - SSIM_WEIGHT) * quality.psnr.min(PSNR_CEILING) / PSNR_CEILING
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ConversionSettings;
+    use crate::validation::{settings_to_request, validate_settings};
+    use image::{ImageBuffer, Rgba};
+
+    // Black dots of 4x4 pixels on white, which heavy noise reduction fills in as speckles
+    fn dots() -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(64, 64, |x, y| {
+            if x % 12 < 4 && y % 12 < 4 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }))
+    }
+
+    fn settings(noise_reduction: u8) -> ValidatedSettings {
+        // Potrace is traced in-crate, so the scores do not depend on vtracer's version
+        validate_settings(&ConversionSettings {
+            engine: Some("potrace".to_string()),
+            noise_reduction: Some(noise_reduction),
+            auto: Some(true),
+            ..ConversionSettings::default()
+        })
+        .unwrap()
+    }
+
+    // Tune against a clock that moves on a second at every reading, so a budget of N seconds
+    // allows N trials after the first
+    fn tune_in_trials(img: &DynamicImage, settings: &ValidatedSettings, trials: u64) -> (ValidatedSettings, usize) {
+        let mut seconds = 0;
+        search(img, settings, Duration::from_secs(trials), &mut || {
+            seconds += 1;
+            Duration::from_secs(seconds - 1)
+        })
+    }
+
+    #[test]
+    fn tuning_finds_better_settings() {
+        let start = settings(10);
+        let (tuned, trials) = tune_in_trials(&dots(), &start, 100);
+
+        // The search settles well before running out of trials
+        assert!(trials < 100, "{} trials", trials);
+
+        // The dots are lost at the starting noise reduction
+        assert!(tuned.noise_reduction < start.noise_reduction, "{:?}", tuned);
+        assert!(score(&dots(), &tuned) > score(&dots(), &start));
+
+        // Only the tuned settings change, and the result converts without tuning again
+        assert_eq!(tuned.auto_budget_ms, None);
+        assert_eq!(
+            ValidatedSettings {
+                corner_threshold: start.corner_threshold,
+                path_simplification: start.path_simplification,
+                noise_reduction: start.noise_reduction,
+                auto_budget_ms: start.auto_budget_ms,
+                ..tuned.clone()
+            },
+            start
+        );
+        assert_eq!(validate_settings(&settings_to_request(&tuned)).unwrap(), tuned);
+    }
+
+    #[test]
+    fn tuning_stops_when_the_budget_runs_out() {
+        let start = settings(5);
+
+        // The starting settings are always scored, then one trial per second of budget
+        for budget in [0, 1, 4] {
+            let (tuned, trials) = tune_in_trials(&dots(), &start, budget);
+            assert_eq!(trials, budget as usize + 1);
+            assert!(validate_settings(&settings_to_request(&tuned)).is_ok());
+        }
+    }
+
+    #[test]
+    fn pixel_tracing_is_not_tuned() {
+        let start = ValidatedSettings { engine: Engine::Pixel, ..settings(5) };
+        let (tuned, trials) = tune_in_trials(&dots(), &start, 100);
+
+        assert_eq!(trials, 0);
+        assert_eq!(tuned, ValidatedSettings { auto_budget_ms: None, ..start });
+    }
+}
@@ -1,37 +1,38 @@
 // src/utils.rs
+use crate::blob::{BlobInfo, BlobStore, BLOB_STORE};
+use crate::config::{config, StorageBackend};
+use crate::geometry::geometry_key;
 use crate::models::FileMetadata;
-use chrono::{DateTime, Duration, Utc};
-use log::{error, info};
-use std::collections::HashMap;
-use std::fs;
+use crate::store::{open_metadata_store, MetadataStore, StoreError};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
-use std::sync::{Arc, Mutex};
 use tokio::time;
 use uuid::Uuid;
 
-// Global storage for file metadata
+// Global storage for file metadata, on disk unless configured otherwise
 lazy_static::lazy_static! {
-    static ref FILE_STORE: Arc<Mutex<HashMap<String, FileMetadata>>> = Arc::new(Mutex::new(HashMap::new()));
+    static ref FILE_STORE: Box<dyn MetadataStore> =
+        open_metadata_store().expect("Failed to open metadata store");
 }
 
-// Directories
-pub const UPLOAD_DIR: &str = "uploads";
-pub const OUTPUT_DIR: &str = "output";
+// Shortest retention a request can ask for, which is also how often expired files are deleted
+pub const MIN_RETENTION_SECS: u64 = 60;
 
-// Create necessary directories
-pub fn create_directories() -> std::io::Result<()> {
-    let upload_path = Path::new(UPLOAD_DIR);
-    let output_path = Path::new(OUTPUT_DIR);
-    
-    if !upload_path.exists() {
-        fs::create_dir_all(upload_path)?;
-    }
-    
-    if !output_path.exists() {
-        fs::create_dir_all(output_path)?;
-    }
-    
-    Ok(())
+// Key prefix of uploads in the blob store; with local storage, a directory
+pub fn upload_dir() -> &'static str {
+    &config().storage.upload_dir
+}
+
+// Key prefix of converted outputs in the blob store
+pub fn output_dir() -> &'static str {
+    &config().storage.output_dir
+}
+
+// Retention of files stored without one, including those recorded before retention was configurable
+pub fn default_retention_secs() -> u64 {
+    config().retention.default_secs
 }
 
 // Generate a unique file ID
@@ -39,78 +40,612 @@ pub fn generate_file_id() -> String {
     Uuid::new_v4().to_string()
 }
 
+// Parse a "#rrggbb" or "#rgb" hex color
+pub fn parse_hex_color(value: &str) -> Option<[u8; 3]> {
+    let hex = value.trim().trim_start_matches('#');
+    // Checked up front so the slicing below stays on character boundaries
+    if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    
+    let expanded = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect::<String>(),
+        6 => hex.to_string(),
+        _ => return None,
+    };
+    
+    let channel = |index: usize| u8::from_str_radix(&expanded[index..index + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+// Format a color as "#rrggbb"
+pub fn format_hex_color([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
 // Store file metadata
 pub fn store_file_metadata(
     file_id: &str,
     original_name: &str,
     input_path: &str,
     output_path: &str,
-) {
+    retention_secs: u64,
+) -> FileMetadata {
     let metadata = FileMetadata {
         original_name: original_name.to_string(),
         input_path: input_path.to_string(),
         output_path: output_path.to_string(),
         timestamp: Utc::now(),
+        revisions: vec![(1, output_path.to_string())],
+        next_revision: 2,
+        retention_secs,
     };
     
-    let mut store = FILE_STORE.lock().unwrap();
-    store.insert(file_id.to_string(), metadata);
+    if let Err(e) = FILE_STORE.insert(file_id, &metadata) {
+        error!("Failed to store metadata for {}: {}", file_id, e);
+    }
+    metadata
+}
+
+// Metadata as stored, whether or not the file has expired
+fn stored_file_metadata(file_id: &str) -> Option<FileMetadata> {
+    match FILE_STORE.get(file_id) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            error!("Failed to read metadata for {}: {}", file_id, e);
+            None
+        }
+    }
 }
 
-// Get file metadata
+// Get file metadata; a file past its expiry is gone even before cleanup deletes it
 pub fn get_file_metadata(file_id: &str) -> Option<FileMetadata> {
-    let store = FILE_STORE.lock().unwrap();
-    store.get(file_id).cloned()
+    stored_file_metadata(file_id).filter(|metadata| metadata.expires_at() > Utc::now())
+}
+
+// Where the SVG of a revision is written; the first conversion keeps the plain file ID
+pub fn revision_output_path(file_id: &str, revision: u32) -> String {
+    if revision <= 1 {
+        format!("{}/{}.svg", output_dir(), file_id)
+    } else {
+        format!("{}/{}-r{}.svg", output_dir(), file_id, revision)
+    }
+}
+
+// Reserve the next revision of a stored file and restart its retention, since it is in use again,
+// replacing the retention when a new one is given
+pub fn reserve_file_revision(file_id: &str, retention_secs: Option<u64>) -> Option<(FileMetadata, u32)> {
+    // An expired file is not brought back
+    get_file_metadata(file_id)?;
+    
+    let reserved = FILE_STORE.update(file_id, &mut |metadata| {
+        metadata.next_revision += 1;
+        metadata.timestamp = Utc::now();
+        if let Some(retention_secs) = retention_secs {
+            metadata.retention_secs = retention_secs;
+        }
+    });
+    
+    match reserved {
+        Ok(metadata) => metadata.map(|metadata| {
+            let revision = metadata.next_revision - 1;
+            (metadata, revision)
+        }),
+        Err(e) => {
+            error!("Failed to reserve a revision of {}: {}", file_id, e);
+            None
+        }
+    }
+}
+
+// Record a finished revision and make it the latest output of the file
+pub fn record_file_revision(file_id: &str, revision: u32, output_path: &str) -> Option<FileMetadata> {
+    let recorded = FILE_STORE.update(file_id, &mut |metadata| {
+        let index = metadata.revisions.partition_point(|(number, _)| *number < revision);
+        metadata.revisions.insert(index, (revision, output_path.to_string()));
+        
+        // A slower, older revision finishing late doesn't replace a newer one
+        if index == metadata.revisions.len() - 1 {
+            metadata.output_path = output_path.to_string();
+        }
+        metadata.timestamp = Utc::now();
+    });
+    
+    match recorded {
+        Ok(Some(metadata)) => Some(metadata),
+        Ok(None) => {
+            // Deleted while converting, so nothing would refer to the new output
+            delete_output(output_path);
+            None
+        }
+        Err(e) => {
+            error!("Failed to record revision {} of {}: {}", revision, file_id, e);
+            None
+        }
+    }
+}
+
+// Metadata of a file whose first conversion just finished. When the file was deleted in the
+// meantime, the output written for it is deleted too.
+pub fn converted_file_metadata(file_id: &str, output_path: &str) -> Option<FileMetadata> {
+    let metadata = stored_file_metadata(file_id);
+    if metadata.is_none() {
+        delete_output(output_path);
+    }
+    metadata
+}
+
+// Delete an SVG output along with the geometry kept for other export formats
+fn delete_output(output_path: &str) {
+    for key in [output_path.to_string(), geometry_key(output_path)] {
+        if let Err(e) = BLOB_STORE.delete(&key) {
+            error!("Failed to delete output file {}: {}", key, e);
+        }
+    }
+}
+
+// Delete the upload and every output of a file, along with its metadata
+pub fn remove_file_and_outputs(file_id: &str, metadata: &FileMetadata) {
+    // Delete input file
+    if let Err(e) = BLOB_STORE.delete(&metadata.input_path) {
+        error!("Failed to delete input file {}: {}", metadata.input_path, e);
+    }
+    
+    // Delete the output of every revision, and the one an adopted upload may still get written
+    for (_, output_path) in &metadata.revisions {
+        delete_output(output_path);
+    }
+    if !metadata.revisions.iter().any(|(_, output_path)| *output_path == metadata.output_path) {
+        delete_output(&metadata.output_path);
+    }
+    
+    // Remove from store
+    if let Err(e) = FILE_STORE.remove(file_id) {
+        error!("Failed to remove metadata for {}: {}", file_id, e);
+    }
+    crate::jobs::remove_jobs_for_file(file_id);
+    crate::preview::forget_preview(file_id);
+}
+
+// File ID and revision of an output key such as "output/<id>.svg" or "output/<id>-r3.json"
+fn parse_output_name(key: &str) -> Option<(String, u32)> {
+    let stem = Path::new(key).file_stem()?.to_str()?;
+    
+    // File IDs are UUIDs, which never contain "-r"
+    match stem.rsplit_once("-r") {
+        Some((file_id, revision)) => Some((file_id.to_string(), revision.parse().ok()?)),
+        None => Some((stem.to_string(), 1)),
+    }
+}
+
+// Blobs under a directory-like prefix, or none if they can't be listed
+fn list_blobs(blobs: &dyn BlobStore, dir: &str) -> Vec<BlobInfo> {
+    match blobs.list(&format!("{}/", dir)) {
+        Ok(blobs) => blobs,
+        Err(e) => {
+            error!("Failed to list {}: {}", dir, e);
+            Vec::new()
+        }
+    }
+}
+
+// What reconciling changed, and how many files are tracked afterwards
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Reconciled {
+    dropped: usize,
+    adopted: usize,
+    deleted: usize,
+    tracked: usize,
+}
+
+// Bring the metadata store and the stored uploads and outputs back in line after a restart:
+// records whose files are gone are dropped, uploads without a record are adopted along with
+// their outputs, and outputs nothing refers to are deleted. The cleanup task then covers
+// everything left.
+//
+// With S3 storage several servers may share the blobs while each keeps its own metadata, so an
+// unrecorded upload or output may well be another server's. Only the records are reconciled then,
+// and every server leaves alone the files it has no record of.
+pub fn reconcile_files() {
+    let shared = config().storage.backend == StorageBackend::S3;
+    let reconciled = match reconcile_stores(FILE_STORE.as_ref(), BLOB_STORE.as_ref(), !shared) {
+        Ok(reconciled) => reconciled,
+        Err(e) => {
+            error!("Failed to read metadata store, skipping reconciliation: {}", e);
+            return;
+        }
+    };
+    
+    if reconciled.dropped + reconciled.adopted + reconciled.deleted > 0 {
+        warn!(
+            "Reconciled stored files: {} stale record(s) dropped, {} orphaned upload(s) adopted, {} orphaned output(s) deleted",
+            reconciled.dropped, reconciled.adopted, reconciled.deleted
+        );
+    }
+    if shared {
+        info!("Storage is shared, so uploads and outputs without a record are left alone");
+    }
+    info!("Tracking {} stored file(s)", reconciled.tracked);
+}
+
+fn reconcile_stores(
+    store: &dyn MetadataStore,
+    blobs: &dyn BlobStore,
+    owns_storage: bool,
+) -> Result<Reconciled, StoreError> {
+    let records = store.list()?;
+    
+    let exists = |key: &str| blobs.exists(key).unwrap_or(false);
+    let mut known: HashSet<String> = HashSet::new();
+    let mut reconciled = Reconciled::default();
+    for (file_id, metadata) in records {
+        let has_output = metadata
+            .revisions
+            .iter()
+            .any(|(_, output_path)| exists(output_path));
+        
+        if has_output || exists(&metadata.input_path) {
+            known.insert(file_id);
+        } else {
+            if let Err(e) = store.remove(&file_id) {
+                error!("Failed to remove metadata for {}: {}", file_id, e);
+            }
+            reconciled.dropped += 1;
+        }
+    }
+    
+    if !owns_storage {
+        reconciled.tracked = known.len();
+        return Ok(reconciled);
+    }
+    
+    let outputs = list_blobs(blobs, output_dir());
+    
+    // SVG outputs by file ID, for the files without a record
+    let mut orphan_outputs: HashMap<String, Vec<(u32, &BlobInfo)>> = HashMap::new();
+    for blob in &outputs {
+        if !blob.key.ends_with(".svg") {
+            continue;
+        }
+        if let Some((file_id, revision)) = parse_output_name(&blob.key) {
+            if !known.contains(&file_id) {
+                orphan_outputs
+                    .entry(file_id)
+                    .or_default()
+                    .push((revision, blob));
+            }
+        }
+    }
+    
+    // Adopt every upload without a record, converted or not, so that it is kept for the usual
+    // retention and then cleaned up
+    for blob in list_blobs(blobs, upload_dir()) {
+        let file_id = match Path::new(&blob.key).file_stem().and_then(|stem| stem.to_str()) {
+            Some(file_id) => file_id.to_string(),
+            None => continue,
+        };
+        if known.contains(&file_id) {
+            continue;
+        }
+        
+        let mut found = orphan_outputs.remove(&file_id).unwrap_or_default();
+        found.sort_by_key(|(revision, _)| *revision);
+        
+        // Retention counts from the last time the file was written, as if it had been converted then
+        let timestamp = found
+            .iter()
+            .filter_map(|(_, output)| output.modified)
+            .chain(blob.modified)
+            .max()
+            .unwrap_or_else(Utc::now);
+        let revisions: Vec<(u32, String)> = found
+            .into_iter()
+            .map(|(revision, output)| (revision, output.key.clone()))
+            .collect();
+        let (latest, output_path) = revisions
+            .last()
+            .cloned()
+            .unwrap_or_else(|| (1, revision_output_path(&file_id, 1)));
+        
+        // The original name is lost, so the stored name stands in for it
+        let metadata = FileMetadata {
+            original_name: Path::new(&blob.key)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("image")
+                .to_string(),
+            input_path: blob.key.clone(),
+            output_path,
+            timestamp,
+            revisions,
+            next_revision: latest + 1,
+            retention_secs: default_retention_secs(),
+        };
+        match store.insert(&file_id, &metadata) {
+            Ok(()) => {
+                known.insert(file_id);
+                reconciled.adopted += 1;
+            }
+            Err(e) => error!("Failed to adopt {}: {}", file_id, e),
+        }
+    }
+    
+    // Outputs whose upload is gone, and geometry left without its SVG
+    for blob in &outputs {
+        let referenced = parse_output_name(&blob.key)
+            .map(|(file_id, _)| known.contains(&file_id))
+            .unwrap_or(false);
+        if !referenced {
+            if let Err(e) = blobs.delete(&blob.key) {
+                error!("Failed to delete orphaned output {}: {}", blob.key, e);
+            }
+            reconciled.deleted += 1;
+        }
+    }
+    
+    reconciled.tracked = known.len();
+    Ok(reconciled)
 }
 
 // Start cleanup task
 pub fn start_cleanup_task() {
-    let file_store = FILE_STORE.clone();
-    
     tokio::spawn(async move {
-        let mut interval = time::interval(time::Duration::from_secs(3600)); // Run every hour
+        // Expired files are already hidden, so this only bounds how long they take up storage
+        let mut interval = time::interval(time::Duration::from_secs(MIN_RETENTION_SECS));
         
         loop {
             interval.tick().await;
-            info!("Running scheduled cleanup...");
             
-            let now = Utc::now();
-            let one_hour = Duration::hours(1);
-            let mut to_remove = Vec::new();
+            crate::jobs::remove_expired_jobs();
             
-            // Find files older than one hour
-            {
-                let store = file_store.lock().unwrap();
-                for (file_id, metadata) in store.iter() {
-                    if now - metadata.timestamp > one_hour {
-                        to_remove.push(file_id.clone());
-                    }
-                }
-            }
-            
-            // Remove files
-            let mut store = file_store.lock().unwrap();
-            for file_id in to_remove {
-                if let Some(metadata) = store.get(&file_id) {
-                    // Delete input file
-                    if Path::new(&metadata.input_path).exists() {
-                        if let Err(e) = fs::remove_file(&metadata.input_path) {
-                            error!("Failed to delete input file {}: {}", metadata.input_path, e);
-                        }
-                    }
-                    
-                    // Delete output file
-                    if Path::new(&metadata.output_path).exists() {
-                        if let Err(e) = fs::remove_file(&metadata.output_path) {
-                            error!("Failed to delete output file {}: {}", metadata.output_path, e);
-                        }
-                    }
-                    
-                    // Remove from store
-                    store.remove(&file_id);
-                    info!("Cleaned up files for {}", file_id);
-                }
+            // Deleting blobs blocks with S3 storage, so it stays off the async runtime threads
+            if let Err(e) = tokio::task::spawn_blocking(remove_expired_files).await {
+                error!("File cleanup failed: {}", e);
             }
         }
     });
 }
+
+// Remove files past their expiry
+fn remove_expired_files() {
+    let now = Utc::now();
+    let records = match FILE_STORE.list() {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Failed to read metadata store: {}", e);
+            return;
+        }
+    };
+    
+    for (file_id, metadata) in records {
+        if metadata.expires_at() > now {
+            continue;
+        }
+        
+        // Check again, in case the file was converted again since the listing
+        match stored_file_metadata(&file_id) {
+            Some(metadata) if metadata.expires_at() <= now => {
+                remove_file_and_outputs(&file_id, &metadata);
+                info!("Cleaned up files for {}", file_id);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hex_color_accepts_short_and_long_forms() {
+        assert_eq!(parse_hex_color("#ff8000"), Some([255, 128, 0]));
+        assert_eq!(parse_hex_color("#F80"), Some([255, 136, 0]));
+        assert_eq!(parse_hex_color("ff8000"), Some([255, 128, 0]));
+        assert_eq!(parse_hex_color("f80"), Some([255, 136, 0]));
+        assert_eq!(parse_hex_color(" #ffffff "), Some([255, 255, 255]));
+    }
+
+    #[test]
+    fn parse_hex_color_rejects_everything_else() {
+        for value in ["", "#", "#ff", "#ffff", "#fffffff", "#ggg", "#12345g", "#+f+f+f", "#€", "#ééé", "#ff€f"] {
+            assert_eq!(parse_hex_color(value), None, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn format_hex_color_round_trips() {
+        assert_eq!(format_hex_color([255, 128, 0]), "#ff8000");
+        assert_eq!(parse_hex_color(&format_hex_color([1, 2, 3])), Some([1, 2, 3]));
+    }
+
+    // A blob store in a fresh temporary directory, removed when dropped
+    struct TempBlobStore {
+        root: std::path::PathBuf,
+        blobs: crate::blob::LocalBlobStore,
+    }
+
+    impl TempBlobStore {
+        fn new(blobs: &[&str]) -> Self {
+            let root = std::env::temp_dir().join(format!("reconcile-{}", generate_file_id()));
+            let store = TempBlobStore {
+                blobs: crate::blob::LocalBlobStore::new(root.to_str().unwrap()),
+                root,
+            };
+            for key in blobs {
+                store.blobs.put(key, b"data").unwrap();
+            }
+            store
+        }
+
+        fn exists(&self, key: &str) -> bool {
+            self.blobs.exists(key).unwrap()
+        }
+    }
+
+    impl Drop for TempBlobStore {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn record(file_id: &str) -> FileMetadata {
+        let output_path = format!("output/{}.svg", file_id);
+        FileMetadata {
+            original_name: "image.png".to_string(),
+            input_path: format!("uploads/{}.png", file_id),
+            output_path: output_path.clone(),
+            timestamp: Utc::now(),
+            revisions: vec![(1, output_path)],
+            next_revision: 2,
+            retention_secs: 600,
+        }
+    }
+
+    #[test]
+    fn reconciling_adopts_orphaned_uploads_with_their_outputs() {
+        let blobs = TempBlobStore::new(&[
+            "uploads/orphan.png",
+            "output/orphan.svg",
+            "output/orphan.json",
+            "output/orphan-r3.svg",
+            "output/orphan-r3.json",
+            "output/gone.svg",
+            "output/gone-r2.json",
+        ]);
+        let store = crate::store::MemoryMetadataStore::new();
+
+        let reconciled = reconcile_stores(&store, &blobs.blobs, true).unwrap();
+        assert_eq!(reconciled, Reconciled { dropped: 0, adopted: 1, deleted: 2, tracked: 1 });
+
+        let adopted = store.get("orphan").unwrap().expect("orphan adopted");
+        assert_eq!(adopted.input_path, "uploads/orphan.png");
+        assert_eq!(adopted.original_name, "orphan.png");
+        assert_eq!(
+            adopted.revisions,
+            [(1, "output/orphan.svg".to_string()), (3, "output/orphan-r3.svg".to_string())]
+        );
+        assert_eq!(adopted.output_path, "output/orphan-r3.svg");
+        assert_eq!(adopted.next_revision, 4);
+        assert_eq!(adopted.retention_secs, default_retention_secs());
+
+        // Outputs of an upload that is gone are deleted, those of the adopted one are kept
+        assert!(!blobs.exists("output/gone.svg"));
+        assert!(!blobs.exists("output/gone-r2.json"));
+        assert!(blobs.exists("output/orphan-r3.json"));
+    }
+
+    #[test]
+    fn reconciling_drops_records_whose_files_are_gone() {
+        let blobs = TempBlobStore::new(&["uploads/kept.png", "output/converted.svg"]);
+        let store = crate::store::MemoryMetadataStore::new();
+        for file_id in ["kept", "converted", "missing"] {
+            store.insert(file_id, &record(file_id)).unwrap();
+        }
+
+        let reconciled = reconcile_stores(&store, &blobs.blobs, true).unwrap();
+        assert_eq!(reconciled, Reconciled { dropped: 1, adopted: 0, deleted: 0, tracked: 2 });
+
+        // A record is kept as long as its upload or any of its outputs is left
+        assert!(store.get("kept").unwrap().is_some());
+        assert!(store.get("converted").unwrap().is_some());
+        assert!(store.get("missing").unwrap().is_none());
+        assert!(blobs.exists("output/converted.svg"));
+    }
+
+    #[test]
+    fn reconciling_shared_storage_leaves_unrecorded_files_alone() {
+        let blobs = TempBlobStore::new(&["uploads/kept.png", "uploads/other.png", "output/other.svg", "output/gone.svg"]);
+        let store = crate::store::MemoryMetadataStore::new();
+        for file_id in ["kept", "missing"] {
+            store.insert(file_id, &record(file_id)).unwrap();
+        }
+
+        let reconciled = reconcile_stores(&store, &blobs.blobs, false).unwrap();
+        assert_eq!(reconciled, Reconciled { dropped: 1, adopted: 0, deleted: 0, tracked: 1 });
+
+        // Files without a record may be another server's
+        assert!(store.get("other").unwrap().is_none());
+        assert!(store.get("missing").unwrap().is_none());
+        for key in ["uploads/other.png", "output/other.svg", "output/gone.svg"] {
+            assert!(blobs.exists(key), "{}", key);
+        }
+    }
+
+    // A file in the server's own stores, last converted `age_secs` ago and kept for `retention_secs`
+    fn stored_file(age_secs: i64, retention_secs: u64) -> String {
+        let file_id = generate_file_id();
+        let metadata = FileMetadata {
+            timestamp: Utc::now() - chrono::Duration::seconds(age_secs),
+            retention_secs,
+            ..record(&file_id)
+        };
+        FILE_STORE.insert(&file_id, &metadata).unwrap();
+        for key in [&metadata.input_path, &metadata.output_path] {
+            BLOB_STORE.put(key, b"data").unwrap();
+        }
+        file_id
+    }
+
+    #[test]
+    fn expired_files_are_hidden_before_cleanup() {
+        let expired = stored_file(601, 600);
+        let live = stored_file(590, 600);
+
+        // Gone as soon as it expires, though cleanup hasn't run yet
+        assert!(get_file_metadata(&expired).is_none());
+        let metadata = stored_file_metadata(&expired).unwrap();
+        assert!(BLOB_STORE.exists(&metadata.input_path).unwrap());
+
+        remove_expired_files();
+        assert!(stored_file_metadata(&expired).is_none());
+        assert!(!BLOB_STORE.exists(&metadata.input_path).unwrap());
+        assert!(!BLOB_STORE.exists(&metadata.output_path).unwrap());
+
+        let metadata = get_file_metadata(&live).unwrap();
+        assert!(BLOB_STORE.exists(&metadata.input_path).unwrap());
+        assert!(BLOB_STORE.exists(&metadata.output_path).unwrap());
+    }
+
+    #[test]
+    fn reconverting_restarts_retention() {
+        let file_id = stored_file(500, 600);
+
+        let (metadata, revision) = reserve_file_revision(&file_id, None).unwrap();
+        assert_eq!(revision, 2);
+        assert_eq!(metadata.retention_secs, 600);
+        assert!(metadata.expires_at() > Utc::now() + chrono::Duration::seconds(590));
+
+        // A new retention replaces the one the file was stored with
+        let (metadata, revision) = reserve_file_revision(&file_id, Some(7200)).unwrap();
+        assert_eq!(revision, 3);
+        assert_eq!(get_file_metadata(&file_id).unwrap().retention_secs, 7200);
+        assert!(metadata.expires_at() > Utc::now() + chrono::Duration::seconds(7190));
+
+        // An expired file is not brought back
+        let expired = stored_file(601, 600);
+        assert!(reserve_file_revision(&expired, Some(7200)).is_none());
+        assert!(get_file_metadata(&expired).is_none());
+    }
+
+    #[test]
+    fn requested_retention_is_held_to_the_server_maximum() {
+        let max_secs = config().retention.max_secs;
+        let retention = |expires_in: Option<u64>| {
+            crate::validation::validate_retention_query(&crate::models::RetentionQuery { expires_in })
+        };
+
+        assert_eq!(retention(None).unwrap(), None);
+        assert_eq!(retention(Some(MIN_RETENTION_SECS)).unwrap(), Some(MIN_RETENTION_SECS));
+        assert_eq!(retention(Some(max_secs)).unwrap(), Some(max_secs));
+        for expires_in in [0, MIN_RETENTION_SECS - 1, max_secs + 1, u64::MAX] {
+            let errors = retention(Some(expires_in)).unwrap_err();
+            assert_eq!((errors[0].field.as_str(), errors[0].code.as_str()), ("expires_in", "out_of_range"));
+        }
+
+        // Files stored without one get the default
+        let file_id = generate_file_id();
+        let metadata = store_file_metadata(&file_id, "logo.png", "uploads/logo.png", "output/logo.svg", default_retention_secs());
+        assert_eq!(metadata.expires_at(), metadata.timestamp + chrono::Duration::seconds(3600));
+    }
+}
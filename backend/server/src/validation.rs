@@ -0,0 +1,985 @@
+// src/validation.rs
+use crate::export::gcode::z_heights;
+use crate::export::{format_number, CurveOutput, DxfVersion, ExportFormat, ExportOptions, Mirror, ToolLift, Units};
+use crate::models::{
+    BackgroundMode, Binarization, ConversionSettings, CurveMode, DownloadQuery, Engine, FieldError,
+    OutputMode, Quantization, RetentionQuery, ValidatedSettings,
+};
+use crate::config::{config, Config};
+use crate::pixel::MAX_PIXEL_COLORS;
+use crate::utils::{format_hex_color, parse_hex_color, MIN_RETENTION_SECS};
+use image::DynamicImage;
+use std::collections::HashSet;
+
+// Allowed ranges for numeric settings
+const SCALE_RANGE: (i64, i64) = (0, 10);
+const ADJUSTMENT_RANGE: (i64, i64) = (-100, 100);
+const COLOR_COUNT_RANGE: (i64, i64) = (2, 64);
+const CORNER_THRESHOLD_RANGE: (i64, i64) = (0, 180);
+const ADAPTIVE_WINDOW_RANGE: (i64, i64) = (3, 501);
+const GAMMA_RANGE: (f32, f32) = (0.1, 10.0);
+const ADAPTIVE_K_RANGE: (f32, f32) = (-1.0, 1.0);
+const STROKE_WIDTH_RANGE: (f32, f32) = (0.1, 100.0);
+const DPI_RANGE: (f64, f64) = (1.0, 2400.0);
+const TOLERANCE_RANGE: (f64, f64) = (0.0001, 10.0);
+const FEED_RATE_RANGE: (f64, f64) = (1.0, 100000.0);
+const Z_RANGE: (f64, f64) = (-100.0, 100.0);
+const ORIGIN_RANGE: (f64, f64) = (-10000.0, 10000.0);
+const BLADE_OFFSET_RANGE: (f64, f64) = (0.0, 5.0);
+const PALETTE_SIZE_RANGE: (usize, usize) = (1, 256);
+const AUTO_BUDGET_RANGE: (i64, i64) = (100, 60000);
+
+// Allowed values for the string settings
+const OUTPUT_MODES: &[(&str, OutputMode)] = &[
+    ("bw", OutputMode::Bw),
+    ("color", OutputMode::Color),
+    ("centerline", OutputMode::Centerline),
+    ("pixel_art", OutputMode::PixelArt),
+];
+const ENGINES: &[(&str, Engine)] = &[
+    ("vtracer", Engine::Vtracer),
+    ("potrace", Engine::Potrace),
+    ("centerline", Engine::Centerline),
+    ("pixel", Engine::Pixel),
+];
+const QUANTIZATIONS: &[(&str, Option<Quantization>)] = &[
+    ("kmeans", Some(Quantization::KMeans)),
+    ("median_cut", Some(Quantization::MedianCut)),
+    ("octree", Some(Quantization::Octree)),
+    ("none", None),
+];
+const CURVE_MODES: &[(&str, CurveMode)] = &[("polygon", CurveMode::Polygon), ("spline", CurveMode::Spline)];
+const BINARIZATIONS: &[(&str, Binarization)] = &[
+    ("fixed", Binarization::Fixed),
+    ("otsu", Binarization::Otsu),
+    ("sauvola", Binarization::Sauvola),
+    ("niblack", Binarization::Niblack),
+    ("per_channel", Binarization::PerChannel),
+];
+const BACKGROUNDS: &[&str] = &["keep", "remove", "color"];
+const UNITS: &[(&str, Units)] = &[("mm", Units::Mm), ("inch", Units::Inch)];
+const DXF_VERSIONS: &[(&str, DxfVersion)] = &[("r12", DxfVersion::R12), ("r2000", DxfVersion::R2000)];
+const CURVE_OUTPUTS: &[(&str, CurveOutput)] = &[("spline", CurveOutput::Spline), ("polyline", CurveOutput::Polyline)];
+const TOOL_LIFTS: &[(&str, ToolLift)] = &[("z", ToolLift::Z), ("pen", ToolLift::Pen)];
+const MIRRORS: &[(&str, Mirror)] = &[("none", Mirror::None), ("x", Mirror::X), ("y", Mirror::Y)];
+const ROTATIONS: &[u16] = &[0, 90, 180, 270];
+
+// Parse and validate the settings JSON of a request; fields it leaves out take the configured defaults
+pub fn parse_settings(json: &str) -> Result<ValidatedSettings, Vec<FieldError>> {
+    // Invalid JSON is parsed as it is, so that its error is reported
+    let merged = match serde_json::from_str::<serde_json::Value>(json) {
+        Ok(serde_json::Value::Object(fields)) => {
+            // Plain values only, which always serialize to an object
+            let mut defaults = match serde_json::to_value(&config().conversion.defaults) {
+                Ok(serde_json::Value::Object(defaults)) => defaults,
+                _ => serde_json::Map::new(),
+            };
+            defaults.extend(fields);
+            serde_json::Value::Object(defaults).to_string()
+        }
+        Ok(_) => {
+            return Err(vec![FieldError {
+                field: "settings".to_string(),
+                code: "invalid_json".to_string(),
+                message: "settings must be a JSON object".to_string(),
+                allowed: None,
+            }])
+        }
+        Err(_) => json.to_string(),
+    };
+    let deserializer = &mut serde_json::Deserializer::from_str(&merged);
+
+    match serde_path_to_error::deserialize::<_, ConversionSettings>(deserializer) {
+        Ok(settings) => validate_settings(&settings),
+        Err(e) => {
+            let path = e.path().to_string();
+            let inner = e.into_inner();
+
+            // Syntax errors have no useful path; type errors point at the field
+            let error = if inner.is_syntax() || inner.is_eof() || path == "." {
+                FieldError {
+                    field: "settings".to_string(),
+                    code: "invalid_json".to_string(),
+                    message: inner.to_string(),
+                    allowed: None,
+                }
+            } else {
+                FieldError {
+                    allowed: allowed_for(&path),
+                    field: path,
+                    code: "invalid_type".to_string(),
+                    message: inner.to_string(),
+                }
+            };
+
+            Err(vec![error])
+        }
+    }
+}
+
+// Check every field and collect all problems instead of stopping at the first
+pub fn validate_settings(settings: &ConversionSettings) -> Result<ValidatedSettings, Vec<FieldError>> {
+    let defaults = ConversionSettings::default();
+    let mut errors = Vec::new();
+
+    let output_mode = check_choice(&mut errors, "output_mode", Some(&settings.output_mode), OUTPUT_MODES)
+        .unwrap_or(OutputMode::Bw);
+    let engine = check_engine(&mut errors, settings, output_mode);
+    let curve_mode = check_choice(&mut errors, "curve_mode", settings.curve_mode.as_ref(), CURVE_MODES)
+        .unwrap_or(CurveMode::Spline);
+    let binarization = check_choice(&mut errors, "binarization", settings.binarization.as_ref(), BINARIZATIONS)
+        .unwrap_or(Binarization::Fixed);
+
+    let color_count = check_range(&mut errors, "color_count", settings.color_count.or(defaults.color_count), COLOR_COUNT_RANGE);
+    let smoothing = check_range(&mut errors, "smoothing", settings.smoothing.or(defaults.smoothing), SCALE_RANGE);
+    let path_simplification = check_range(
+        &mut errors,
+        "path_simplification",
+        settings.path_simplification.or(defaults.path_simplification),
+        SCALE_RANGE,
+    );
+    let noise_reduction = check_range(&mut errors, "noise_reduction", settings.noise_reduction.or(defaults.noise_reduction), SCALE_RANGE);
+    let brightness = check_range(&mut errors, "brightness", settings.brightness.or(defaults.brightness), ADJUSTMENT_RANGE);
+    let contrast = check_range(&mut errors, "contrast", settings.contrast.or(defaults.contrast), ADJUSTMENT_RANGE);
+    let corner_threshold = check_range(
+        &mut errors,
+        "corner_threshold",
+        settings.corner_threshold.or(defaults.corner_threshold),
+        CORNER_THRESHOLD_RANGE,
+    );
+    let adaptive_window = check_range(
+        &mut errors,
+        "adaptive_window",
+        settings.adaptive_window.or(defaults.adaptive_window),
+        ADAPTIVE_WINDOW_RANGE,
+    );
+
+    let gamma = check_float(&mut errors, "gamma", settings.gamma.or(defaults.gamma), GAMMA_RANGE);
+    let adaptive_k = settings
+        .adaptive_k
+        .map(|k| check_float(&mut errors, "adaptive_k", Some(k), ADAPTIVE_K_RANGE));
+    let stroke_width = settings
+        .stroke_width
+        .map(|width| check_float(&mut errors, "stroke_width", Some(width), STROKE_WIDTH_RANGE));
+
+    // The budget only matters when tuning is on
+    let auto_budget_ms = match settings.auto {
+        Some(true) => Some(check_range(
+            &mut errors,
+            "auto_time_budget_ms",
+            settings.auto_time_budget_ms.or(defaults.auto_time_budget_ms),
+            AUTO_BUDGET_RANGE,
+        )),
+        _ => None,
+    };
+
+    let quantization = check_quantization(&mut errors, settings, output_mode);
+    let palette = check_palette(&mut errors, settings);
+    let background = check_background(&mut errors, settings);
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(ValidatedSettings {
+        output_mode,
+        engine,
+        threshold: settings.threshold.or(defaults.threshold).unwrap_or(128),
+        binarization,
+        adaptive_window,
+        adaptive_k,
+        color_count,
+        quantization,
+        palette,
+        stroke_width,
+        smoothing,
+        path_simplification,
+        noise_reduction,
+        background,
+        background_tolerance: settings
+            .background_tolerance
+            .or(defaults.background_tolerance)
+            .unwrap_or(24),
+        brightness,
+        contrast,
+        gamma,
+        corner_threshold,
+        curve_mode,
+        auto_budget_ms,
+    })
+}
+
+// Check that the pixel engine can trace the colors left in a preprocessed image, one pass per color.
+// Only reachable without quantization or a palette, which both keep far fewer colors.
+pub fn check_image_colors(img: &DynamicImage, settings: &ValidatedSettings) -> Result<(), Vec<FieldError>> {
+    // Black and white output is binarized first
+    if settings.engine != Engine::Pixel || settings.output_mode == OutputMode::Bw {
+        return Ok(());
+    }
+
+    let mut colors = HashSet::new();
+    for pixel in img.to_rgba8().pixels().filter(|pixel| pixel[3] >= 128) {
+        colors.insert([pixel[0], pixel[1], pixel[2]]);
+        if colors.len() > MAX_PIXEL_COLORS {
+            return Err(vec![FieldError {
+                field: "quantization".to_string(),
+                code: "unsupported".to_string(),
+                message: format!(
+                    "the image has more than {} colors, more than the pixel engine traces; reduce them with quantization or a palette",
+                    MAX_PIXEL_COLORS
+                ),
+                allowed: Some(
+                    choice_names(QUANTIZATIONS)
+                        .into_iter()
+                        .filter(|name| *name != "none")
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+            }]);
+        }
+    }
+
+    Ok(())
+}
+
+// Turn validated settings back into the request form, e.g. to show the settings auto tuning picked
+pub fn settings_to_request(settings: &ValidatedSettings) -> ConversionSettings {
+    let (background, background_color) = match settings.background {
+        BackgroundMode::Keep => ("keep", None),
+        BackgroundMode::Remove => ("remove", None),
+        BackgroundMode::Color(color) => ("color", Some(format_hex_color(color))),
+    };
+
+    ConversionSettings {
+        output_mode: choice_name(OUTPUT_MODES, settings.output_mode).map(str::to_string).unwrap_or_default(),
+        engine: choice_name(ENGINES, settings.engine).map(str::to_string),
+        threshold: Some(settings.threshold),
+        binarization: choice_name(BINARIZATIONS, settings.binarization).map(str::to_string),
+        adaptive_window: Some(settings.adaptive_window),
+        adaptive_k: settings.adaptive_k,
+        color_count: Some(settings.color_count),
+        quantization: choice_name(QUANTIZATIONS, settings.quantization).map(str::to_string),
+        palette: settings
+            .palette
+            .as_ref()
+            .map(|palette| palette.iter().copied().map(format_hex_color).collect()),
+        stroke_width: settings.stroke_width,
+        smoothing: Some(settings.smoothing),
+        path_simplification: Some(settings.path_simplification),
+        noise_reduction: Some(settings.noise_reduction),
+        background_transparency: Some(settings.background == BackgroundMode::Remove),
+        background: Some(background.to_string()),
+        background_color,
+        background_tolerance: Some(settings.background_tolerance),
+        brightness: Some(settings.brightness),
+        contrast: Some(settings.contrast),
+        gamma: Some(settings.gamma),
+        corner_threshold: Some(settings.corner_threshold),
+        curve_mode: choice_name(CURVE_MODES, settings.curve_mode).map(str::to_string),
+        auto: Some(settings.auto_budget_ms.is_some()),
+        auto_time_budget_ms: settings.auto_budget_ms,
+    }
+}
+
+// Check the format and options of a download request
+pub fn validate_download_query(query: &DownloadQuery) -> Result<(ExportFormat, ExportOptions), Vec<FieldError>> {
+    check_download_query(query, config())
+}
+
+// Check a download request against the formats the given configuration turns on
+fn check_download_query(query: &DownloadQuery, config: &Config) -> Result<(ExportFormat, ExportOptions), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    let mut options = ExportOptions::default();
+
+    let format = match query.format.as_deref() {
+        None => ExportFormat::Svg,
+        Some(value) => match ExportFormat::parse(value) {
+            Some(format) => format,
+            None => {
+                let names: Vec<&str> = ExportFormat::ALL.iter().map(|format| format.extension()).collect();
+                errors.push(unknown_value("format", value, &names));
+                ExportFormat::Svg
+            }
+        },
+    };
+    if errors.is_empty() && !config.format_enabled(format) {
+        errors.push(FieldError {
+            field: "format".to_string(),
+            code: "unsupported".to_string(),
+            message: format!("{} downloads are turned off on this server", format.extension()),
+            allowed: Some(config.conversion.formats.join(", ")),
+        });
+    }
+
+    if let Some(dpi) = check_option_f64(&mut errors, "dpi", query.dpi, DPI_RANGE) {
+        options.dpi = dpi;
+    }
+    options.tolerance = check_option_f64(&mut errors, "tolerance", query.tolerance, TOLERANCE_RANGE);
+
+    if let Some(units) = check_choice(&mut errors, "units", query.units.as_ref(), UNITS) {
+        options.units = units;
+    }
+    if let Some(version) = check_choice(&mut errors, "dxf_version", query.dxf_version.as_ref(), DXF_VERSIONS) {
+        options.dxf_version = version;
+    }
+
+    // Curves default to splines where the format can hold them
+    match check_choice(&mut errors, "curves", query.curves.as_ref(), CURVE_OUTPUTS) {
+        Some(CurveOutput::Spline) if format == ExportFormat::Dxf && options.dxf_version == DxfVersion::R12 => {
+            errors.push(FieldError {
+                field: "curves".to_string(),
+                code: "unsupported".to_string(),
+                message: "DXF R12 has no SPLINE entity; use curves=polyline or dxf_version=r2000".to_string(),
+                allowed: Some("polyline".to_string()),
+            });
+        }
+        Some(curves) => options.curves = curves,
+        None if options.dxf_version == DxfVersion::R12 => options.curves = CurveOutput::Polyline,
+        None => {}
+    }
+
+    if let Some(lift) = check_choice(&mut errors, "lift", query.lift.as_ref(), TOOL_LIFTS) {
+        options.lift = lift;
+    }
+    options.feed_rate = check_option_f64(&mut errors, "feed_rate", query.feed_rate, FEED_RATE_RANGE);
+    options.z_up = check_option_f64(&mut errors, "z_up", query.z_up, Z_RANGE);
+    options.z_down = check_option_f64(&mut errors, "z_down", query.z_down, Z_RANGE);
+
+    // The G-code tool has to clear the work when travelling. Heights left out take the machine
+    // defaults, heights already rejected above are not compared again.
+    let rejected = (query.z_up.is_some() && options.z_up.is_none()) || (query.z_down.is_some() && options.z_down.is_none());
+    let (z_up, z_down) = z_heights(&options);
+    if format == ExportFormat::Gcode && !rejected && z_up <= z_down {
+        // Blame z_down only when the request left z_up at its default
+        let error = if options.z_up.is_none() {
+            FieldError {
+                field: "z_down".to_string(),
+                code: "out_of_range".to_string(),
+                message: format!("z_down must be below z_up ({})", format_number(z_up)),
+                allowed: Some(format!("{} to {}", Z_RANGE.0, format_number(z_up))),
+            }
+        } else {
+            FieldError {
+                field: "z_up".to_string(),
+                code: "out_of_range".to_string(),
+                message: format!("z_up must be above z_down ({})", format_number(z_down)),
+                allowed: Some(format!("{} to {}", format_number(z_down), Z_RANGE.1)),
+            }
+        };
+        errors.push(error);
+    }
+
+    if let Some(origin_x) = check_option_f64(&mut errors, "origin_x", query.origin_x, ORIGIN_RANGE) {
+        options.origin_x = origin_x;
+    }
+    if let Some(origin_y) = check_option_f64(&mut errors, "origin_y", query.origin_y, ORIGIN_RANGE) {
+        options.origin_y = origin_y;
+    }
+    match query.rotation {
+        Some(rotation) if ROTATIONS.contains(&rotation) => options.rotation = rotation,
+        Some(rotation) => {
+            let names: Vec<String> = ROTATIONS.iter().map(|rotation| rotation.to_string()).collect();
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            errors.push(unknown_value("rotation", &rotation.to_string(), &names));
+        }
+        None => {}
+    }
+    if let Some(mirror) = check_choice(&mut errors, "mirror", query.mirror.as_ref(), MIRRORS) {
+        options.mirror = mirror;
+    }
+    options.blade_offset = check_option_f64(&mut errors, "blade_offset", query.blade_offset, BLADE_OFFSET_RANGE);
+
+    if errors.is_empty() {
+        Ok((format, options))
+    } else {
+        Err(errors)
+    }
+}
+
+// Check the requested retention of a stored file against the server maximum; None when unset
+pub fn validate_retention_query(query: &RetentionQuery) -> Result<Option<u64>, Vec<FieldError>> {
+    let max_secs = config().retention.max_secs;
+
+    match query.expires_in {
+        Some(secs) if !(MIN_RETENTION_SECS..=max_secs).contains(&secs) => Err(vec![FieldError {
+            field: "expires_in".to_string(),
+            code: "out_of_range".to_string(),
+            message: format!(
+                "expires_in must be between {} and {} seconds, got {}",
+                MIN_RETENTION_SECS, max_secs, secs
+            ),
+            allowed: Some(format!("{} to {}", MIN_RETENTION_SECS, max_secs)),
+        }]),
+        expires_in => Ok(expires_in),
+    }
+}
+
+// Resolve the tracing engine and check it can produce the requested output mode
+fn check_engine(errors: &mut Vec<FieldError>, settings: &ConversionSettings, output_mode: OutputMode) -> Engine {
+    let engine = match check_choice(errors, "engine", settings.engine.as_ref(), ENGINES) {
+        Some(engine) => engine,
+        None if output_mode == OutputMode::Centerline => Engine::Centerline,
+        None if output_mode == OutputMode::PixelArt => Engine::Pixel,
+        None => Engine::Vtracer,
+    };
+
+    let unsupported = match (output_mode, engine) {
+        (OutputMode::Centerline, Engine::Centerline) => None,
+        (OutputMode::Centerline, _) => Some(("centerline output needs the centerline engine", "centerline")),
+        (OutputMode::PixelArt, Engine::Pixel) => None,
+        (OutputMode::PixelArt, _) => Some(("pixel_art output needs the pixel engine", "pixel")),
+        (OutputMode::Color, Engine::Potrace) => Some(("potrace only traces black and white", "vtracer, pixel")),
+        _ => None,
+    };
+
+    if let Some((message, allowed)) = unsupported {
+        errors.push(FieldError {
+            field: "engine".to_string(),
+            code: "unsupported".to_string(),
+            message: message.to_string(),
+            allowed: Some(allowed.to_string()),
+        });
+    }
+
+    engine
+}
+
+// Resolve the quantization method; color output is reduced with k-means unless told otherwise,
+// pixel art keeps its colors, and black and white output has no colors to reduce
+fn check_quantization(
+    errors: &mut Vec<FieldError>,
+    settings: &ConversionSettings,
+    output_mode: OutputMode,
+) -> Option<Quantization> {
+    let quantization = check_choice(errors, "quantization", settings.quantization.as_ref(), QUANTIZATIONS);
+
+    match (output_mode, quantization) {
+        (OutputMode::Color | OutputMode::PixelArt, Some(quantization)) => quantization,
+        (OutputMode::Color, None) => Some(Quantization::KMeans),
+        _ => None,
+    }
+}
+
+// Parse the fixed palette, reporting each bad entry by its index
+fn check_palette(errors: &mut Vec<FieldError>, settings: &ConversionSettings) -> Option<Vec<[u8; 3]>> {
+    let entries = settings.palette.as_ref()?;
+    let (min, max) = PALETTE_SIZE_RANGE;
+
+    if !(min..=max).contains(&entries.len()) {
+        errors.push(FieldError {
+            field: "palette".to_string(),
+            code: "out_of_range".to_string(),
+            message: format!("palette must have between {} and {} colors, got {}", min, max, entries.len()),
+            allowed: Some(format!("{} to {} colors", min, max)),
+        });
+        return None;
+    }
+
+    let mut palette = Vec::with_capacity(entries.len());
+    for (index, value) in entries.iter().enumerate() {
+        match parse_hex_color(value) {
+            Some(color) => palette.push(color),
+            None => errors.push(FieldError {
+                field: format!("palette[{}]", index),
+                code: "invalid_format".to_string(),
+                message: format!("'{}' is not a hex color", value),
+                allowed: Some("#rgb or #rrggbb".to_string()),
+            }),
+        }
+    }
+
+    Some(palette)
+}
+
+// Resolve the background mode, falling back to background_transparency when unset
+fn check_background(errors: &mut Vec<FieldError>, settings: &ConversionSettings) -> BackgroundMode {
+    let mode = match settings.background.as_deref() {
+        Some(mode) => mode,
+        None if settings.background_transparency == Some(true) => "remove",
+        None => "keep",
+    };
+
+    match mode {
+        "keep" => BackgroundMode::Keep,
+        "remove" => BackgroundMode::Remove,
+        "color" => match settings.background_color.as_deref() {
+            Some(value) => match parse_hex_color(value) {
+                Some(color) => BackgroundMode::Color(color),
+                None => {
+                    errors.push(FieldError {
+                        field: "background_color".to_string(),
+                        code: "invalid_format".to_string(),
+                        message: format!("'{}' is not a hex color", value),
+                        allowed: Some("#rgb or #rrggbb".to_string()),
+                    });
+                    BackgroundMode::Keep
+                }
+            },
+            None => {
+                errors.push(FieldError {
+                    field: "background_color".to_string(),
+                    code: "missing".to_string(),
+                    message: "background_color is required when background is 'color'".to_string(),
+                    allowed: Some("#rgb or #rrggbb".to_string()),
+                });
+                BackgroundMode::Keep
+            }
+        },
+        other => {
+            errors.push(unknown_value("background", other, BACKGROUNDS));
+            BackgroundMode::Keep
+        }
+    }
+}
+
+// Look up a string setting in its list of allowed values
+fn check_choice<T: Copy>(
+    errors: &mut Vec<FieldError>,
+    field: &str,
+    value: Option<&String>,
+    choices: &[(&str, T)],
+) -> Option<T> {
+    let value = value?;
+
+    match choices.iter().find(|(name, _)| *name == value.as_str()) {
+        Some((_, choice)) => Some(*choice),
+        None => {
+            errors.push(unknown_value(field, value, &choice_names(choices)));
+            None
+        }
+    }
+}
+
+// Name of a value in its list of allowed values
+fn choice_name<T: PartialEq>(choices: &[(&'static str, T)], value: T) -> Option<&'static str> {
+    choices.iter().find(|(_, choice)| *choice == value).map(|(name, _)| *name)
+}
+
+fn choice_names<'a, T>(choices: &[(&'a str, T)]) -> Vec<&'a str> {
+    choices.iter().map(|(name, _)| *name).collect()
+}
+
+fn unknown_value(field: &str, value: &str, names: &[&str]) -> FieldError {
+    FieldError {
+        field: field.to_string(),
+        code: "unknown_value".to_string(),
+        message: format!("'{}' is not a valid {}", value, field),
+        allowed: Some(names.join(", ")),
+    }
+}
+
+// Check an integer setting against its inclusive range
+fn check_range<T>(errors: &mut Vec<FieldError>, field: &str, value: Option<T>, (min, max): (i64, i64)) -> T
+where
+    T: Copy + Into<i64> + TryFrom<i64>,
+{
+    let fallback = T::try_from(min).ok();
+
+    match value {
+        Some(value) if (min..=max).contains(&value.into()) => value,
+        Some(value) => {
+            errors.push(FieldError {
+                field: field.to_string(),
+                code: "out_of_range".to_string(),
+                message: format!("{} must be between {} and {}, got {}", field, min, max, value.into()),
+                allowed: Some(format!("{} to {}", min, max)),
+            });
+            fallback.unwrap_or(value)
+        }
+        None => fallback.expect("range minimum fits the field type"),
+    }
+}
+
+// Check a float setting against its inclusive range, rejecting NaN and infinities
+fn check_float(errors: &mut Vec<FieldError>, field: &str, value: Option<f32>, (min, max): (f32, f32)) -> f32 {
+    match value {
+        Some(value) if value.is_finite() && (min..=max).contains(&value) => value,
+        Some(value) => {
+            errors.push(FieldError {
+                field: field.to_string(),
+                code: "out_of_range".to_string(),
+                message: format!("{} must be between {} and {}, got {}", field, min, max, value),
+                allowed: Some(format!("{} to {}", min, max)),
+            });
+            min
+        }
+        None => min,
+    }
+}
+
+// Check an optional float against its inclusive range, keeping it only when valid
+fn check_option_f64(errors: &mut Vec<FieldError>, field: &str, value: Option<f64>, (min, max): (f64, f64)) -> Option<f64> {
+    let value = value?;
+
+    if value.is_finite() && (min..=max).contains(&value) {
+        Some(value)
+    } else {
+        errors.push(FieldError {
+            field: field.to_string(),
+            code: "out_of_range".to_string(),
+            message: format!("{} must be between {} and {}, got {}", field, min, max, value),
+            allowed: Some(format!("{} to {}", min, max)),
+        });
+        None
+    }
+}
+
+// Allowed range or values for a field, used when the JSON type itself is wrong
+fn allowed_for(field: &str) -> Option<String> {
+    let range = |(min, max): (i64, i64)| Some(format!("{} to {}", min, max));
+
+    match field {
+        "threshold" | "background_tolerance" => range((0, 255)),
+        "color_count" => range(COLOR_COUNT_RANGE),
+        "smoothing" | "path_simplification" | "noise_reduction" => range(SCALE_RANGE),
+        "brightness" | "contrast" => range(ADJUSTMENT_RANGE),
+        "corner_threshold" => range(CORNER_THRESHOLD_RANGE),
+        "adaptive_window" => range(ADAPTIVE_WINDOW_RANGE),
+        "auto_time_budget_ms" => range(AUTO_BUDGET_RANGE),
+        "auto" => Some("true, false".to_string()),
+        "gamma" => Some(format!("{} to {}", GAMMA_RANGE.0, GAMMA_RANGE.1)),
+        "adaptive_k" => Some(format!("{} to {}", ADAPTIVE_K_RANGE.0, ADAPTIVE_K_RANGE.1)),
+        "stroke_width" => Some(format!("{} to {}", STROKE_WIDTH_RANGE.0, STROKE_WIDTH_RANGE.1)),
+        "output_mode" => Some(choice_names(OUTPUT_MODES).join(", ")),
+        "engine" => Some(choice_names(ENGINES).join(", ")),
+        "quantization" => Some(choice_names(QUANTIZATIONS).join(", ")),
+        "curve_mode" => Some(choice_names(CURVE_MODES).join(", ")),
+        "binarization" => Some(choice_names(BINARIZATIONS).join(", ")),
+        "background" => Some(BACKGROUNDS.join(", ")),
+        "background_color" => Some("#rgb or #rrggbb".to_string()),
+        "background_transparency" => Some("true, false".to_string()),
+        field if field.starts_with("palette") => Some("list of #rgb or #rrggbb colors".to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Field and code of every error for a settings JSON
+    fn settings_errors(json: &str) -> Vec<(String, String)> {
+        match parse_settings(json) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|error| (error.field, error.code)).collect(),
+        }
+    }
+
+    fn download_errors(query: serde_json::Value) -> Vec<(String, String)> {
+        let query: DownloadQuery = serde_json::from_value(query).unwrap();
+        match validate_download_query(&query) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|error| (error.field, error.code)).collect(),
+        }
+    }
+
+    fn error(field: &str, code: &str) -> Vec<(String, String)> {
+        vec![(field.to_string(), code.to_string())]
+    }
+
+    #[test]
+    fn settings_ranges_accept_their_bounds_and_reject_values_outside() {
+        let ranges = [
+            ("color_count", "2", "64", Some("1"), "65"),
+            ("smoothing", "0", "10", None, "11"),
+            ("path_simplification", "0", "10", None, "11"),
+            ("noise_reduction", "0", "10", None, "11"),
+            ("brightness", "-100", "100", Some("-101"), "101"),
+            ("contrast", "-100", "100", Some("-101"), "101"),
+            ("corner_threshold", "0", "180", None, "181"),
+            ("adaptive_window", "3", "501", Some("2"), "502"),
+            ("gamma", "0.1", "10.0", Some("0.05"), "10.5"),
+            ("adaptive_k", "-1.0", "1.0", Some("-1.5"), "1.5"),
+            ("stroke_width", "0.1", "100.0", Some("0.0"), "100.5"),
+        ];
+
+        for (field, min, max, below, above) in ranges {
+            for value in [min, max] {
+                let json = format!(r#"{{"{}": {}}}"#, field, value);
+                assert_eq!(settings_errors(&json), Vec::new(), "{}", json);
+            }
+            for value in below.into_iter().chain([above]) {
+                let json = format!(r#"{{"{}": {}}}"#, field, value);
+                assert_eq!(settings_errors(&json), error(field, "out_of_range"), "{}", json);
+            }
+        }
+    }
+
+    #[test]
+    fn auto_budget_is_only_checked_when_tuning_is_on() {
+        assert_eq!(settings_errors(r#"{"auto": true, "auto_time_budget_ms": 100}"#), Vec::new());
+        assert_eq!(settings_errors(r#"{"auto": true, "auto_time_budget_ms": 60000}"#), Vec::new());
+        assert_eq!(
+            settings_errors(r#"{"auto": true, "auto_time_budget_ms": 99}"#),
+            error("auto_time_budget_ms", "out_of_range")
+        );
+        assert_eq!(
+            settings_errors(r#"{"auto": true, "auto_time_budget_ms": 60001}"#),
+            error("auto_time_budget_ms", "out_of_range")
+        );
+        assert_eq!(settings_errors(r#"{"auto": false, "auto_time_budget_ms": 99}"#), Vec::new());
+    }
+
+    #[test]
+    fn settings_choices_accept_their_names_and_reject_others() {
+        let choices: [(&str, Vec<&str>); 5] = [
+            ("output_mode", choice_names(OUTPUT_MODES)),
+            ("quantization", choice_names(QUANTIZATIONS)),
+            ("curve_mode", choice_names(CURVE_MODES)),
+            ("binarization", choice_names(BINARIZATIONS)),
+            ("background", vec!["keep", "remove"]),
+        ];
+
+        for (field, names) in choices {
+            for name in names {
+                let json = format!(r#"{{"{}": "{}"}}"#, field, name);
+                assert_eq!(settings_errors(&json), Vec::new(), "{}", json);
+            }
+            let json = format!(r#"{{"{}": "nope"}}"#, field);
+            assert_eq!(settings_errors(&json), error(field, "unknown_value"), "{}", json);
+        }
+    }
+
+    #[test]
+    fn engines_must_match_the_output_mode() {
+        let cases = [
+            ("bw", "vtracer", None),
+            ("bw", "potrace", None),
+            ("bw", "pixel", None),
+            ("color", "vtracer", None),
+            ("color", "pixel", None),
+            ("centerline", "centerline", None),
+            ("pixel_art", "pixel", None),
+            ("color", "potrace", Some("unsupported")),
+            ("centerline", "vtracer", Some("unsupported")),
+            ("pixel_art", "potrace", Some("unsupported")),
+            ("bw", "nope", Some("unknown_value")),
+        ];
+
+        for (output_mode, engine, code) in cases {
+            let json = format!(r#"{{"output_mode": "{}", "engine": "{}"}}"#, output_mode, engine);
+            let expected = code.map(|code| error("engine", code)).unwrap_or_default();
+            assert_eq!(settings_errors(&json), expected, "{}", json);
+        }
+
+        // Without an engine the output mode picks one
+        let settings = parse_settings(r#"{"output_mode": "centerline"}"#).unwrap();
+        assert_eq!(settings.engine, Engine::Centerline);
+        let settings = parse_settings(r#"{"output_mode": "pixel_art"}"#).unwrap();
+        assert_eq!(settings.engine, Engine::Pixel);
+    }
+
+    #[test]
+    fn background_color_must_be_a_hex_color() {
+        assert_eq!(
+            parse_settings(r##"{"background": "color", "background_color": "#fff"}"##).unwrap().background,
+            BackgroundMode::Color([255, 255, 255])
+        );
+        assert_eq!(
+            settings_errors(r#"{"background": "color"}"#),
+            error("background_color", "missing")
+        );
+        for value in ["white", "#ffff", "#€", "#ééé"] {
+            let json = format!(r#"{{"background": "color", "background_color": "{}"}}"#, value);
+            assert_eq!(settings_errors(&json), error("background_color", "invalid_format"), "{}", json);
+        }
+    }
+
+    #[test]
+    fn palettes_are_checked_for_size_and_format() {
+        let full: Vec<String> = (0..PALETTE_SIZE_RANGE.1).map(|index| format!("#{:06x}", index)).collect();
+        let json = serde_json::json!({ "palette": full }).to_string();
+        assert_eq!(settings_errors(&json), Vec::new());
+
+        let oversized: Vec<String> = (0..=PALETTE_SIZE_RANGE.1).map(|index| format!("#{:06x}", index)).collect();
+        let json = serde_json::json!({ "palette": oversized }).to_string();
+        assert_eq!(settings_errors(&json), error("palette", "out_of_range"));
+        assert_eq!(settings_errors(r#"{"palette": []}"#), error("palette", "out_of_range"));
+
+        assert_eq!(
+            settings_errors(r##"{"palette": ["#000", "red", "#fff", "#12"]}"##),
+            vec![
+                ("palette[1]".to_string(), "invalid_format".to_string()),
+                ("palette[3]".to_string(), "invalid_format".to_string()),
+            ]
+        );
+
+        // Multi-byte characters are rejected like any other non-hex entry
+        assert_eq!(
+            settings_errors(r##"{"palette": ["#€", "#fff", "#ééé", "#ff€f"]}"##),
+            vec![
+                ("palette[0]".to_string(), "invalid_format".to_string()),
+                ("palette[2]".to_string(), "invalid_format".to_string()),
+                ("palette[3]".to_string(), "invalid_format".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn pixel_engine_images_are_limited_in_colors() {
+        // One distinct color per pixel, plus transparent pixels that are not traced
+        let image = |colors: u32| {
+            DynamicImage::ImageRgba8(image::ImageBuffer::from_fn(colors + 10, 1, |x, _| {
+                let alpha = if x < colors { 255 } else { 0 };
+                image::Rgba([(x >> 8) as u8, x as u8, 0, alpha])
+            }))
+        };
+        let pixel_art = parse_settings(r#"{"output_mode": "pixel_art"}"#).unwrap();
+
+        assert!(check_image_colors(&image(MAX_PIXEL_COLORS as u32), &pixel_art).is_ok());
+        let errors = check_image_colors(&image(MAX_PIXEL_COLORS as u32 + 1), &pixel_art).unwrap_err();
+        assert_eq!((errors[0].field.as_str(), errors[0].code.as_str()), ("quantization", "unsupported"));
+        assert_eq!(errors[0].allowed.as_deref(), Some("kmeans, median_cut, octree"));
+
+        // Black and white output and the other engines are not limited
+        for json in [r#"{"output_mode": "bw", "engine": "pixel"}"#, r#"{"output_mode": "color", "quantization": "none"}"#] {
+            let settings = parse_settings(json).unwrap();
+            assert!(check_image_colors(&image(MAX_PIXEL_COLORS as u32 + 1), &settings).is_ok(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn malformed_settings_json_is_reported() {
+        assert_eq!(settings_errors("{"), error("settings", "invalid_json"));
+        assert_eq!(settings_errors("[1, 2]"), error("settings", "invalid_json"));
+        assert_eq!(settings_errors("42"), error("settings", "invalid_json"));
+        assert_eq!(settings_errors(r#"{"smoothing": "high"}"#), error("smoothing", "invalid_type"));
+        assert_eq!(settings_errors(r#"{"threshold": 256}"#), error("threshold", "invalid_type"));
+
+        let errors = parse_settings(r#"{"threshold": 256}"#).unwrap_err();
+        assert_eq!(errors[0].allowed.as_deref(), Some("0 to 255"));
+    }
+
+    #[test]
+    fn every_invalid_setting_is_reported_at_once() {
+        let json = r#"{"output_mode": "nope", "color_count": 1, "gamma": 20.0, "background": "blur"}"#;
+        assert_eq!(
+            settings_errors(json),
+            vec![
+                ("output_mode".to_string(), "unknown_value".to_string()),
+                ("color_count".to_string(), "out_of_range".to_string()),
+                ("gamma".to_string(), "out_of_range".to_string()),
+                ("background".to_string(), "unknown_value".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn validated_settings_round_trip_to_the_request_form() {
+        let json = r##"{"output_mode": "color", "color_count": 12, "palette": ["#ff0000"], "background": "color", "background_color": "#00ff00", "auto": true}"##;
+        let settings = parse_settings(json).unwrap();
+        assert_eq!(validate_settings(&settings_to_request(&settings)).unwrap(), settings);
+    }
+
+    #[test]
+    fn download_formats_are_checked_against_the_configuration() {
+        for format in ExportFormat::ALL {
+            let errors = download_errors(serde_json::json!({ "format": format.extension() }));
+            assert_eq!(errors, Vec::new(), "{}", format.extension());
+        }
+        assert_eq!(download_errors(serde_json::json!({ "format": "bmp" })), error("format", "unknown_value"));
+
+        let mut svg_only = Config::default();
+        svg_only.conversion.formats = vec!["svg".to_string()];
+        let query = DownloadQuery {
+            format: Some("pdf".to_string()),
+            ..DownloadQuery::default()
+        };
+        let errors = check_download_query(&query, &svg_only).unwrap_err();
+        assert_eq!((errors[0].field.as_str(), errors[0].code.as_str()), ("format", "unsupported"));
+        assert_eq!(errors[0].allowed.as_deref(), Some("svg"));
+    }
+
+    #[test]
+    fn download_ranges_accept_their_bounds_and_reject_values_outside() {
+        let ranges = [
+            ("dpi", 1.0, 2400.0, 0.5, 2401.0),
+            ("tolerance", 0.0001, 10.0, 0.0, 11.0),
+            ("feed_rate", 1.0, 100000.0, 0.5, 100001.0),
+            ("z_up", 5.0, 100.0, -101.0, 101.0),
+            ("z_down", -100.0, -1.0, -101.0, 101.0),
+            ("origin_x", -10000.0, 10000.0, -10001.0, 10001.0),
+            ("origin_y", -10000.0, 10000.0, -10001.0, 10001.0),
+            ("blade_offset", 0.0, 5.0, -0.1, 5.1),
+        ];
+
+        for (field, min, max, below, above) in ranges {
+            for value in [min, max] {
+                assert_eq!(download_errors(serde_json::json!({ field: value })), Vec::new(), "{} {}", field, value);
+            }
+            for value in [below, above] {
+                let errors = download_errors(serde_json::json!({ field: value }));
+                assert!(errors.contains(&(field.to_string(), "out_of_range".to_string())), "{} {}: {:?}", field, value, errors);
+            }
+        }
+    }
+
+    #[test]
+    fn download_choices_accept_their_names_and_reject_others() {
+        let choices: [(&str, Vec<&str>); 5] = [
+            ("units", choice_names(UNITS)),
+            ("dxf_version", choice_names(DXF_VERSIONS)),
+            ("curves", choice_names(CURVE_OUTPUTS)),
+            ("lift", choice_names(TOOL_LIFTS)),
+            ("mirror", choice_names(MIRRORS)),
+        ];
+
+        for (field, names) in choices {
+            for name in names {
+                assert_eq!(download_errors(serde_json::json!({ field: name })), Vec::new(), "{} {}", field, name);
+            }
+            assert_eq!(download_errors(serde_json::json!({ field: "nope" })), error(field, "unknown_value"), "{}", field);
+        }
+
+        for rotation in ROTATIONS {
+            assert_eq!(download_errors(serde_json::json!({ "rotation": rotation })), Vec::new());
+        }
+        assert_eq!(download_errors(serde_json::json!({ "rotation": 45 })), error("rotation", "unknown_value"));
+    }
+
+    #[test]
+    fn z_up_must_clear_z_down_including_the_defaults() {
+        let cases = [
+            (serde_json::json!({ "z_up": 10.0, "z_down": -2.0 }), Vec::new()),
+            (serde_json::json!({ "z_up": 1.0, "z_down": 2.0 }), error("z_up", "out_of_range")),
+            // The default z_down is 0 and the default z_up 5 mm
+            (serde_json::json!({ "z_up": 0.0 }), error("z_up", "out_of_range")),
+            (serde_json::json!({ "z_up": 0.5 }), Vec::new()),
+            (serde_json::json!({ "z_down": 5.0 }), error("z_down", "out_of_range")),
+            (serde_json::json!({ "z_down": 4.5 }), Vec::new()),
+            // Defaults are converted to the requested units
+            (serde_json::json!({ "units": "inch", "z_down": 0.1 }), Vec::new()),
+            (serde_json::json!({ "units": "inch", "z_down": 0.2 }), error("z_down", "out_of_range")),
+            // A height outside Z_RANGE is only reported once
+            (serde_json::json!({ "z_down": 200.0 }), error("z_down", "out_of_range")),
+            (serde_json::json!({ "z_up": -200.0 }), error("z_up", "out_of_range")),
+        ];
+
+        for (mut query, expected) in cases {
+            query["format"] = "gcode".into();
+            assert_eq!(download_errors(query.clone()), expected, "{}", query);
+        }
+
+        // The heights only matter to G-code
+        for format in ["svg", "pdf", "hpgl"] {
+            let query = serde_json::json!({ "format": format, "z_up": 1.0, "z_down": 2.0 });
+            assert_eq!(download_errors(query.clone()), Vec::new(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn r12_dxf_has_no_splines() {
+        assert_eq!(
+            download_errors(serde_json::json!({ "format": "dxf", "dxf_version": "r12", "curves": "spline" })),
+            error("curves", "unsupported")
+        );
+
+        let query = DownloadQuery {
+            format: Some("dxf".to_string()),
+            dxf_version: Some("r12".to_string()),
+            ..DownloadQuery::default()
+        };
+        let (_, options) = validate_download_query(&query).unwrap();
+        assert_eq!(options.curves, CurveOutput::Polyline);
+    }
+}
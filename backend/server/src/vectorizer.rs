@@ -1,10 +1,23 @@
 // src/vectorizer.rs
-use crate::models::ConversionSettings;
-use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
+use crate::blob::BLOB_STORE;
+use crate::cache;
+use crate::export::svg::write_svg;
+use crate::geometry::{geometry_key, VectorImage};
+use crate::models::{
+    BackgroundMode, Binarization, ConversionStage, FieldError, OutputMode, QualityReport, ValidatedSettings,
+};
+use crate::quality::measure_quality;
+use crate::quantize::{quantize_image, snap_image_to_palette};
+use crate::tracer::create_tracer;
+use crate::tuning::tune_settings;
+use crate::validation::check_image_colors;
+use image::io::Reader as ImageReader;
+use image::{DynamicImage, GenericImageView, ImageBuffer, ImageFormat, Rgba};
 use log::{error, info};
-use std::path::Path;
+use std::collections::{HashMap, VecDeque};
+use std::io::Cursor;
+use std::time::Duration;
 use thiserror::Error;
-use vtracer::{ColorMode, Config, ConverterConfig, PathSimplifyMode};
 
 #[derive(Error, Debug)]
 pub enum VectorizerError {
@@ -16,151 +29,539 @@ pub enum VectorizerError {
     
     #[error("Failed to save SVG: {0}")]
     SaveError(String),
+    
+    // Settings that turned out not to suit the image, reported like request validation errors
+    #[error("Invalid settings: {}", field_messages(.0))]
+    InvalidSettings(Vec<FieldError>),
+}
+
+fn field_messages(errors: &[FieldError]) -> String {
+    errors.iter().map(|error| error.message.as_str()).collect::<Vec<_>>().join("; ")
+}
+
+// Result of a conversion
+#[derive(Debug, Clone)]
+pub struct ConversionOutput {
+    pub svg_data: String,
+    // Distinct colors of the traced paths, in drawing order
+    pub palette: Vec<[u8; 3]>,
+    pub quality: QualityReport,
+    // Settings the image was traced with, which auto tuning may have changed
+    pub settings: ValidatedSettings,
+    // Served from the result cache without tracing
+    pub cached: bool,
 }
 
-// Convert image to SVG using vtracer
+// Convert image to SVG with the configured tracing engine
 pub fn convert_image_to_svg(
-    input_path: &Path,
-    output_path: &Path,
-    settings: &ConversionSettings,
-) -> Result<String, VectorizerError> {
+    input_key: &str,
+    output_key: &str,
+    settings: &ValidatedSettings,
+) -> Result<ConversionOutput, VectorizerError> {
+    convert_image_to_svg_with_progress(input_key, output_key, settings, &mut |_, _| {})
+}
+
+// Convert image to SVG, reporting the current stage and overall percentage as it goes
+pub fn convert_image_to_svg_with_progress(
+    input_key: &str,
+    output_key: &str,
+    settings: &ValidatedSettings,
+    on_progress: &mut dyn FnMut(ConversionStage, u8),
+) -> Result<ConversionOutput, VectorizerError> {
     // Load the image
-    let img = match image::open(input_path) {
-        Ok(img) => img,
-        Err(e) => {
-            error!("Failed to load image: {}", e);
-            return Err(VectorizerError::ImageLoadError(e.to_string()));
+    on_progress(ConversionStage::Decoding, 0);
+    let data = read_upload(input_key)?;
+    
+    // The same bytes converted with the same settings before give the same result
+    let cache_key = cache::cache_key(&data, settings);
+    if let Some((output, geometry)) = cache::lookup(&cache_key) {
+        on_progress(ConversionStage::WritingSvg, 95);
+        save_output(output_key, output.svg_data.as_bytes(), &geometry)?;
+        on_progress(ConversionStage::WritingSvg, 100);
+        return Ok(ConversionOutput { cached: true, ..output });
+    }
+    
+    let img = decode_image_data(data, input_key)?;
+    
+    // Search for settings on a small copy first when asked to
+    let settings = match settings.auto_budget_ms {
+        Some(budget_ms) => {
+            on_progress(ConversionStage::Tuning, 5);
+            tune_settings(&img, settings, Duration::from_millis(budget_ms as u64))
         }
+        None => settings.clone(),
     };
     
-    // Apply pre-processing (brightness, contrast, gamma)
-    let img = preprocess_image(img, settings);
+    let (img, vector_image) = trace_image(img, &settings, on_progress)?;
+    let svg_data = write_svg(&vector_image);
     
-    // Create vtracer config based on settings
-    let config = create_vtracer_config(settings);
+    // Save the SVG, with the traced geometry for the other export formats
+    on_progress(ConversionStage::WritingSvg, 95);
+    let geometry = vector_image.to_bytes();
+    save_output(output_key, svg_data.as_bytes(), &geometry)?;
     
-    // Convert image to SVG
-    let svg_data = match convert_with_vtracer(&img, &config) {
-        Ok(svg) => svg,
-        Err(e) => {
-            error!("Failed to convert image: {}", e);
-            return Err(VectorizerError::ProcessingError(e.to_string()));
-        }
+    // Render the result back and score it against what the tracer traced
+    let quality = measure_quality(&img, &vector_image, &svg_data);
+    info!(
+        "Conversion quality: PSNR {} dB, SSIM {}, {} paths, {} nodes",
+        quality.psnr, quality.ssim, quality.paths, quality.nodes
+    );
+    
+    let output = ConversionOutput {
+        svg_data,
+        palette: vector_image.palette(),
+        quality,
+        settings,
+        cached: false,
     };
+    cache::insert(&cache_key, &output, geometry);
     
-    // Save SVG to file
-    if let Err(e) = std::fs::write(output_path, &svg_data) {
+    on_progress(ConversionStage::WritingSvg, 100);
+    Ok(output)
+}
+
+// Store an SVG and the geometry it was written from
+fn save_output(output_key: &str, svg_data: &[u8], geometry: &[u8]) -> Result<(), VectorizerError> {
+    if let Err(e) = BLOB_STORE.put(output_key, svg_data) {
         error!("Failed to save SVG: {}", e);
         return Err(VectorizerError::SaveError(e.to_string()));
     }
     
-    Ok(svg_data)
+    if let Err(e) = BLOB_STORE.put(&geometry_key(output_key), geometry) {
+        error!("Failed to save geometry: {}", e);
+        return Err(VectorizerError::SaveError(e.to_string()));
+    }
+    
+    Ok(())
+}
+
+// Read the bytes of an uploaded image
+fn read_upload(input_key: &str) -> Result<Vec<u8>, VectorizerError> {
+    BLOB_STORE.get(input_key).map_err(|e| {
+        error!("Failed to read image: {}", e);
+        VectorizerError::ImageLoadError(e.to_string())
+    })
+}
+
+// Decode an uploaded image
+pub fn decode_image(input_key: &str) -> Result<DynamicImage, VectorizerError> {
+    decode_image_data(read_upload(input_key)?, input_key)
+}
+
+// Decode the bytes of an uploaded image, the key giving the format when the content doesn't
+fn decode_image_data(data: Vec<u8>, input_key: &str) -> Result<DynamicImage, VectorizerError> {
+    // Go by the content, falling back to the extension for formats without a signature
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| VectorizerError::ImageLoadError(e.to_string()))?;
+    if reader.format().is_none() {
+        if let Ok(format) = ImageFormat::from_path(input_key) {
+            reader.set_format(format);
+        }
+    }
+    
+    match reader.decode() {
+        Ok(img) => Ok(img),
+        Err(e) => {
+            error!("Failed to load image: {}", e);
+            Err(VectorizerError::ImageLoadError(e.to_string()))
+        }
+    }
+}
+
+// Preprocess and trace a decoded image, returning the image the paths were traced from along with them
+pub fn trace_image(
+    img: DynamicImage,
+    settings: &ValidatedSettings,
+    on_progress: &mut dyn FnMut(ConversionStage, u8),
+) -> Result<(DynamicImage, VectorImage), VectorizerError> {
+    // Apply pre-processing (brightness, contrast, gamma, color reduction)
+    on_progress(ConversionStage::Preprocessing, 10);
+    let img = preprocess_image(img, settings);
+    
+    // Make the background transparent so vtracer leaves it out
+    let img = remove_background(img, settings);
+    check_image_colors(&img, settings).map_err(VectorizerError::InvalidSettings)?;
+    
+    // Trace with the engine chosen in the settings
+    let tracer = create_tracer(settings.engine);
+    match tracer.trace(&img, settings, on_progress) {
+        // Black and white tracers binarize first, and are judged by that image
+        Ok(output) => Ok((output.binarized.unwrap_or(img), output.vector_image)),
+        Err(e) => {
+            error!("Failed to convert image with {}: {}", tracer.name(), e);
+            Err(VectorizerError::ProcessingError(e))
+        }
+    }
 }
 
 // Apply image pre-processing based on settings
-fn preprocess_image(img: DynamicImage, settings: &ConversionSettings) -> DynamicImage {
+fn preprocess_image(img: DynamicImage, settings: &ValidatedSettings) -> DynamicImage {
     let mut img = img;
     
     // Apply brightness adjustment
-    if let Some(brightness) = settings.brightness {
-        if brightness != 0 {
-            img = adjust_brightness(&img, brightness as f32 / 100.0);
-        }
+    if settings.brightness != 0 {
+        img = adjust_brightness(&img, settings.brightness as f32 / 100.0);
     }
     
     // Apply contrast adjustment
-    if let Some(contrast) = settings.contrast {
-        if contrast != 0 {
-            img = adjust_contrast(&img, contrast as f32 / 100.0);
-        }
+    if settings.contrast != 0 {
+        img = adjust_contrast(&img, settings.contrast as f32 / 100.0);
     }
     
     // Apply gamma adjustment
-    if let Some(gamma) = settings.gamma {
-        if (gamma - 1.0).abs() > 0.01 {
-            img = adjust_gamma(&img, gamma);
+    if (settings.gamma - 1.0).abs() > 0.01 {
+        img = adjust_gamma(&img, settings.gamma);
+    }
+    
+    // Reduce the colors last, so the palette matches the adjusted image
+    if matches!(settings.output_mode, OutputMode::Color | OutputMode::PixelArt) {
+        if let Some(palette) = &settings.palette {
+            let (snapped, used) = snap_image_to_palette(&img, palette);
+            info!("Snapped image to {} of {} palette colors", used.len(), palette.len());
+            img = snapped;
+        } else if let Some(method) = settings.quantization {
+            let (quantized, used) = quantize_image(&img, method, settings.color_count as usize);
+            info!("Quantized image to {} colors with {:?}", used.len(), method);
+            img = quantized;
         }
     }
     
     img
 }
 
-// Create vtracer config from settings
-fn create_vtracer_config(settings: &ConversionSettings) -> Config {
-    let mut config = Config::default();
+// Background removal
+
+// Share of the border a color must cover to count as the background
+const MIN_BACKGROUND_SHARE: f32 = 0.4;
+
+// Make the background transparent according to the background setting
+fn remove_background(img: DynamicImage, settings: &ValidatedSettings) -> DynamicImage {
+    let tolerance = settings.background_tolerance;
     
-    // Set color mode based on output_mode
-    if settings.output_mode == "bw" {
-        config.color_mode = ColorMode::Binary;
-        
-        // Set threshold for B/W mode
-        if let Some(threshold) = settings.threshold {
-            // Note: vtracer doesn't have a direct binarization_threshold field
-            // We'll use the hierarchical_threshold field as a workaround
-            config.hierarchical_threshold = threshold as usize;
-        }
-    } else {
-        config.color_mode = ColorMode::Color;
-        
-        // Set color count for Color mode
-        if let Some(color_count) = settings.color_count {
-            config.color_precision = color_count as usize;
+    match settings.background {
+        BackgroundMode::Keep => img,
+        BackgroundMode::Remove => {
+            let mut rgba = img.to_rgba8();
+            match detect_background_color(&rgba, tolerance) {
+                Some(color) => {
+                    info!("Removing detected background #{:02x}{:02x}{:02x}", color[0], color[1], color[2]);
+                    flood_fill_background(&mut rgba, color, tolerance);
+                    DynamicImage::ImageRgba8(rgba)
+                }
+                None => img,
+            }
+        }
+        BackgroundMode::Color(color) => {
+            // A given color is removed everywhere, not only where it touches the edges
+            let mut rgba = img.to_rgba8();
+            for pixel in rgba.pixels_mut() {
+                if color_matches(pixel, color, tolerance) {
+                    pixel[3] = 0;
+                }
+            }
+            DynamicImage::ImageRgba8(rgba)
         }
     }
+}
+
+// Find the color that dominates the image border, if there is one
+pub fn detect_background_color(rgba: &ImageBuffer<Rgba<u8>, Vec<u8>>, tolerance: u8) -> Option<[u8; 3]> {
+    let (width, height) = rgba.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    
+    let border: Vec<&Rgba<u8>> = border_coordinates(width, height)
+        .map(|(x, y)| rgba.get_pixel(x, y))
+        .filter(|pixel| pixel[3] >= 128)
+        .collect();
     
-    // Set path simplification
-    if let Some(path_simplification) = settings.path_simplification {
-        // Map 0-10 scale to vtracer's expected values
-        let factor = path_simplification as f64 / 10.0;
-        config.path_precision = (100.0 - (factor * 90.0)) as usize; // Higher precision = less simplification
+    if border.is_empty() {
+        return None;
     }
     
-    // Set corner threshold
-    if let Some(corner_threshold) = settings.corner_threshold {
-        config.corner_threshold = corner_threshold as f64;
+    // Bucket border pixels by their top 5 bits per channel and take the fullest bucket
+    let mut buckets: HashMap<[u8; 3], (u64, [u64; 3])> = HashMap::new();
+    for pixel in &border {
+        let entry = buckets
+            .entry([pixel[0] >> 3, pixel[1] >> 3, pixel[2] >> 3])
+            .or_insert((0, [0; 3]));
+        entry.0 += 1;
+        for channel in 0..3 {
+            entry.1[channel] += pixel[channel] as u64;
+        }
     }
     
-    // Set curve mode
-    if let Some(ref curve_mode) = settings.curve_mode {
-        config.mode = match curve_mode.as_str() {
-            "polygon" => PathSimplifyMode::Polygon,
-            _ => PathSimplifyMode::Spline,
-        };
+    let (count, sums) = buckets.values().max_by_key(|(count, _)| *count)?;
+    let color = [
+        (sums[0] / count) as u8,
+        (sums[1] / count) as u8,
+        (sums[2] / count) as u8,
+    ];
+    
+    // Count everything within tolerance, not just the bucket, before deciding
+    let matching = border
+        .iter()
+        .filter(|pixel| color_matches(pixel, color, tolerance))
+        .count();
+    let total_border = border_coordinates(width, height).count();
+    
+    if (matching as f32) < total_border as f32 * MIN_BACKGROUND_SHARE {
+        return None;
     }
     
-    // Set filter speckle (noise reduction)
-    if let Some(noise_reduction) = settings.noise_reduction {
-        // Map 0-10 scale to vtracer's expected values
-        config.filter_speckle = noise_reduction as usize * 2;
+    Some(color)
+}
+
+// Clear every pixel connected to the border through pixels of the background color
+fn flood_fill_background(rgba: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, color: [u8; 3], tolerance: u8) {
+    let (width, height) = rgba.dimensions();
+    let mut visited = vec![false; (width * height) as usize];
+    let mut queue: VecDeque<(u32, u32)> = VecDeque::new();
+    
+    for (x, y) in border_coordinates(width, height) {
+        let index = (y * width + x) as usize;
+        if !visited[index] && color_matches(rgba.get_pixel(x, y), color, tolerance) {
+            visited[index] = true;
+            queue.push_back((x, y));
+        }
     }
     
-    config
+    while let Some((x, y)) = queue.pop_front() {
+        rgba.get_pixel_mut(x, y)[3] = 0;
+        
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        for (nx, ny) in neighbours {
+            if nx >= width || ny >= height {
+                continue;
+            }
+            
+            let index = (ny * width + nx) as usize;
+            if !visited[index] && color_matches(rgba.get_pixel(nx, ny), color, tolerance) {
+                visited[index] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
 }
 
-// Convert image using vtracer
-fn convert_with_vtracer(img: &DynamicImage, config: &Config) -> Result<String, String> {
-    let (width, height) = img.dimensions();
-    let rgba_img = img.to_rgba8();
+// Coordinates of every pixel on the image border, each visited once
+fn border_coordinates(width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+    let horizontal = (0..width).flat_map(move |x| {
+        let bottom = if height > 1 { Some((x, height - 1)) } else { None };
+        std::iter::once((x, 0)).chain(bottom)
+    });
+    let vertical = (1..height.saturating_sub(1)).flat_map(move |y| {
+        let right = if width > 1 { Some((width - 1, y)) } else { None };
+        std::iter::once((0, y)).chain(right)
+    });
+    horizontal.chain(vertical)
+}
+
+fn color_matches(pixel: &Rgba<u8>, color: [u8; 3], tolerance: u8) -> bool {
+    pixel[3] >= 128
+        && (0..3).all(|channel| pixel[channel].abs_diff(color[channel]) <= tolerance)
+}
+
+// Binarization
+
+// Default k factors for the adaptive methods
+const SAUVOLA_K: f32 = 0.2;
+const NIBLACK_K: f32 = -0.2;
+
+// Dynamic range of the standard deviation used by Sauvola
+const SAUVOLA_R: f32 = 128.0;
+
+// Reduce the image to pure black and white using the configured method
+pub fn binarize_image(img: &DynamicImage, settings: &ValidatedSettings) -> DynamicImage {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
     
-    // Create converter with config
-    let mut converter = match ConverterConfig::new(config.clone(), width as usize, height as usize) {
-        Ok(converter) => converter,
-        Err(e) => return Err(format!("Failed to create converter: {}", e)),
+    if settings.binarization == Binarization::PerChannel {
+        return binarize_per_channel(&rgba);
+    }
+    
+    let luma = luminance(&rgba);
+    let window = settings.adaptive_window;
+    
+    // Work out the cutoff for every pixel
+    let thresholds: Vec<f32> = match settings.binarization {
+        Binarization::Otsu => {
+            let threshold = otsu_threshold(luma.iter().copied()) as f32;
+            vec![threshold; luma.len()]
+        }
+        Binarization::Sauvola => {
+            let k = settings.adaptive_k.unwrap_or(SAUVOLA_K);
+            adaptive_thresholds(&luma, width, height, window, |mean, std_dev| {
+                mean * (1.0 + k * (std_dev / SAUVOLA_R - 1.0))
+            })
+        }
+        Binarization::Niblack => {
+            let k = settings.adaptive_k.unwrap_or(NIBLACK_K);
+            adaptive_thresholds(&luma, width, height, window, |mean, std_dev| mean + k * std_dev)
+        }
+        Binarization::Fixed | Binarization::PerChannel => {
+            let threshold = settings.threshold as f32;
+            vec![threshold; luma.len()]
+        }
     };
     
-    // Process image
+    let mut output = ImageBuffer::new(width, height);
+    for (index, (x, y, pixel)) in rgba.enumerate_pixels().enumerate() {
+        // Transparent pixels count as background
+        let value = if pixel[3] < 128 || luma[index] as f32 > thresholds[index] {
+            255
+        } else {
+            0
+        };
+        output.put_pixel(x, y, Rgba([value, value, value, 255]));
+    }
+    
+    DynamicImage::ImageRgba8(output)
+}
+
+// Threshold each RGB channel at its own Otsu cutoff, leaving at most 8 colors
+fn binarize_per_channel(rgba: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> DynamicImage {
+    let (width, height) = rgba.dimensions();
+    let cutoffs: Vec<u8> = (0..3)
+        .map(|channel| otsu_threshold(rgba.pixels().map(|pixel| pixel[channel])))
+        .collect();
+    
+    let mut output = ImageBuffer::new(width, height);
+    for (x, y, pixel) in rgba.enumerate_pixels() {
+        let adjusted = if pixel[3] < 128 {
+            Rgba([255, 255, 255, 255])
+        } else {
+            Rgba([
+                if pixel[0] > cutoffs[0] { 255 } else { 0 },
+                if pixel[1] > cutoffs[1] { 255 } else { 0 },
+                if pixel[2] > cutoffs[2] { 255 } else { 0 },
+                255,
+            ])
+        };
+        output.put_pixel(x, y, adjusted);
+    }
+    
+    DynamicImage::ImageRgba8(output)
+}
+
+// Rec. 601 luminance of every pixel in row-major order
+pub fn luminance(rgba: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Vec<u8> {
+    rgba.pixels()
+        .map(|pixel| {
+            (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        })
+        .collect()
+}
+
+// Otsu's method: the cutoff that maximises the variance between the two classes
+pub fn otsu_threshold<I: Iterator<Item = u8>>(values: I) -> u8 {
+    let mut histogram = [0u64; 256];
+    let mut total = 0u64;
+    for value in values {
+        histogram[value as usize] += 1;
+        total += 1;
+    }
+    
+    if total == 0 {
+        return 128;
+    }
+    
+    let sum_all: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, &count)| value as f64 * count as f64)
+        .sum();
+    
+    let mut sum_background = 0.0;
+    let mut weight_background = 0u64;
+    let mut best_variance = 0.0;
+    let mut best_threshold = 128u8;
+    
+    for (value, &count) in histogram.iter().enumerate() {
+        weight_background += count;
+        if weight_background == 0 {
+            continue;
+        }
+        
+        let weight_foreground = total - weight_background;
+        if weight_foreground == 0 {
+            break;
+        }
+        
+        sum_background += value as f64 * count as f64;
+        let mean_background = sum_background / weight_background as f64;
+        let mean_foreground = (sum_all - sum_background) / weight_foreground as f64;
+        let variance = weight_background as f64
+            * weight_foreground as f64
+            * (mean_background - mean_foreground).powi(2);
+        
+        if variance > best_variance {
+            best_variance = variance;
+            best_threshold = value as u8;
+        }
+    }
+    
+    best_threshold
+}
+
+// Per-pixel cutoffs from the mean and standard deviation of a square window around each pixel
+fn adaptive_thresholds<F: Fn(f32, f32) -> f32>(
+    luma: &[u8],
+    width: u32,
+    height: u32,
+    window: u32,
+    threshold: F,
+) -> Vec<f32> {
+    let (width, height) = (width as usize, height as usize);
+    let stride = width + 1;
+    
+    // Summed-area tables of the values and their squares, padded with a zero row and column
+    let mut sums = vec![0f64; stride * (height + 1)];
+    let mut squares = vec![0f64; stride * (height + 1)];
     for y in 0..height {
+        let mut row_sum = 0f64;
+        let mut row_squares = 0f64;
         for x in 0..width {
-            let pixel = rgba_img.get_pixel(x, y);
-            converter.set_pixel(x as usize, y as usize, pixel[0], pixel[1], pixel[2], pixel[3]);
+            let value = luma[y * width + x] as f64;
+            row_sum += value;
+            row_squares += value * value;
+            sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row_sum;
+            squares[(y + 1) * stride + x + 1] = squares[y * stride + x + 1] + row_squares;
         }
     }
     
-    // Convert to SVG
-    match converter.convert() {
-        Ok(svg) => Ok(svg),
-        Err(e) => Err(format!("Conversion error: {}", e)),
+    let half = (window / 2) as usize;
+    let mut thresholds = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let top = y.saturating_sub(half);
+        let bottom = (y + half + 1).min(height);
+        for x in 0..width {
+            let left = x.saturating_sub(half);
+            let right = (x + half + 1).min(width);
+            
+            let area = ((bottom - top) * (right - left)) as f64;
+            let region = |table: &[f64]| {
+                table[bottom * stride + right] - table[top * stride + right]
+                    - table[bottom * stride + left]
+                    + table[top * stride + left]
+            };
+            
+            let mean = region(&sums) / area;
+            let variance = (region(&squares) / area - mean * mean).max(0.0);
+            thresholds.push(threshold(mean as f32, variance.sqrt() as f32));
+        }
     }
+    
+    thresholds
 }
 
 // Image processing utilities
@@ -225,3 +626,112 @@ fn adjust_gamma(img: &DynamicImage, gamma: f32) -> DynamicImage {
     
     DynamicImage::ImageRgba8(output)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ConversionSettings;
+    use crate::validation::validate_settings;
+    
+    fn settings(binarization: &str) -> ValidatedSettings {
+        validate_settings(&ConversionSettings {
+            binarization: Some(binarization.to_string()),
+            ..ConversionSettings::default()
+        })
+        .unwrap()
+    }
+    
+    #[test]
+    fn otsu_splits_a_bimodal_histogram_between_its_peaks() {
+        // Dark values around 40 and light values around 200
+        let values = (30..=50).chain(190..=210).flat_map(|value| std::iter::repeat_n(value, 10));
+        let threshold = otsu_threshold(values);
+        
+        assert!((50..190).contains(&threshold), "threshold {}", threshold);
+        assert_eq!(otsu_threshold(std::iter::empty()), 128);
+    }
+    
+    #[test]
+    fn adaptive_binarization_keeps_dark_strokes_on_a_gradient() {
+        // Background brightening from 80 to 175, with strokes at a third of the local background
+        let strokes = [12, 36, 60, 84];
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(96, 24, |x, _| {
+            let background = 80 + x as u8;
+            let value = if strokes.contains(&x) { background / 3 } else { background };
+            Rgba([value, value, value, 255])
+        }));
+        
+        for method in ["sauvola", "niblack"] {
+            let output = binarize_image(&img, &settings(method)).to_rgba8();
+            for (x, y, pixel) in output.enumerate_pixels() {
+                let expected = if strokes.contains(&x) { 0 } else { 255 };
+                assert_eq!(pixel[0], expected, "{} at ({}, {})", method, x, y);
+            }
+        }
+        
+        // A fixed cutoff of 128 turns the dark end of the background black as well
+        let fixed = binarize_image(&img, &settings("fixed")).to_rgba8();
+        assert_eq!(fixed.get_pixel(0, 0)[0], 0);
+    }
+    
+    #[test]
+    fn black_and_white_tracing_returns_the_binarized_image() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(32, 32, |x, y| {
+            let value = (x * 8) as u8;
+            Rgba([value, value, if y < 16 { value } else { 255 - value }, 255])
+        }));
+        
+        for engine in ["vtracer", "potrace", "centerline", "pixel"] {
+            let output_mode = if engine == "centerline" { "centerline" } else { "bw" };
+            let settings = validate_settings(&ConversionSettings {
+                output_mode: output_mode.to_string(),
+                engine: Some(engine.to_string()),
+                ..ConversionSettings::default()
+            })
+            .unwrap();
+            
+            let (traced, _) = trace_image(img.clone(), &settings, &mut |_, _| {}).unwrap();
+            let binarized = binarize_image(&img, &settings);
+            assert_eq!(traced.to_rgba8(), binarized.to_rgba8(), "{}", engine);
+        }
+        
+        // Color output is traced, and judged, as it is
+        let settings = validate_settings(&ConversionSettings {
+            output_mode: "color".to_string(),
+            quantization: Some("none".to_string()),
+            ..ConversionSettings::default()
+        })
+        .unwrap();
+        let (traced, _) = trace_image(img.clone(), &settings, &mut |_, _| {}).unwrap();
+        assert_eq!(traced.to_rgba8(), img.to_rgba8());
+    }
+    
+    #[test]
+    fn per_channel_binarization_thresholds_each_channel_on_its_own() {
+        let colors = [
+            Rgba([200, 30, 30, 255]),
+            Rgba([30, 200, 30, 255]),
+            Rgba([30, 30, 200, 255]),
+            Rgba([200, 200, 30, 255]),
+            Rgba([255, 255, 255, 255]),
+            Rgba([0, 0, 0, 255]),
+            // Transparent pixels count as background
+            Rgba([0, 0, 0, 0]),
+        ];
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(colors.len() as u32, 4, |x, _| colors[x as usize]));
+        
+        let output = binarize_image(&img, &settings("per_channel")).to_rgba8();
+        let expected = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [255, 255, 0, 255],
+            [255, 255, 255, 255],
+            [0, 0, 0, 255],
+            [255, 255, 255, 255],
+        ];
+        for (x, y, pixel) in output.enumerate_pixels() {
+            assert_eq!(pixel.0, expected[x as usize], "pixel ({}, {})", x, y);
+        }
+    }
+}
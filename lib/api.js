@@ -34,6 +34,124 @@ export async function convertImageToSvg(file, settings) {
   }
 }
 
+// Function to convert several images in one request, with optional per-file setting overrides
+export async function convertBatch(files, settings, overrides = {}) {
+  const formData = new FormData();
+  files.forEach((file) => formData.append('image', file));
+  formData.append('settings', JSON.stringify(settings));
+  Object.entries(overrides).forEach(([index, override]) => {
+    formData.append(`settings[${index}]`, JSON.stringify(override));
+  });
+  
+  try {
+    const response = await apiClient.post('/api/convert-batch', formData);
+    return response.data;
+  } catch (error) {
+    console.error('Error converting batch:', error);
+    throw error;
+  }
+}
+
+// Function to convert an already uploaded image again with new settings
+export async function reconvertImage(fileId, settings) {
+  try {
+    const response = await apiClient.post(`/api/convert/${fileId}`, JSON.stringify(settings), {
+      headers: { 'Content-Type': 'application/json' },
+    });
+    return response.data;
+  } catch (error) {
+    console.error('Error re-converting image:', error);
+    throw error;
+  }
+}
+
+// Function to get a quick low-resolution preview of an uploaded image with new settings
+export async function previewConversion(fileId, settings) {
+  try {
+    const response = await apiClient.post(`/api/preview/${fileId}`, JSON.stringify(settings), {
+      headers: { 'Content-Type': 'application/json' },
+    });
+    return response.data;
+  } catch (error) {
+    console.error('Error previewing conversion:', error);
+    throw error;
+  }
+}
+
+// Function to analyze an image and get suggested settings before converting
+export async function analyzeImage(file) {
+  const formData = new FormData();
+  formData.append('image', file);
+  
+  try {
+    const response = await apiClient.post('/api/analyze', formData);
+    return response.data;
+  } catch (error) {
+    console.error('Error analyzing image:', error);
+    throw error;
+  }
+}
+
+// Function to queue an image conversion as a background job
+export async function createConversionJob(file, settings) {
+  const formData = new FormData();
+  formData.append('image', file);
+  formData.append('settings', JSON.stringify(settings));
+  
+  try {
+    const response = await apiClient.post('/api/jobs', formData);
+    return response.data;
+  } catch (error) {
+    console.error('Error creating conversion job:', error);
+    throw error;
+  }
+}
+
+// Function to get the status of a conversion job
+export async function getJobStatus(jobId) {
+  try {
+    const response = await apiClient.get(`/api/jobs/${jobId}`);
+    return response.data;
+  } catch (error) {
+    console.error('Error getting job status:', error);
+    throw error;
+  }
+}
+
+// Function to follow a conversion job's progress over Server-Sent Events
+// Returns a function that closes the connection
+export function subscribeToJobEvents(jobId, onEvent, onError) {
+  const source = new EventSource(`${API_URL}/api/jobs/${jobId}/events`);
+  
+  source.addEventListener('progress', (event) => {
+    const data = JSON.parse(event.data);
+    onEvent(data);
+    
+    if (data.status === 'succeeded' || data.status === 'failed') {
+      source.close();
+    }
+  });
+  
+  source.onerror = (error) => {
+    source.close();
+    if (onError) {
+      onError(error);
+    }
+  };
+  
+  return () => source.close();
+}
+
+// Function to delete an uploaded image and its conversions straight away
+export async function deleteFile(fileId) {
+  try {
+    await apiClient.delete(`/api/files/${fileId}`);
+  } catch (error) {
+    console.error('Error deleting file:', error);
+    throw error;
+  }
+}
+
 // Function to check server health
 export async function checkServerHealth() {
   try {
@@ -61,6 +179,14 @@ export async function downloadBatchAsSvg(fileIds) {
 
 export default {
   convertImageToSvg,
+  convertBatch,
+  reconvertImage,
+  previewConversion,
+  analyzeImage,
+  createConversionJob,
+  getJobStatus,
+  subscribeToJobEvents,
+  deleteFile,
   checkServerHealth,
   downloadBatchAsSvg,
 };